@@ -1,13 +1,71 @@
 use crate::Config;
 use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use entity::error::{ApiError, ErrorCode};
+use entity::event::DomainEvent;
 use entity::request::{
-    DeleteDomainVersionOption, DomainWithOptVersionOption, DomainWithVersionOption,
-    GetDomainOption, UpdateUploadingStatusOption,
+    AuditQuery, BatchReleaseOption, DeleteDomainVersionOption, DeleteTagOption, DiffVersionOption,
+    DomainWithOptVersionOption, DomainWithVersionOption, DownloadFileOption, EventsQuery,
+    GetDomainOption, ListFilesOption, PurgeTrashOption, ReleaseHistoryQuery, RollbackOption,
+    ScheduledReleaseOption, SetTagOption, TrashItemOption, UpdateUploadingStatusOption,
 };
-use entity::storage::{CertInfo, DomainInfo, ShortMetaData, UploadDomainPosition};
+use entity::storage::{
+    ADMIN_API_VERSION, AuditRecord, CertInfo, DomainInfo, DomainVersion, FileEntry, ReleaseRecord,
+    ScheduledRelease, ServerVersion, ShortMetaData, TrashItem, UploadDomainPosition, VersionDiff,
+};
+use futures::{Stream, StreamExt, future, stream};
 use reqwest::{StatusCode, header, multipart};
 use std::borrow::Cow;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+// error response of admin server is decoded to ApiError, get it by `downcast_ref::<ApiError>()`
+async fn api_error(resp: reqwest::Response) -> anyhow::Error {
+    let status = resp.status();
+    let text = match resp.text().await {
+        Ok(text) => text,
+        Err(e) => return e.into(),
+    };
+    match serde_json::from_str::<ApiError>(&text) {
+        Ok(error) => error.into(),
+        // not from admin server, like a proxy in front of it
+        Err(_) => {
+            let message = if text.is_empty() {
+                status.to_string()
+            } else {
+                text
+            };
+            ApiError::new(ErrorCode::from_status(status.as_u16()), message).into()
+        }
+    }
+}
+
+// write response body to file, the file is removed if body is broken
+async fn save_response(resp: reqwest::Response, output: &Path) -> anyhow::Result<u64> {
+    if resp.status() != StatusCode::OK {
+        return Err(api_error(resp).await);
+    }
+    let mut file = tokio::fs::File::create(output).await?;
+    let mut body = resp.bytes_stream();
+    let mut size = 0;
+    let result: anyhow::Result<()> = async {
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        drop(file);
+        let _ = tokio::fs::remove_file(output).await;
+        return Err(e);
+    }
+    Ok(size)
+}
 
 #[derive(Clone)]
 pub struct API {
@@ -20,7 +78,7 @@ macro_rules! handle {
         if $resp.status() == StatusCode::OK {
             Ok(())
         } else {
-            Err(anyhow!($resp.text().await?))
+            Err(api_error($resp).await)
         }
     };
 }
@@ -29,7 +87,7 @@ macro_rules! string_resp {
         if $resp.status() == StatusCode::OK {
             Ok($resp.text().await?)
         } else {
-            Err(anyhow!($resp.text().await?))
+            Err(api_error($resp).await)
         }
     };
 }
@@ -38,7 +96,7 @@ macro_rules! json_resp {
         if $resp.status() == StatusCode::OK {
             Ok($resp.json::<Value>().await?)
         } else {
-            Err(anyhow!($resp.text().await?))
+            Err(api_error($resp).await)
         }
     };
     ($resp:ident, $t:ty) => {
@@ -47,7 +105,7 @@ macro_rules! json_resp {
             tracing::debug!("{:?}", &resp);
             Ok(resp)
         } else {
-            Err(anyhow!($resp.text().await?))
+            Err(api_error($resp).await)
         }
     };
 }
@@ -55,13 +113,29 @@ macro_rules! json_resp {
 impl API {
     pub fn new(conf: &Config) -> anyhow::Result<API> {
         let mut headers = header::HeaderMap::new();
-        headers.insert(
-            "Authorization",
-            header::HeaderValue::from_str(format!("Bearer {}", &conf.server.auth_token).as_str())
+        // client certificate may be used instead of token
+        if !conf.server.auth_token.is_empty() {
+            headers.insert(
+                "Authorization",
+                header::HeaderValue::from_str(
+                    format!("Bearer {}", &conf.server.auth_token).as_str(),
+                )
                 .unwrap(),
-        );
+            );
+        }
         let mut builder = reqwest::Client::builder();
         builder = builder.default_headers(headers);
+        if let (Some(cert), Some(key)) = (&conf.server.client_cert, &conf.server.client_key) {
+            let mut pem =
+                fs::read(cert).map_err(|e| anyhow!("can not read client cert {cert}: {e}"))?;
+            pem.extend(fs::read(key).map_err(|e| anyhow!("can not read client key {key}: {e}"))?);
+            builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+        }
+        if let Some(ca_cert) = &conf.server.ca_cert {
+            let pem =
+                fs::read(ca_cert).map_err(|e| anyhow!("can not read ca cert {ca_cert}: {e}"))?;
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
         let async_client = builder.build()?;
         Ok(API {
             async_client,
@@ -70,7 +144,28 @@ impl API {
     }
 
     fn url(&self, uri: &str) -> String {
-        format!("{}/{}", self.address, uri)
+        format!("{}/v{ADMIN_API_VERSION}/{}", self.address, uri)
+    }
+
+    // fail with clear message if admin server does not support admin api version of spa-client
+    pub async fn check_server_version(&self) -> anyhow::Result<ServerVersion> {
+        let resp = self.async_client.get(self.url("version")).send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(anyhow!(
+                "admin server does not support admin api v{ADMIN_API_VERSION} of spa-client({}), please upgrade spa-server or use an older spa-client",
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        let version = json_resp!(resp, ServerVersion)?;
+        if !version.api_versions.contains(&ADMIN_API_VERSION) {
+            return Err(anyhow!(
+                "admin server({}) supports admin api {:?}, but spa-client({}) uses v{ADMIN_API_VERSION}, please use spa-client of the same version as spa-server",
+                version.version,
+                version.api_versions,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        Ok(version)
     }
 
     pub async fn get_domain_info(&self, domain: Option<String>) -> anyhow::Result<Vec<DomainInfo>> {
@@ -80,6 +175,20 @@ impl API {
         json_resp!(resp, Vec<DomainInfo>)
     }
 
+    // files of option are checked before release, version keeps uploading if they don't match
+    pub async fn finish_and_release(
+        &self,
+        param: UpdateUploadingStatusOption,
+    ) -> anyhow::Result<String> {
+        let resp = self
+            .async_client
+            .post(self.url("files/upload_status"))
+            .json(&param)
+            .send()
+            .await?;
+        string_resp!(resp)
+    }
+
     pub async fn change_uploading_status(
         &self,
         param: UpdateUploadingStatusOption,
@@ -101,7 +210,87 @@ impl API {
         let resp = self
             .async_client
             .post(self.url("update_version"))
-            .json(&DomainWithOptVersionOption { domain, version })
+            .json(&DomainWithOptVersionOption {
+                domain,
+                version,
+                tag: None,
+                release_at: None,
+            })
+            .send()
+            .await?;
+        string_resp!(resp)
+    }
+
+    // versions of all domains are released together, or none of them if any fails
+    pub async fn batch_release(
+        &self,
+        releases: Vec<DomainWithOptVersionOption>,
+    ) -> anyhow::Result<Vec<DomainVersion>> {
+        let resp = self
+            .async_client
+            .post(self.url("release/batch"))
+            .json(&BatchReleaseOption { releases })
+            .send()
+            .await?;
+        json_resp!(resp, Vec<DomainVersion>)
+    }
+
+    // version or tag is required, the version is resolved when it's scheduled
+    pub async fn schedule_release(
+        &self,
+        domain: String,
+        version: Option<u32>,
+        tag: Option<String>,
+        release_at: DateTime<Utc>,
+    ) -> anyhow::Result<String> {
+        let resp = self
+            .async_client
+            .post(self.url("update_version"))
+            .json(&DomainWithOptVersionOption {
+                domain,
+                version,
+                tag,
+                release_at: Some(release_at),
+            })
+            .send()
+            .await?;
+        string_resp!(resp)
+    }
+
+    pub async fn list_scheduled_releases(
+        &self,
+        domain: Option<String>,
+    ) -> anyhow::Result<Vec<ScheduledRelease>> {
+        let resp = self
+            .async_client
+            .get(self.url("schedule"))
+            .query(&GetDomainOption { domain })
+            .send()
+            .await?;
+        json_resp!(resp, Vec<ScheduledRelease>)
+    }
+
+    pub async fn cancel_scheduled_release(&self, id: u64) -> anyhow::Result<ScheduledRelease> {
+        let resp = self
+            .async_client
+            .post(self.url("schedule/cancel"))
+            .json(&ScheduledReleaseOption { id })
+            .send()
+            .await?;
+        json_resp!(resp, ScheduledRelease)
+    }
+
+    // release the version which tag or channel points to
+    pub async fn release_domain_tag(&self, domain: String, tag: String) -> anyhow::Result<String> {
+        let resp = self
+            .async_client
+            .post(self.url("update_version"))
+            .json(&DomainWithOptVersionOption {
+                domain,
+                version: None,
+                tag: Some(tag),
+                release_at: None,
+            })
             .send()
             .await?;
         string_resp!(resp)
@@ -112,18 +301,99 @@ impl API {
         domain: Option<String>,
         max_reserve: Option<u32>,
     ) -> anyhow::Result<()> {
+        self.remove_deprecated_versions(DeleteDomainVersionOption {
+            domain,
+            max_reserve,
+            max_age_days: None,
+            dry_run: false,
+        })
+        .await?;
+        Ok(())
+    }
+
+    // return deleted versions, or versions to delete if dry_run
+    pub async fn remove_deprecated_versions(
+        &self,
+        option: DeleteDomainVersionOption,
+    ) -> anyhow::Result<Vec<DomainVersion>> {
         let resp = self
             .async_client
             .post(self.url("files/delete"))
-            .json(&DeleteDomainVersionOption {
-                domain,
-                max_reserve,
-            })
+            .json(&option)
+            .send()
+            .await?;
+        json_resp!(resp, Vec<DomainVersion>)
+    }
+
+    // delete a version which is neither serving nor uploading
+    pub async fn delete_version(&self, domain: String, version: u32) -> anyhow::Result<()> {
+        let resp = self
+            .async_client
+            .post(self.url("files/delete_version"))
+            .json(&DomainWithVersionOption { domain, version })
             .send()
             .await?;
         handle!(resp)
     }
 
+    pub async fn abort_upload(&self, domain: String, version: u32) -> anyhow::Result<()> {
+        let resp = self
+            .async_client
+            .post(self.url("files/abort_upload"))
+            .json(&DomainWithVersionOption { domain, version })
+            .send()
+            .await?;
+        handle!(resp)
+    }
+
+    // pinned version is protected from deletion
+    pub async fn pin_version(
+        &self,
+        domain: String,
+        version: u32,
+        pinned: bool,
+    ) -> anyhow::Result<()> {
+        let uri = if pinned { "files/pin" } else { "files/unpin" };
+        let resp = self
+            .async_client
+            .post(self.url(uri))
+            .json(&DomainWithVersionOption { domain, version })
+            .send()
+            .await?;
+        handle!(resp)
+    }
+
+    pub async fn list_trash(&self, domain: Option<String>) -> anyhow::Result<Vec<TrashItem>> {
+        let resp = self
+            .async_client
+            .get(self.url("trash"))
+            .query(&GetDomainOption { domain })
+            .send()
+            .await?;
+        json_resp!(resp, Vec<TrashItem>)
+    }
+
+    // restored version is not released
+    pub async fn restore_trash(&self, id: String) -> anyhow::Result<TrashItem> {
+        let resp = self
+            .async_client
+            .post(self.url("trash/restore"))
+            .json(&TrashItemOption { id })
+            .send()
+            .await?;
+        json_resp!(resp, TrashItem)
+    }
+
+    pub async fn purge_trash(&self, option: PurgeTrashOption) -> anyhow::Result<Vec<TrashItem>> {
+        let resp = self
+            .async_client
+            .post(self.url("trash/purge"))
+            .json(&option)
+            .send()
+            .await?;
+        json_resp!(resp, Vec<TrashItem>)
+    }
+
     pub async fn revoke_version(&self, domain: String, version: u32) -> anyhow::Result<()> {
         let resp = self
             .async_client
@@ -134,6 +404,81 @@ impl API {
         handle!(resp)
     }
 
+    // release the version which was serving before the latest release
+    pub async fn rollback(&self, domain: String) -> anyhow::Result<String> {
+        let resp = self
+            .async_client
+            .post(self.url("rollback"))
+            .json(&RollbackOption { domain })
+            .send()
+            .await?;
+        string_resp!(resp)
+    }
+
+    pub async fn get_release_history(
+        &self,
+        query: &ReleaseHistoryQuery,
+    ) -> anyhow::Result<Vec<ReleaseRecord>> {
+        let resp = self
+            .async_client
+            .get(self.url("history"))
+            .query(query)
+            .send()
+            .await?;
+        json_resp!(resp, Vec<ReleaseRecord>)
+    }
+
+    pub async fn revoke_domain_tag(&self, domain: String, tag: String) -> anyhow::Result<()> {
+        let resp = self
+            .async_client
+            .post(self.url("files/revoke_version"))
+            .json(&DomainWithOptVersionOption {
+                domain,
+                version: None,
+                tag: Some(tag),
+                release_at: None,
+            })
+            .send()
+            .await?;
+        handle!(resp)
+    }
+
+    // return the version which tag or channel points to
+    pub async fn set_tag(
+        &self,
+        option: SetTagOption,
+        channel: bool,
+    ) -> anyhow::Result<DomainVersion> {
+        let uri = if channel { "channels/set" } else { "tags/set" };
+        let resp = self
+            .async_client
+            .post(self.url(uri))
+            .json(&option)
+            .send()
+            .await?;
+        json_resp!(resp, DomainVersion)
+    }
+
+    pub async fn delete_tag(
+        &self,
+        domain: String,
+        name: String,
+        channel: bool,
+    ) -> anyhow::Result<()> {
+        let uri = if channel {
+            "channels/delete"
+        } else {
+            "tags/delete"
+        };
+        let resp = self
+            .async_client
+            .post(self.url(uri))
+            .json(&DeleteTagOption { domain, name })
+            .send()
+            .await?;
+        handle!(resp)
+    }
+
     //TODO: use thiserror instead of anyhow
     pub async fn upload_file<T: Into<Cow<'static, str>>>(
         &self,
@@ -164,7 +509,7 @@ impl API {
         if resp.status() == StatusCode::OK {
             Ok(())
         } else {
-            Err(anyhow!(resp.text().await?))
+            Err(api_error(resp).await)
         }
     }
 
@@ -204,11 +549,149 @@ impl API {
             .await?;
         json_resp!(resp, Vec<CertInfo>)
     }
+
+    pub async fn list_files(&self, query: &ListFilesOption) -> anyhow::Result<Vec<FileEntry>> {
+        let resp = self
+            .async_client
+            .get(self.url("files/list"))
+            .query(query)
+            .send()
+            .await?;
+        json_resp!(resp, Vec<FileEntry>)
+    }
+
+    pub async fn diff_versions(
+        &self,
+        domain: &str,
+        from: u32,
+        to: u32,
+    ) -> anyhow::Result<VersionDiff> {
+        let resp = self
+            .async_client
+            .get(self.url("files/diff"))
+            .query(&DiffVersionOption {
+                domain: domain.to_string(),
+                from,
+                to,
+            })
+            .send()
+            .await?;
+        json_resp!(resp, VersionDiff)
+    }
+
+    // return the size of saved file
+    pub async fn download_file(
+        &self,
+        domain: &str,
+        version: u32,
+        path: &str,
+        output: &Path,
+    ) -> anyhow::Result<u64> {
+        let resp = self
+            .async_client
+            .get(self.url("file/download"))
+            .query(&DownloadFileOption {
+                domain: domain.to_string(),
+                version,
+                path: path.to_string(),
+            })
+            .send()
+            .await?;
+        save_response(resp, output).await
+    }
+
+    // download tar.gz of version, return the size of saved file
+    pub async fn download_archive(
+        &self,
+        domain: &str,
+        version: u32,
+        output: &Path,
+    ) -> anyhow::Result<u64> {
+        let resp = self
+            .async_client
+            .get(self.url("files/archive"))
+            .query(&DomainWithVersionOption {
+                domain: domain.to_string(),
+                version,
+            })
+            .send()
+            .await?;
+        save_response(resp, output).await
+    }
+
+    pub async fn get_audit(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditRecord>> {
+        let resp = self
+            .async_client
+            .get(self.url("audit"))
+            .query(query)
+            .send()
+            .await?;
+        json_resp!(resp, Vec<AuditRecord>)
+    }
+
+    // server-sent events stream, it ends when server closes the connection
+    pub async fn get_events(
+        &self,
+        query: &EventsQuery,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<DomainEvent>> + use<>> {
+        let resp = self
+            .async_client
+            .get(self.url("events"))
+            .query(query)
+            .send()
+            .await?;
+        if resp.status() != StatusCode::OK {
+            return Err(api_error(resp).await);
+        }
+        let events = resp
+            .bytes_stream()
+            .scan(SseDecoder::default(), |decoder, chunk| {
+                let events = match chunk {
+                    Ok(chunk) => decoder
+                        .decode(&chunk)
+                        .into_iter()
+                        .map(|data| {
+                            serde_json::from_str::<DomainEvent>(&data).map_err(|e| anyhow!(e))
+                        })
+                        .collect(),
+                    Err(e) => vec![Err(anyhow!(e))],
+                };
+                future::ready(Some(stream::iter(events)))
+            });
+        Ok(events.flatten())
+    }
+}
+
+// split data of server-sent events, comment and other fields are ignored
+#[derive(Default)]
+struct SseDecoder {
+    buf: Vec<u8>,
+}
+
+impl SseDecoder {
+    fn decode(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(chunk);
+        let mut result = Vec::new();
+        while let Some(index) = self.buf.windows(2).position(|x| x == b"\n\n") {
+            let block = self.buf.drain(..index + 2).collect::<Vec<u8>>();
+            let data = String::from_utf8_lossy(&block)
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if !data.is_empty() {
+                result.push(data);
+            }
+        }
+        result
+    }
 }
+
 #[cfg(test)]
 mod test {
     use crate::LOCAL_HOST;
-    use crate::api::API;
+    use crate::api::{API, SseDecoder};
     use entity::request::UpdateUploadingStatusOption;
     use entity::storage::UploadingStatus;
 
@@ -240,8 +723,25 @@ mod test {
                 domain: "www.baidu.com".to_owned(),
                 version: 1,
                 status: UploadingStatus::Finish,
+                metadata: None,
+                files: None,
+                release: false,
             })
             .await;
         println!("{:?}", r);
     }
+
+    #[test]
+    fn sse_decode() {
+        let mut decoder = SseDecoder::default();
+        assert!(
+            decoder
+                .decode(b": keep-alive\n\nid: 1\nevent: rel")
+                .is_empty()
+        );
+        assert_eq!(
+            decoder.decode(b"ease\ndata: {\"id\":1}\n\nid: 2\ndata:a\ndata:b\n\n"),
+            vec!["{\"id\":1}".to_string(), "a\nb".to_string()]
+        );
+    }
 }
@@ -1,4 +1,6 @@
+use chrono::{DateTime, Utc};
 use clap::{Args, Parser, Subcommand};
+use entity::storage::{AuditAction, AuditResult};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -19,15 +21,179 @@ pub enum Commands {
     Release {
         domain: String,
         version: Option<u32>,
+        /// release the version which tag or channel points to
+        #[clap(long, conflicts_with = "version")]
+        tag: Option<String>,
+        /// schedule the release at RFC 3339 time, like 2024-01-01T00:00:00Z
+        #[clap(long)]
+        at: Option<DateTime<Utc>>,
     },
+    /// release versions of multiple domains together, or none of them if any fails
+    ReleaseBatch {
+        /// ${domain}:${version}, ${domain}:${tag}, or ${domain} for its max version
+        #[clap(required = true, value_parser = parse_release_target)]
+        releases: Vec<ReleaseTarget>,
+    },
+    /// releases waiting for their time
+    #[clap(subcommand)]
+    Schedule(ScheduleCommands),
+    /// delete versions out of retention, serving and uploading versions are always kept
     Delete {
         domain: Option<String>,
+        /// keep the most recent finished versions
         max_reserve: Option<u32>,
+        /// keep versions finished in recent days
+        #[clap(long)]
+        max_age_days: Option<u32>,
+        /// only print versions to delete
+        #[clap(long)]
+        dry_run: bool,
     },
     Revoke {
+        domain: String,
+        version: Option<u32>,
+        /// revoke to the version which tag or channel points to
+        #[clap(long, conflicts_with = "version", required_unless_present = "version")]
+        tag: Option<String>,
+    },
+    /// release the version which was serving before the latest release
+    Rollback {
+        domain: String,
+    },
+    /// print release history of domain
+    History {
+        domain: String,
+        /// print the latest releases, default value is 100
+        #[clap(long, short)]
+        limit: Option<usize>,
+    },
+    /// delete a version which is neither serving nor uploading
+    DeleteVersion {
+        domain: String,
+        version: u32,
+    },
+    /// abort uploading version and remove its files
+    Abort {
         domain: String,
         version: u32,
     },
+    /// deleted versions in trash
+    #[clap(subcommand)]
+    Trash(TrashCommands),
+    /// protect version from deletion
+    Pin {
+        domain: String,
+        version: u32,
+    },
+    Unpin {
+        domain: String,
+        version: u32,
+    },
+    /// tag is unique in domain and can't be moved to another version
+    #[clap(subcommand)]
+    Tag(TagCommands),
+    /// channel can be moved, like: stable, beta
+    #[clap(subcommand)]
+    Channel(TagCommands),
+    Audit(AuditArg),
+    /// print recent deployment events
+    Events {
+        domain: Option<String>,
+        /// keep waiting for new events
+        #[clap(long, short)]
+        follow: bool,
+    },
+    /// list files of version
+    Ls {
+        domain: String,
+        version: u32,
+        /// directory relative to version
+        path: Option<String>,
+        /// also list files of sub directories
+        #[clap(long, short)]
+        recursive: bool,
+    },
+    /// show changed files from version `from` to version `to`
+    Diff {
+        domain: String,
+        from: u32,
+        to: u32,
+    },
+    /// download a file of version, or the whole version as tar.gz if path is not set
+    Download {
+        domain: String,
+        version: u32,
+        /// file path relative to version
+        path: Option<String>,
+        /// default is file name, or ${domain}-${version}.tar.gz
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrashCommands {
+    /// list deleted versions, also includes sub path of domain
+    List { domain: Option<String> },
+    /// move deleted version back, it's not released
+    Restore { id: String },
+    /// remove deleted versions permanently, all of them if neither domain nor id is set
+    Purge {
+        #[clap(long, short)]
+        domain: Option<String>,
+        id: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseTarget {
+    pub domain: String,
+    pub version: Option<u32>,
+    // tag or channel
+    pub tag: Option<String>,
+}
+
+// tag can't be a number, so the target is version if it's a number
+fn parse_release_target(s: &str) -> Result<ReleaseTarget, String> {
+    let (domain, target) = match s.split_once(':') {
+        Some((domain, target)) => (domain, Some(target)),
+        None => (s, None),
+    };
+    if domain.is_empty() || target.is_some_and(|x| x.is_empty()) {
+        return Err(format!(
+            "invalid release: {s}, it should be like www.example.com:2"
+        ));
+    }
+    let version = target.and_then(|x| x.parse::<u32>().ok());
+    Ok(ReleaseTarget {
+        domain: domain.to_string(),
+        version,
+        tag: target.filter(|_| version.is_none()).map(|x| x.to_string()),
+    })
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ScheduleCommands {
+    /// list scheduled releases, also includes sub path of domain
+    List { domain: Option<String> },
+    /// cancel scheduled release before its time
+    Cancel { id: u64 },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TagCommands {
+    /// point name to version, or to the version which tag or channel `from` points to
+    Set {
+        domain: String,
+        name: String,
+        version: Option<u32>,
+        #[clap(long, conflicts_with = "version", required_unless_present = "version")]
+        from: Option<String>,
+    },
+    Delete {
+        domain: String,
+        name: String,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -37,12 +203,49 @@ pub struct UploadArg {
     pub version: Option<u32>,
     #[clap(short)]
     pub parallel: Option<u32>,
+    /// message of the version, replaces the message of git commit
+    #[clap(short, long)]
+    pub message: Option<String>,
+    /// don't collect commit, branch and author from git
+    #[clap(long)]
+    pub no_git: bool,
+    /// release the version after upload, server releases it only if all files are verified
+    #[clap(long)]
+    pub release: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AuditArg {
+    pub domain: Option<String>,
+    /// token name
+    #[clap(long)]
+    pub name: Option<String>,
+    /// upload_status, upload_file, update_version, revoke_version, delete_version, abort_upload,
+    /// pin_version, unpin_version, restore_version, purge_trash, set_tag, delete_tag, set_channel,
+    /// delete_channel, rollback, cancel_release or batch_release
+    #[clap(long)]
+    pub action: Option<AuditAction>,
+    /// success or failure
+    #[clap(long)]
+    pub result: Option<AuditResult>,
+    /// RFC 3339 time, like 2024-01-01T00:00:00Z
+    #[clap(long)]
+    pub since: Option<DateTime<Utc>>,
+    #[clap(long)]
+    pub until: Option<DateTime<Utc>>,
+    #[clap(long, short)]
+    pub limit: Option<usize>,
 }
 
 #[cfg(test)]
 mod test {
-    use crate::commands::{CliCommand, Commands, UploadArg};
+    use crate::commands::{
+        AuditArg, CliCommand, Commands, ReleaseTarget, ScheduleCommands, TagCommands,
+        TrashCommands, UploadArg,
+    };
+    use chrono::{DateTime, Utc};
     use clap::Parser;
+    use entity::storage::AuditAction;
     use std::path::PathBuf;
 
     #[test]
@@ -81,16 +284,26 @@ mod test {
             "2",
             "-p",
             "2",
+            "-m",
+            "fix login",
+            "--no-git",
+            "--release",
         ]);
         if let Commands::Upload(UploadArg {
             path,
             domain,
             version,
             parallel,
+            message,
+            no_git,
+            release,
         }) = c.commands
         {
             assert_eq!(path, PathBuf::from("/abc/d"));
             assert_eq!(parallel, Some(2));
+            assert_eq!(message, Some("fix login".to_string()));
+            assert!(no_git);
+            assert!(release);
             assert_eq!(domain, "www.example.com".to_string());
             assert_eq!(version, Some(2));
         } else {
@@ -101,7 +314,10 @@ mod test {
     fn release_command() {
         let c = CliCommand::parse_from(["test", "release", "www.example.com", "2"]);
         // println!("{:?}", &c);
-        if let Commands::Release { domain, version } = c.commands {
+        if let Commands::Release {
+            domain, version, ..
+        } = c.commands
+        {
             assert_eq!(domain, "www.example.com".to_string());
             assert_eq!(version, Some(2));
         } else {
@@ -111,9 +327,58 @@ mod test {
     #[test]
     fn release_command2() {
         let c = CliCommand::parse_from(["test", "release", "www.example.com"]);
-        if let Commands::Release { domain, version } = c.commands {
+        if let Commands::Release {
+            domain,
+            version,
+            tag,
+            at,
+        } = c.commands
+        {
             assert_eq!(domain, "www.example.com".to_string());
             assert_eq!(version, None);
+            assert_eq!(tag, None);
+            assert_eq!(at, None);
+        } else {
+            unreachable!()
+        }
+    }
+    #[test]
+    fn release_tag_command() {
+        let c = CliCommand::parse_from(["test", "release", "www.example.com", "--tag", "v2.3.1"]);
+        if let Commands::Release { version, tag, .. } = c.commands {
+            assert_eq!(version, None);
+            assert_eq!(tag, Some("v2.3.1".to_string()));
+        } else {
+            unreachable!()
+        }
+        assert!(
+            CliCommand::try_parse_from(["test", "release", "www.example.com", "2", "--tag", "v1"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn delete_command() {
+        let c = CliCommand::parse_from([
+            "test",
+            "delete",
+            "www.example.com",
+            "2",
+            "--max-age-days",
+            "30",
+            "--dry-run",
+        ]);
+        if let Commands::Delete {
+            domain,
+            max_reserve,
+            max_age_days,
+            dry_run,
+        } = c.commands
+        {
+            assert_eq!(domain, Some("www.example.com".to_string()));
+            assert_eq!(max_reserve, Some(2));
+            assert_eq!(max_age_days, Some(30));
+            assert!(dry_run);
         } else {
             unreachable!()
         }
@@ -122,9 +387,254 @@ mod test {
     #[test]
     fn revoke_version() {
         let c = CliCommand::parse_from(["test", "revoke", "www.example.com", "1"]);
-        if let Commands::Revoke { domain, version } = c.commands {
+        if let Commands::Revoke {
+            domain, version, ..
+        } = c.commands
+        {
+            assert_eq!(domain, "www.example.com".to_string());
+            assert_eq!(version, Some(1));
+        } else {
+            unreachable!()
+        }
+        let c = CliCommand::parse_from(["test", "revoke", "www.example.com", "--tag", "stable"]);
+        assert!(matches!(c.commands, Commands::Revoke { tag: Some(_), .. }));
+        assert!(CliCommand::try_parse_from(["test", "revoke", "www.example.com"]).is_err());
+    }
+
+    #[test]
+    fn audit_command() {
+        let c = CliCommand::parse_from([
+            "test",
+            "audit",
+            "www.example.com",
+            "--action",
+            "update_version",
+            "--since",
+            "2024-01-01T00:00:00Z",
+            "-l",
+            "10",
+        ]);
+        if let Commands::Audit(AuditArg {
+            domain,
+            name,
+            action,
+            since,
+            limit,
+            ..
+        }) = c.commands
+        {
+            assert_eq!(domain, Some("www.example.com".to_string()));
+            assert_eq!(name, None);
+            assert_eq!(action, Some(AuditAction::UpdateVersion));
+            assert_eq!(since.unwrap().to_rfc3339(), "2024-01-01T00:00:00+00:00");
+            assert_eq!(limit, Some(10));
+        } else {
+            unreachable!()
+        }
+        assert!(CliCommand::try_parse_from(["test", "audit", "--action", "unknown"]).is_err());
+    }
+
+    #[test]
+    fn events_command() {
+        let c = CliCommand::parse_from(["test", "events", "www.example.com", "-f"]);
+        if let Commands::Events { domain, follow } = c.commands {
+            assert_eq!(domain, Some("www.example.com".to_string()));
+            assert!(follow);
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn delete_version_command() {
+        let c = CliCommand::parse_from(["test", "delete-version", "www.example.com", "2"]);
+        if let Commands::DeleteVersion { domain, version } = c.commands {
+            assert_eq!(domain, "www.example.com".to_string());
+            assert_eq!(version, 2);
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn pin_command() {
+        let c = CliCommand::parse_from(["test", "pin", "www.example.com", "2"]);
+        if let Commands::Pin { domain, version } = c.commands {
+            assert_eq!(domain, "www.example.com".to_string());
+            assert_eq!(version, 2);
+        } else {
+            unreachable!()
+        }
+        let c = CliCommand::parse_from(["test", "unpin", "www.example.com", "2"]);
+        assert!(matches!(c.commands, Commands::Unpin { version: 2, .. }));
+    }
+
+    #[test]
+    fn trash_command() {
+        let c = CliCommand::parse_from(["test", "trash", "list", "www.example.com"]);
+        if let Commands::Trash(TrashCommands::List { domain }) = c.commands {
+            assert_eq!(domain, Some("www.example.com".to_string()));
+        } else {
+            unreachable!()
+        }
+        let c = CliCommand::parse_from([
+            "test",
+            "trash",
+            "restore",
+            "1700000000000-www.example.com-1",
+        ]);
+        assert!(matches!(
+            c.commands,
+            Commands::Trash(TrashCommands::Restore { .. })
+        ));
+        let c = CliCommand::parse_from(["test", "trash", "purge", "-d", "www.example.com"]);
+        if let Commands::Trash(TrashCommands::Purge { domain, id }) = c.commands {
+            assert_eq!(domain, Some("www.example.com".to_string()));
+            assert_eq!(id, None);
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn history_command() {
+        let c = CliCommand::parse_from(["test", "history", "www.example.com", "-l", "10"]);
+        if let Commands::History { domain, limit } = c.commands {
+            assert_eq!(domain, "www.example.com".to_string());
+            assert_eq!(limit, Some(10));
+        } else {
+            unreachable!()
+        }
+        let c = CliCommand::parse_from(["test", "rollback", "www.example.com"]);
+        assert!(matches!(c.commands, Commands::Rollback { .. }));
+    }
+
+    #[test]
+    fn release_batch_command() {
+        let c = CliCommand::parse_from([
+            "test",
+            "release-batch",
+            "www.example.com:2",
+            "www.example.com/admin:stable",
+            "app.example.com",
+        ]);
+        if let Commands::ReleaseBatch { releases } = c.commands {
+            let target = |domain: &str, version: Option<u32>, tag: Option<&str>| ReleaseTarget {
+                domain: domain.to_string(),
+                version,
+                tag: tag.map(|x| x.to_string()),
+            };
+            assert_eq!(
+                releases,
+                vec![
+                    target("www.example.com", Some(2), None),
+                    target("www.example.com/admin", None, Some("stable")),
+                    target("app.example.com", None, None),
+                ]
+            );
+        } else {
+            unreachable!()
+        }
+        assert!(CliCommand::try_parse_from(["test", "release-batch"]).is_err());
+        assert!(CliCommand::try_parse_from(["test", "release-batch", "www.example.com:"]).is_err());
+    }
+
+    #[test]
+    fn schedule_command() {
+        let c = CliCommand::parse_from([
+            "test",
+            "release",
+            "www.example.com",
+            "2",
+            "--at",
+            "2024-01-01T08:00:00+08:00",
+        ]);
+        if let Commands::Release { version, at, .. } = c.commands {
+            assert_eq!(version, Some(2));
+            assert_eq!(
+                at,
+                Some("2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap())
+            );
+        } else {
+            unreachable!()
+        }
+        let c = CliCommand::parse_from(["test", "schedule", "list"]);
+        assert!(matches!(
+            c.commands,
+            Commands::Schedule(ScheduleCommands::List { domain: None })
+        ));
+        let c = CliCommand::parse_from(["test", "schedule", "cancel", "3"]);
+        assert!(matches!(
+            c.commands,
+            Commands::Schedule(ScheduleCommands::Cancel { id: 3 })
+        ));
+    }
+
+    #[test]
+    fn tag_command() {
+        let c = CliCommand::parse_from(["test", "tag", "set", "www.example.com", "v2.3.1", "2"]);
+        if let Commands::Tag(TagCommands::Set {
+            domain,
+            name,
+            version,
+            from,
+        }) = c.commands
+        {
+            assert_eq!(domain, "www.example.com".to_string());
+            assert_eq!(name, "v2.3.1".to_string());
+            assert_eq!(version, Some(2));
+            assert_eq!(from, None);
+        } else {
+            unreachable!()
+        }
+        let c = CliCommand::parse_from([
+            "test",
+            "channel",
+            "set",
+            "www.example.com",
+            "stable",
+            "--from",
+            "beta",
+        ]);
+        assert!(matches!(
+            c.commands,
+            Commands::Channel(TagCommands::Set { from: Some(_), .. })
+        ));
+        let c = CliCommand::parse_from(["test", "channel", "delete", "www.example.com", "beta"]);
+        assert!(matches!(
+            c.commands,
+            Commands::Channel(TagCommands::Delete { .. })
+        ));
+        assert!(
+            CliCommand::try_parse_from(["test", "tag", "set", "www.example.com", "v1"]).is_err()
+        );
+    }
+
+    #[test]
+    fn diff_command() {
+        let c = CliCommand::parse_from(["test", "diff", "www.example.com", "1", "2"]);
+        if let Commands::Diff { domain, from, to } = c.commands {
+            assert_eq!(domain, "www.example.com".to_string());
+            assert_eq!((from, to), (1, 2));
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn download_command() {
+        let c = CliCommand::parse_from(["test", "download", "www.example.com", "2", "-o", "a.tgz"]);
+        if let Commands::Download {
+            domain,
+            version,
+            path,
+            output,
+        } = c.commands
+        {
             assert_eq!(domain, "www.example.com".to_string());
-            assert_eq!(version, 1);
+            assert_eq!(version, 2);
+            assert_eq!(path, None);
+            assert_eq!(output, Some(PathBuf::from("a.tgz")));
         } else {
             unreachable!()
         }
@@ -12,7 +12,13 @@ pub struct Config {
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct AdminServerConfig {
     pub address: String,
+    // could be empty if client certificate is used
     pub auth_token: String,
+    // pem file path of client certificate and private key for mutual tls
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    // pem file path of custom CA to verify admin server certificate
+    pub ca_cert: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -24,6 +30,13 @@ fn env_opt(key: &str) -> Option<String> {
     std::env::var(key).ok()
 }
 
+fn server_opt(conf: &DocumentMut, key: &str, env_key: &str) -> Option<String> {
+    conf.get("server")
+        .and_then(|x| x.get(key))
+        .and_then(|x| x.as_str().map(|x| x.to_string()))
+        .or_else(|| env_opt(env_key))
+}
+
 impl Config {
     pub fn load(config_dir: Option<PathBuf>) -> anyhow::Result<Config> {
         Self::load_toml(config_dir)
@@ -35,19 +48,22 @@ impl Config {
             conf = config_str.parse::<DocumentMut>()?;
         }
 
+        let client_cert = server_opt(&conf, "client_cert", "SPA_SERVER_CLIENT_CERT");
+        let client_key = server_opt(&conf, "client_key", "SPA_SERVER_CLIENT_KEY");
+        if client_cert.is_some() != client_key.is_some() {
+            return Err(anyhow!(
+                "server.client_cert and server.client_key should be set together"
+            ));
+        }
         let admin_server = AdminServerConfig {
-            address: conf
-                .get("server")
-                .and_then(|x| x.get("address"))
-                .and_then(|x| x.as_str().map(|x| x.to_string()))
-                .or_else(|| env_opt("SPA_SERVER_ADDRESS"))
+            address: server_opt(&conf, "address", "SPA_SERVER_ADDRESS")
                 .ok_or(anyhow!("server.address could not get"))?,
-            auth_token: conf
-                .get("server")
-                .and_then(|x| x.get("auth_token"))
-                .and_then(|x| x.as_str().map(|x| x.to_string()))
-                .or_else(|| env_opt("SPA_SERVER_AUTH_TOKEN"))
+            auth_token: server_opt(&conf, "auth_token", "SPA_SERVER_AUTH_TOKEN")
+                .or_else(|| client_cert.as_ref().map(|_| String::new()))
                 .ok_or(anyhow!("server.auth_token could not get"))?,
+            client_cert,
+            client_key,
+            ca_cert: server_opt(&conf, "ca_cert", "SPA_SERVER_CA_CERT"),
         };
         let uploading_config = UploadConfig {
             parallel: conf
@@ -5,12 +5,24 @@ pub mod api;
 pub mod commands;
 pub mod config;
 mod upload_files;
+mod version_metadata;
 
 use crate::api::API;
-use crate::commands::{CliCommand, Commands};
+use crate::commands::{CliCommand, Commands, ScheduleCommands, TagCommands, TrashCommands};
 use crate::config::Config;
-pub use crate::upload_files::upload_files;
+pub use crate::upload_files::{upload_files, upload_files_and_release, upload_files_with_metadata};
+pub use crate::version_metadata::git_metadata;
 use anyhow::anyhow;
+use entity::event::{DomainEvent, DomainEventData};
+use entity::request::{
+    AuditQuery, DeleteDomainVersionOption, DomainWithOptVersionOption, EventsQuery,
+    ListFilesOption, PurgeTrashOption, ReleaseHistoryQuery, SetTagOption,
+};
+use entity::storage::{FileEntry, ReleaseRecord, ScheduledRelease, TrashItem, VersionDiff};
+use futures::StreamExt;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::time::Duration;
 
 use clap::Parser;
 use console::style;
@@ -37,6 +49,7 @@ async fn run_with_commands(commands: CliCommand) -> anyhow::Result<()> {
         &config.server.address
     );
     let api = API::new(&config)?;
+    api.check_server_version().await?;
 
     match commands.commands {
         Commands::Info { domain } => {
@@ -45,26 +58,402 @@ async fn run_with_commands(commands: CliCommand) -> anyhow::Result<()> {
         }
         Commands::Upload(arg) => {
             let parallel = arg.parallel.unwrap_or(config.upload.parallel);
-            upload_files(api, arg.domain, arg.version, arg.path, parallel).await?;
+            let mut metadata = if arg.no_git {
+                None
+            } else {
+                git_metadata(&arg.path)
+            };
+            if let Some(message) = arg.message {
+                metadata.get_or_insert_default().message = Some(message);
+            }
+            if arg.release {
+                upload_files_and_release(
+                    api,
+                    arg.domain,
+                    arg.version,
+                    arg.path,
+                    parallel,
+                    metadata,
+                )
+                .await?;
+            } else {
+                upload_files_with_metadata(
+                    api,
+                    arg.domain,
+                    arg.version,
+                    arg.path,
+                    parallel,
+                    metadata,
+                )
+                .await?;
+            }
         }
-        Commands::Release { domain, version } => {
-            let resp = api.release_domain_version(domain, version).await?;
+        Commands::Release {
+            domain,
+            version,
+            tag,
+            at,
+        } => {
+            let resp = match (at, tag) {
+                (Some(at), tag) => api.schedule_release(domain, version, tag, at).await?,
+                (None, Some(tag)) => api.release_domain_tag(domain, tag).await?,
+                (None, None) => api.release_domain_version(domain, version).await?,
+            };
             success(&resp);
         }
+        Commands::ReleaseBatch { releases } => {
+            let releases = releases
+                .into_iter()
+                .map(|x| DomainWithOptVersionOption {
+                    domain: x.domain,
+                    version: x.version,
+                    tag: x.tag,
+                    release_at: None,
+                })
+                .collect();
+            for released in api.batch_release(releases).await? {
+                println!("{} version:{}", released.domain, released.version);
+            }
+            success("batch release success!");
+        }
+        Commands::Schedule(ScheduleCommands::List { domain }) => {
+            for item in api.list_scheduled_releases(domain).await? {
+                println!("{}", render_scheduled_release(&item));
+            }
+        }
+        Commands::Schedule(ScheduleCommands::Cancel { id }) => {
+            let item = api.cancel_scheduled_release(id).await?;
+            success(&format!(
+                "cancel success! {} version:{} at {}",
+                item.domain,
+                item.version,
+                item.release_at.format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
         Commands::Delete {
             domain,
             max_reserve,
+            max_age_days,
+            dry_run,
         } => {
-            api.remove_files(domain, max_reserve).await?;
-            success("delete success!");
+            let deleted = api
+                .remove_deprecated_versions(DeleteDomainVersionOption {
+                    domain,
+                    max_reserve,
+                    max_age_days,
+                    dry_run,
+                })
+                .await?;
+            for version in &deleted {
+                println!("{}:{}", version.domain, version.version);
+            }
+            if dry_run {
+                success(&format!("{} versions would be deleted", deleted.len()));
+            } else {
+                success(&format!(
+                    "delete success! {} versions deleted",
+                    deleted.len()
+                ));
+            }
         }
-        Commands::Revoke { domain, version } => {
-            api.revoke_version(domain, version).await?;
+        Commands::Revoke {
+            domain,
+            version,
+            tag,
+        } => {
+            match (version, tag) {
+                (Some(version), _) => api.revoke_version(domain, version).await?,
+                (None, Some(tag)) => api.revoke_domain_tag(domain, tag).await?,
+                (None, None) => return Err(anyhow!("version or tag is required")),
+            }
             success("revoke success!");
         }
+        Commands::Rollback { domain } => {
+            let resp = api.rollback(domain).await?;
+            success(&resp);
+        }
+        Commands::History { domain, limit } => {
+            let records = api
+                .get_release_history(&ReleaseHistoryQuery { domain, limit })
+                .await?;
+            for record in &records {
+                println!("{}", render_release_record(record));
+            }
+        }
+        Commands::DeleteVersion { domain, version } => {
+            api.delete_version(domain, version).await?;
+            success("delete success!");
+        }
+        Commands::Abort { domain, version } => {
+            api.abort_upload(domain, version).await?;
+            success("abort success!");
+        }
+        Commands::Trash(TrashCommands::List { domain }) => {
+            for item in api.list_trash(domain).await? {
+                println!("{}", render_trash_item(&item));
+            }
+        }
+        Commands::Trash(TrashCommands::Restore { id }) => {
+            let item = api.restore_trash(id).await?;
+            success(&format!(
+                "restore success! {} version:{}",
+                item.domain, item.version
+            ));
+        }
+        Commands::Trash(TrashCommands::Purge { domain, id }) => {
+            let items = api.purge_trash(PurgeTrashOption { domain, id }).await?;
+            for item in &items {
+                println!("{}", render_trash_item(item));
+            }
+            success(&format!("purge success! {} versions purged", items.len()));
+        }
+        Commands::Pin { domain, version } => {
+            api.pin_version(domain, version, true).await?;
+            success("pin success!");
+        }
+        Commands::Unpin { domain, version } => {
+            api.pin_version(domain, version, false).await?;
+            success("unpin success!");
+        }
+        Commands::Tag(command) => run_tag_command(&api, command, false).await?,
+        Commands::Channel(command) => run_tag_command(&api, command, true).await?,
+        Commands::Audit(arg) => {
+            let query = AuditQuery {
+                domain: arg.domain,
+                name: arg.name,
+                action: arg.action,
+                result: arg.result,
+                since: arg.since,
+                until: arg.until,
+                limit: arg.limit,
+            };
+            let records = api.get_audit(&query).await?;
+            println!("{}", serde_json::to_string(&records)?);
+        }
+        Commands::Events { domain, follow } => {
+            print_events(&api, domain, follow).await?;
+        }
+        Commands::Ls {
+            domain,
+            version,
+            path,
+            recursive,
+        } => {
+            let query = ListFilesOption {
+                domain,
+                version,
+                path,
+                recursive: Some(recursive),
+            };
+            for entry in api.list_files(&query).await? {
+                println!("{}", render_file_entry(&entry));
+            }
+        }
+        Commands::Diff { domain, from, to } => {
+            let diff = api.diff_versions(&domain, from, to).await?;
+            println!("{}", render_version_diff(&diff));
+        }
+        Commands::Download {
+            domain,
+            version,
+            path,
+            output,
+        } => {
+            let (output, size) = match path {
+                Some(path) => {
+                    let output = output.unwrap_or_else(|| {
+                        PathBuf::from(path.rsplit('/').find(|x| !x.is_empty()).unwrap_or("index"))
+                    });
+                    let size = api.download_file(&domain, version, &path, &output).await?;
+                    (output, size)
+                }
+                None => {
+                    let output = output.unwrap_or_else(|| {
+                        PathBuf::from(format!("{}-{version}.tar.gz", domain.replace('/', "_")))
+                    });
+                    let size = api.download_archive(&domain, version, &output).await?;
+                    (output, size)
+                }
+            };
+            success(&format!("download {} bytes to {}", size, output.display()));
+        }
     };
     Ok(())
 }
+
+fn render_file_entry(entry: &FileEntry) -> String {
+    let modified = entry
+        .modified
+        .map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".repeat(19));
+    if entry.is_dir {
+        format!("{:>10} {modified} {}/", "-", style(&entry.path).blue())
+    } else {
+        format!("{:>10} {modified} {}", entry.length, entry.path)
+    }
+}
+
+async fn run_tag_command(api: &API, command: TagCommands, channel: bool) -> anyhow::Result<()> {
+    let kind = if channel { "channel" } else { "tag" };
+    match command {
+        TagCommands::Set {
+            domain,
+            name,
+            version,
+            from,
+        } => {
+            let result = api
+                .set_tag(
+                    SetTagOption {
+                        domain,
+                        name: name.clone(),
+                        version,
+                        from,
+                    },
+                    channel,
+                )
+                .await?;
+            success(&format!(
+                "{kind}:{name} is set to {}:{}",
+                result.domain, result.version
+            ));
+        }
+        TagCommands::Delete { domain, name } => {
+            api.delete_tag(domain, name.clone(), channel).await?;
+            success(&format!("{kind}:{name} is deleted"));
+        }
+    }
+    Ok(())
+}
+
+fn render_release_record(record: &ReleaseRecord) -> String {
+    let previous = record
+        .previous_version
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    format!(
+        "{} {:<8} {} -> {} by {}",
+        record.time.format("%Y-%m-%d %H:%M:%S"),
+        format!("{:?}", record.action).to_lowercase(),
+        previous,
+        record.version,
+        record.actor
+    )
+}
+
+fn render_scheduled_release(item: &ScheduledRelease) -> String {
+    format!(
+        "{} {} {} version:{} by {}",
+        item.release_at.format("%Y-%m-%d %H:%M:%S"),
+        item.id,
+        item.domain,
+        item.version,
+        item.actor
+    )
+}
+
+fn render_trash_item(item: &TrashItem) -> String {
+    format!(
+        "{} {} {} version:{}",
+        item.deleted_at.format("%Y-%m-%d %H:%M:%S"),
+        item.id,
+        item.domain,
+        item.version
+    )
+}
+
+fn render_version_diff(diff: &VersionDiff) -> String {
+    let length = |x: Option<u64>| x.map(|x| x.to_string()).unwrap_or_else(|| "-".to_string());
+    let mut lines = vec![format!(
+        "{:<8} {:>10} {:>10} path",
+        "status", "from size", "to size"
+    )];
+    for (status, files) in [
+        (style("added").green(), &diff.added),
+        (style("removed").red(), &diff.removed),
+        (style("modified").yellow(), &diff.modified),
+    ] {
+        for file in files {
+            lines.push(format!(
+                "{:<8} {:>10} {:>10} {}",
+                status,
+                length(file.from_length),
+                length(file.to_length),
+                file.path
+            ));
+        }
+    }
+    lines.push(format!(
+        "{} added, {} removed, {} modified, {} unchanged",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len(),
+        diff.unchanged
+    ));
+    lines.join("\n")
+}
+
+fn render_event(event: &DomainEvent) -> String {
+    let time = event.time.format("%Y-%m-%d %H:%M:%S");
+    let kind = style(format!("{:<13}", event.data.kind().as_str())).cyan();
+    let target = match &event.data {
+        DomainEventData::FileUpload {
+            domain,
+            version,
+            path,
+        } => format!("{domain} version:{version} path:{path}"),
+        DomainEventData::CronRun { deleted } => format!("deleted versions:{deleted}"),
+        DomainEventData::BatchRelease { releases } => releases
+            .iter()
+            .map(|x| format!("{} version:{}", x.domain, x.version))
+            .collect::<Vec<_>>()
+            .join(", "),
+        data => format!(
+            "{} version:{}",
+            data.domain().unwrap_or_default(),
+            data.version().unwrap_or_default()
+        ),
+    };
+    format!("{time} {kind} {target}")
+}
+
+async fn print_events(api: &API, domain: Option<String>, follow: bool) -> anyhow::Result<()> {
+    // replay recent events firstly
+    let mut last_event_id = 0;
+    loop {
+        let query = EventsQuery {
+            domain: domain.clone(),
+            last_event_id: Some(last_event_id),
+            follow: Some(follow),
+        };
+        match api.get_events(&query).await {
+            Ok(events) => {
+                let mut events = pin!(events);
+                while let Some(event) = events.next().await {
+                    match event {
+                        Ok(event) => {
+                            last_event_id = event.id;
+                            println!("{}", render_event(&event));
+                        }
+                        Err(e) => {
+                            println!("{}", style(format!("events stream error: {e}")).red());
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) if follow => {
+                println!("{}", style(format!("connect events error: {e}")).red());
+            }
+            Err(e) => return Err(e),
+        }
+        if !follow {
+            return Ok(());
+        }
+        // reconnect and replay missed events
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
 #[cfg(test)]
 const LOCAL_HOST: &str = "local.fornetcode.com";
 
@@ -1,8 +1,9 @@
+use crate::version_metadata::git_metadata;
 use crate::{API, success};
 use anyhow::anyhow;
 use console::style;
 use entity::request::UpdateUploadingStatusOption;
-use entity::storage::{GetDomainPositionStatus, ShortMetaData, UploadingStatus};
+use entity::storage::{GetDomainPositionStatus, ShortMetaData, UploadingStatus, VersionMetadata};
 use futures::StreamExt;
 use futures::future::Either;
 use md5::{Digest, Md5};
@@ -18,12 +19,49 @@ use std::time::Duration;
 use tracing::warn;
 use walkdir::WalkDir;
 
+// metadata is collected from git if path is in a git repository
 pub async fn upload_files(
     api: API,
     domain: String,
     version: Option<u32>,
     path: PathBuf,
     parallel: u32,
+) -> anyhow::Result<()> {
+    let metadata = git_metadata(&path);
+    upload_files_with_metadata(api, domain, version, path, parallel, metadata).await
+}
+
+pub async fn upload_files_with_metadata(
+    api: API,
+    domain: String,
+    version: Option<u32>,
+    path: PathBuf,
+    parallel: u32,
+    metadata: Option<VersionMetadata>,
+) -> anyhow::Result<()> {
+    upload(api, domain, version, path, parallel, metadata, false).await
+}
+
+// the version is released by server when it's finished, only if all files are verified
+pub async fn upload_files_and_release(
+    api: API,
+    domain: String,
+    version: Option<u32>,
+    path: PathBuf,
+    parallel: u32,
+    metadata: Option<VersionMetadata>,
+) -> anyhow::Result<()> {
+    upload(api, domain, version, path, parallel, metadata, true).await
+}
+
+async fn upload(
+    api: API,
+    domain: String,
+    version: Option<u32>,
+    path: PathBuf,
+    parallel: u32,
+    metadata: Option<VersionMetadata>,
+    release: bool,
 ) -> anyhow::Result<()> {
     let path = fs::canonicalize(path)?;
     println!("the upload path is {:?}", &path);
@@ -49,6 +87,8 @@ pub async fn upload_files(
         .collect::<HashMap<String, ShortMetaData>>();
 
     let mut byte_buffer = vec![0u8; 1024 * 1024];
+    // all local files, server checks them before release
+    let mut local_files = Vec::new();
     let parent = path.clone();
     let uploading_files = WalkDir::new(path)
         .min_depth(1)
@@ -65,12 +105,21 @@ pub async fn upload_files(
                 .map(|c| c.as_os_str().to_string_lossy())
                 .collect::<Vec<_>>()
                 .join("/");
-            if server_metadata
-                .get(&key)
-                .filter(|x| {
-                    let md5 = md5_file(entity.path(), &mut byte_buffer);
-                    x.length == metadata.len() && md5.filter(|md5| md5 == &x.md5).is_some()
-                })
+            let server_file = server_metadata.get(&key);
+            let md5 = if release || server_file.is_some() {
+                md5_file(entity.path(), &mut byte_buffer)
+            } else {
+                None
+            };
+            if release && let Some(md5) = &md5 {
+                local_files.push(ShortMetaData {
+                    path: key.clone(),
+                    md5: md5.clone(),
+                    length: metadata.len(),
+                });
+            }
+            if server_file
+                .filter(|x| x.length == metadata.len() && md5.as_ref() == Some(&x.md5))
                 .is_none()
             {
                 Some((key, entity.path().to_path_buf()))
@@ -82,15 +131,10 @@ pub async fn upload_files(
     if server_metadata.is_empty() && uploading_files.is_empty() {
         return Err(anyhow!("There is no file to uploading"));
     }
+    let local_files = release.then_some(local_files);
     if uploading_files.is_empty() {
         success("all files already upload");
-        api.change_uploading_status(UpdateUploadingStatusOption {
-            domain: domain.clone().to_string(),
-            version,
-            status: UploadingStatus::Finish,
-        })
-        .await?;
-        return Ok(());
+        return finish(&api, domain, version, metadata, local_files).await;
     }
     let uploading_file_count = uploading_files.len();
     println!(
@@ -106,6 +150,9 @@ pub async fn upload_files(
         domain: domain.clone(),
         version,
         status: UploadingStatus::Uploading,
+        metadata,
+        files: None,
+        release: false,
     })
     .await?;
     println!(
@@ -155,12 +202,32 @@ pub async fn upload_files(
             fail_keys.len()
         ));
     } else {
-        api.change_uploading_status(UpdateUploadingStatusOption {
-            domain: domain.clone().to_string(),
-            version,
-            status: UploadingStatus::Finish,
-        })
-        .await?;
+        finish(&api, domain.to_string(), version, None, local_files).await?;
+    }
+    Ok(())
+}
+
+// release with finish if local files are set
+async fn finish(
+    api: &API,
+    domain: String,
+    version: u32,
+    metadata: Option<VersionMetadata>,
+    local_files: Option<Vec<ShortMetaData>>,
+) -> anyhow::Result<()> {
+    let option = UpdateUploadingStatusOption {
+        domain,
+        version,
+        status: UploadingStatus::Finish,
+        metadata,
+        release: local_files.is_some(),
+        files: local_files,
+    };
+    if option.release {
+        let resp = api.finish_and_release(option).await?;
+        success(&resp);
+    } else {
+        api.change_uploading_status(option).await?;
     }
     Ok(())
 }
@@ -0,0 +1,54 @@
+use chrono::Utc;
+use entity::storage::VersionMetadata;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn git(path: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!output.is_empty()).then_some(output)
+}
+
+fn env_var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|x| !x.is_empty())
+}
+
+// GitHub Actions or GitLab CI
+fn ci_url() -> Option<String> {
+    if let (Some(server), Some(repository), Some(run_id)) = (
+        env_var("GITHUB_SERVER_URL"),
+        env_var("GITHUB_REPOSITORY"),
+        env_var("GITHUB_RUN_ID"),
+    ) {
+        return Some(format!("{server}/{repository}/actions/runs/{run_id}"));
+    }
+    env_var("CI_JOB_URL")
+}
+
+// collect metadata of the last commit, return None if path is not in a git repository
+pub fn git_metadata(path: &Path) -> Option<VersionMetadata> {
+    let commit = git(path, &["rev-parse", "HEAD"])?;
+    // CI checkouts are usually detached
+    let branch = git(path, &["rev-parse", "--abbrev-ref", "HEAD"])
+        .filter(|x| x != "HEAD")
+        .or_else(|| env_var("GITHUB_REF_NAME"))
+        .or_else(|| env_var("CI_COMMIT_REF_NAME"));
+    Some(VersionMetadata {
+        commit: Some(commit),
+        branch,
+        author: git(path, &["log", "-1", "--format=%an <%ae>"]),
+        message: git(path, &["log", "-1", "--format=%s"]),
+        ci_url: ci_url(),
+        build_time: Some(Utc::now()),
+        extra: Default::default(),
+    })
+}
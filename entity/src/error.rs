@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use utoipa::ToSchema;
+
+// stable error code of admin api
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidParameters,
+    Unauthorized,
+    Forbidden,
+    TooManyRequests,
+    DomainNotFound,
+    DomainIsAlias,
+    // single SPA and multiple SPA(sub path) can't be in the same host
+    MixedSingleMultiple,
+    VersionUploading,
+    VersionServing,
+    VersionNotUploading,
+    VersionNotFound,
+    // pinned version can't be deleted
+    VersionPinned,
+    // restore target version already exists
+    VersionExists,
+    TrashNotFound,
+    // tag is set to another version, or tag and channel have same name
+    TagExists,
+    TagNotFound,
+    // no release before serving version to rollback
+    NoPreviousRelease,
+    ScheduleNotFound,
+    FileNotFound,
+    // uploaded files don't match expected files
+    FileMismatch,
+    PathInvalid,
+    Internal,
+    // code added by newer server
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> u16 {
+        match self {
+            ErrorCode::Unauthorized => 401,
+            ErrorCode::Forbidden => 403,
+            ErrorCode::DomainNotFound
+            | ErrorCode::VersionNotFound
+            | ErrorCode::FileNotFound
+            | ErrorCode::TrashNotFound
+            | ErrorCode::TagNotFound
+            | ErrorCode::ScheduleNotFound => 404,
+            ErrorCode::TooManyRequests => 429,
+            ErrorCode::Internal | ErrorCode::Unknown => 500,
+            _ => 400,
+        }
+    }
+
+    // for response without json body, like from proxy
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            400 => ErrorCode::InvalidParameters,
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::Forbidden,
+            429 => ErrorCode::TooManyRequests,
+            500 => ErrorCode::Internal,
+            _ => ErrorCode::Unknown,
+        }
+    }
+}
+
+// error response body of admin api: {"code": "version_uploading", "message": "..", "details": {..}}
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new<T: Into<String>>(code: ErrorCode, message: T) -> Self {
+        ApiError {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
@@ -0,0 +1,176 @@
+use crate::storage::DomainVersion;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainEventKind {
+    UploadStart,
+    UploadFinish,
+    UploadAbort,
+    UploadExpire,
+    FileUpload,
+    Release,
+    Revoke,
+    Delete,
+    CronRun,
+    BatchRelease,
+}
+
+impl DomainEventKind {
+    pub const ALL: [DomainEventKind; 10] = [
+        DomainEventKind::UploadStart,
+        DomainEventKind::UploadFinish,
+        DomainEventKind::UploadAbort,
+        DomainEventKind::UploadExpire,
+        DomainEventKind::FileUpload,
+        DomainEventKind::Release,
+        DomainEventKind::Revoke,
+        DomainEventKind::Delete,
+        DomainEventKind::CronRun,
+        DomainEventKind::BatchRelease,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainEventKind::UploadStart => "upload_start",
+            DomainEventKind::UploadFinish => "upload_finish",
+            DomainEventKind::UploadAbort => "upload_abort",
+            DomainEventKind::UploadExpire => "upload_expire",
+            DomainEventKind::FileUpload => "file_upload",
+            DomainEventKind::Release => "release",
+            DomainEventKind::Revoke => "revoke",
+            DomainEventKind::Delete => "delete",
+            DomainEventKind::CronRun => "cron_run",
+            DomainEventKind::BatchRelease => "batch_release",
+        }
+    }
+}
+
+impl FromStr for DomainEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DomainEventKind::ALL
+            .into_iter()
+            .find(|x| x.as_str() == s)
+            .ok_or_else(|| format!("unknown event: {s}"))
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DomainEventData {
+    UploadStart {
+        domain: String,
+        version: u32,
+    },
+    UploadFinish {
+        domain: String,
+        version: u32,
+    },
+    // uploading version is aborted and its files are removed
+    UploadAbort {
+        domain: String,
+        version: u32,
+    },
+    // uploading version is idle too long, its files are removed
+    UploadExpire {
+        domain: String,
+        version: u32,
+        last_activity: DateTime<Utc>,
+    },
+    FileUpload {
+        domain: String,
+        version: u32,
+        path: String,
+    },
+    Release {
+        domain: String,
+        version: u32,
+    },
+    // release an older version than serving one
+    Revoke {
+        domain: String,
+        version: u32,
+    },
+    Delete {
+        domain: String,
+        version: u32,
+    },
+    // cron job of deleting deprecated versions
+    CronRun {
+        deleted: usize,
+    },
+    // versions of multiple domains are switched together
+    BatchRelease {
+        releases: Vec<DomainVersion>,
+    },
+}
+
+impl DomainEventData {
+    pub fn kind(&self) -> DomainEventKind {
+        match self {
+            DomainEventData::UploadStart { .. } => DomainEventKind::UploadStart,
+            DomainEventData::UploadFinish { .. } => DomainEventKind::UploadFinish,
+            DomainEventData::UploadAbort { .. } => DomainEventKind::UploadAbort,
+            DomainEventData::UploadExpire { .. } => DomainEventKind::UploadExpire,
+            DomainEventData::FileUpload { .. } => DomainEventKind::FileUpload,
+            DomainEventData::Release { .. } => DomainEventKind::Release,
+            DomainEventData::Revoke { .. } => DomainEventKind::Revoke,
+            DomainEventData::Delete { .. } => DomainEventKind::Delete,
+            DomainEventData::CronRun { .. } => DomainEventKind::CronRun,
+            DomainEventData::BatchRelease { .. } => DomainEventKind::BatchRelease,
+        }
+    }
+
+    pub fn domain(&self) -> Option<&str> {
+        match self {
+            DomainEventData::UploadStart { domain, .. }
+            | DomainEventData::UploadFinish { domain, .. }
+            | DomainEventData::UploadAbort { domain, .. }
+            | DomainEventData::UploadExpire { domain, .. }
+            | DomainEventData::FileUpload { domain, .. }
+            | DomainEventData::Release { domain, .. }
+            | DomainEventData::Revoke { domain, .. }
+            | DomainEventData::Delete { domain, .. } => Some(domain),
+            DomainEventData::CronRun { .. } | DomainEventData::BatchRelease { .. } => None,
+        }
+    }
+
+    // batch release has multiple domains
+    pub fn domains(&self) -> Vec<&str> {
+        match self {
+            DomainEventData::BatchRelease { releases } => {
+                releases.iter().map(|x| x.domain.as_str()).collect()
+            }
+            data => data.domain().into_iter().collect(),
+        }
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        match self {
+            DomainEventData::UploadStart { version, .. }
+            | DomainEventData::UploadFinish { version, .. }
+            | DomainEventData::UploadAbort { version, .. }
+            | DomainEventData::UploadExpire { version, .. }
+            | DomainEventData::FileUpload { version, .. }
+            | DomainEventData::Release { version, .. }
+            | DomainEventData::Revoke { version, .. }
+            | DomainEventData::Delete { version, .. } => Some(*version),
+            DomainEventData::CronRun { .. } | DomainEventData::BatchRelease { .. } => None,
+        }
+    }
+}
+
+// deployment lifecycle event, like: {"id":1,"time":"..","event":"release","domain":"..","version":1}
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct DomainEvent {
+    // increase from 1 after server start
+    pub id: u64,
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub data: DomainEventData,
+}
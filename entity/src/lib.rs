@@ -1,2 +1,4 @@
+pub mod error;
+pub mod event;
 pub mod request;
 pub mod storage;
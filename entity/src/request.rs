@@ -1,54 +1,188 @@
-use crate::storage::UploadingStatus;
+use crate::storage::{AuditAction, AuditResult, ShortMetaData, UploadingStatus, VersionMetadata};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetDomainOption {
     pub domain: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Default)]
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Default, ToSchema)]
 pub enum GetDomainPositionFormat {
     #[default]
     Path,
     Json,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetDomainPositionOption {
     pub domain: String,
     //#[serde(default="crate::admin_server::request::GetDomainPositionFormat::Path")]
     #[serde(default)]
+    #[param(inline)]
     pub format: GetDomainPositionFormat,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct UploadFileOption {
     pub domain: String,
     pub version: u32,
     pub path: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DomainWithVersionOption {
     pub domain: String,
     pub version: u32,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListFilesOption {
+    pub domain: String,
+    pub version: u32,
+    // directory relative to version, default is the root of version
+    pub path: Option<String>,
+    // also list files of sub directories, default value is false
+    pub recursive: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadFileOption {
+    pub domain: String,
+    pub version: u32,
+    // file path relative to version, like: js/app.js
+    pub path: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffVersionOption {
+    pub domain: String,
+    pub from: u32,
+    pub to: u32,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct DomainWithOptVersionOption {
     pub domain: String,
     pub version: Option<u32>,
+    // tag or channel, instead of version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    // schedule the release at the time instead of releasing now, only for release
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Deserialize, Serialize)]
+// versions of all domains are released together, or none of them if any fails
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct BatchReleaseOption {
+    pub releases: Vec<DomainWithOptVersionOption>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct UpdateUploadingStatusOption {
     pub domain: String,
     pub version: u32,
     pub status: UploadingStatus,
+    // saved with version, it replaces the old one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<VersionMetadata>,
+    // expected files of version, checked by path, md5 and length before finish
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<ShortMetaData>>,
+    // release the version after finish, only for finish
+    #[serde(default)]
+    pub release: bool,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct DeleteDomainVersionOption {
     pub domain: Option<String>,
+    // keep the most recent finished versions
     pub max_reserve: Option<u32>,
+    // keep versions finished in recent days
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    // only return versions to delete
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReleaseHistoryQuery {
+    pub domain: String,
+    // return the latest records, default value is 100
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct RollbackOption {
+    pub domain: String,
+}
+
+// point tag or channel to version, or to the version which tag or channel `from` points to
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct SetTagOption {
+    pub domain: String,
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<u32>,
+    #[serde(default)]
+    pub from: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct DeleteTagOption {
+    pub domain: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct ScheduledReleaseOption {
+    pub id: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct TrashItemOption {
+    pub id: String,
+}
+
+// purge the item of id, or all items of domain(also includes sub path), or all items
+#[derive(Deserialize, Serialize, Debug, Default, ToSchema)]
+pub struct PurgeTrashOption {
+    pub domain: Option<String>,
+    pub id: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    // also includes events of sub path
+    pub domain: Option<String>,
+    // replay recent events after it, same as header `Last-Event-ID`
+    pub last_event_id: Option<u64>,
+    // keep the stream open for new events, default value is true
+    pub follow: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub domain: Option<String>,
+    pub name: Option<String>,
+    pub action: Option<AuditAction>,
+    pub result: Option<AuditResult>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    // return the latest records, default value is 100
+    pub limit: Option<usize>,
 }
@@ -1,32 +1,176 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::str::FromStr;
+use utoipa::ToSchema;
 
-#[derive(Deserialize, Serialize, Debug)]
+// admin api path prefix, like: /v1/status
+pub const ADMIN_API_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct ServerVersion {
+    // spa-server version, like: 3.0.1
+    pub version: String,
+    // supported admin api versions
+    pub api_versions: Vec<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
 pub struct DomainInfo {
     pub domain: String, // www.example.com|www.example.com/a/b
     pub current_version: Option<u32>,
     pub versions: Vec<u32>,
-    //pub uploading_version: Vec<u32>, //TODO: add uploading_versions
+    // version in uploading status
+    #[serde(default)]
+    pub uploading_version: Option<u32>,
+    #[serde(default)]
+    pub upload_session: Option<UploadSession>,
+    // versions protected from deletion
+    #[serde(default)]
+    pub pinned_versions: Vec<u32>,
+    // metadata of versions, like: {"2": {"commit": "..", "message": ".."}}
+    #[serde(default)]
+    pub metadata: BTreeMap<u32, VersionMetadata>,
+    // tag name -> version, a tag is unique in domain
+    #[serde(default)]
+    pub tags: BTreeMap<String, u32>,
+    // channel name -> version, like: {"stable": 2, "beta": 3}
+    #[serde(default)]
+    pub channels: BTreeMap<String, u32>,
+    // releases waiting for their time, order by release time
+    #[serde(default)]
+    pub scheduled_releases: Vec<ScheduledRelease>,
     //pub web_path: Vec<String>, // [www.example.com/index.html|www.example.com/a/b/index.html,...]
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct DomainVersion {
+    pub domain: String,
+    pub version: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseAction {
+    #[default]
+    Release,
+    Revoke,
+    Rollback,
+}
+
+// release version at given time, it's kept until released or canceled
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ScheduledRelease {
+    pub id: u64,
+    pub domain: String,
+    pub version: u32,
+    pub release_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    // token name of admin api caller
+    pub actor: String,
+}
+
+// a change of serving version, kept in release history of domain
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ReleaseRecord {
+    pub time: DateTime<Utc>,
+    pub version: u32,
+    // serving version before release
+    pub previous_version: Option<u32>,
+    // token name of admin api caller
+    pub actor: String,
+    #[serde(default)]
+    pub action: ReleaseAction,
+}
+
+// set when uploading, all fields are optional
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct VersionMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    // url of CI job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ci_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_time: Option<DateTime<Utc>>,
+    // any other key value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
+// deleted version in trash, it could be restored before purged
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct TrashItem {
+    pub id: String,
+    pub domain: String,
+    pub version: u32,
+    pub deleted_at: DateTime<Utc>,
+}
+
+// uploading version would be expired if no activity after idle timeout
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct UploadSession {
+    pub version: u32,
+    pub start: DateTime<Utc>,
+    // last time of changing upload status or uploading file
+    pub last_activity: DateTime<Utc>,
+    // files are being verified and released, uploading file is rejected
+    #[serde(default)]
+    pub finishing: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
 pub struct ShortMetaData {
     pub path: String,
     pub md5: String,
     pub length: u64,
 }
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug)]
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct FileEntry {
+    // relative path to version directory, like: js/app.js
+    pub path: String,
+    pub is_dir: bool,
+    // 0 for directory
+    pub length: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, ToSchema)]
+pub struct FileDiff {
+    pub path: String,
+    // None if file is added
+    pub from_length: Option<u64>,
+    // None if file is removed
+    pub to_length: Option<u64>,
+}
+
+// files are compared by md5, and sorted by path
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct VersionDiff {
+    pub added: Vec<FileDiff>,
+    pub removed: Vec<FileDiff>,
+    pub modified: Vec<FileDiff>,
+    // count of unchanged files
+    pub unchanged: usize,
+}
+
+#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug, ToSchema)]
 #[repr(u8)]
 pub enum UploadingStatus {
     Uploading = 0,
     Finish = 1,
 }
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug)]
+#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug, ToSchema)]
 #[repr(u8)]
 pub enum GetDomainPositionStatus {
     NewDomain = 0,
@@ -34,16 +178,103 @@ pub enum GetDomainPositionStatus {
     InUploading = 2,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
 pub struct UploadDomainPosition {
+    #[schema(value_type = String)]
     pub path: PathBuf,
     pub version: u32,
     pub status: GetDomainPositionStatus,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
 pub struct CertInfo {
     pub begin: DateTime<Utc>,
     pub end: DateTime<Utc>,
     pub host: String,
 }
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    UploadStatus,
+    UploadFile,
+    UpdateVersion,
+    RevokeVersion,
+    DeleteVersion,
+    AbortUpload,
+    PinVersion,
+    UnpinVersion,
+    RestoreVersion,
+    PurgeTrash,
+    SetTag,
+    DeleteTag,
+    SetChannel,
+    DeleteChannel,
+    Rollback,
+    CancelRelease,
+    BatchRelease,
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upload_status" => Ok(AuditAction::UploadStatus),
+            "upload_file" => Ok(AuditAction::UploadFile),
+            "update_version" => Ok(AuditAction::UpdateVersion),
+            "revoke_version" => Ok(AuditAction::RevokeVersion),
+            "delete_version" => Ok(AuditAction::DeleteVersion),
+            "abort_upload" => Ok(AuditAction::AbortUpload),
+            "pin_version" => Ok(AuditAction::PinVersion),
+            "unpin_version" => Ok(AuditAction::UnpinVersion),
+            "restore_version" => Ok(AuditAction::RestoreVersion),
+            "purge_trash" => Ok(AuditAction::PurgeTrash),
+            "set_tag" => Ok(AuditAction::SetTag),
+            "delete_tag" => Ok(AuditAction::DeleteTag),
+            "set_channel" => Ok(AuditAction::SetChannel),
+            "delete_channel" => Ok(AuditAction::DeleteChannel),
+            "rollback" => Ok(AuditAction::Rollback),
+            "cancel_release" => Ok(AuditAction::CancelRelease),
+            "batch_release" => Ok(AuditAction::BatchRelease),
+            _ => Err(format!("unknown audit action: {s}")),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditResult {
+    Success,
+    Failure,
+}
+
+impl FromStr for AuditResult {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(AuditResult::Success),
+            "failure" => Ok(AuditResult::Failure),
+            _ => Err(format!("unknown audit result: {s}")),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct AuditRecord {
+    pub time: DateTime<Utc>,
+    // token name of admin api caller
+    pub name: String,
+    pub ip: Option<String>,
+    pub action: AuditAction,
+    pub domain: Option<String>,
+    pub version: Option<u32>,
+    // like uploaded file path, deleted versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub result: AuditResult,
+    // failure reason
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
@@ -1,34 +1,75 @@
-use crate::config::{AdminConfig, get_host_path_from_domain};
+use crate::audit::{Audit, AuditLog};
+use crate::auth::BearerValidator;
+use crate::config::{AdminConfig, RetentionPolicy, get_host_path_from_domain};
 use crate::domain_storage::DomainStorage;
+use crate::limiter::HttpMetrics;
+use crate::tls::{AdminTlsAcceptor, load_server_config};
 use delay_timer::prelude::*;
+use entity::error::{ApiError, ErrorCode};
+use entity::event::DomainEventData;
+use entity::storage::{ADMIN_API_VERSION, AuditAction, ReleaseAction, ScheduledRelease};
 use salvo::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct BearerValidator {
-    token: String,
-}
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "spa-server admin api"),
+    servers((url = "/v1")),
+    paths(
+        service::get_version,
+        service::get_domain_info,
+        service::get_upload_position,
+        service::update_domain_version,
+        service::change_upload_status,
+        service::update_file,
+        service::get_files_metadata,
+        service::list_files,
+        service::diff_versions,
+        service::download_file,
+        service::download_archive,
+        service::remove_domain_version,
+        service::delete_version,
+        service::abort_upload,
+        service::pin_version,
+        service::unpin_version,
+        service::list_trash,
+        service::restore_trash,
+        service::purge_trash,
+        service::revoke_version,
+        service::get_release_history,
+        service::rollback,
+        service::batch_release,
+        service::list_scheduled_releases,
+        service::cancel_scheduled_release,
+        service::set_tag,
+        service::delete_tag,
+        service::set_channel,
+        service::delete_channel,
+        service::get_metrics,
+        service::get_audit_log,
+        service::get_events,
+    ),
+    components(schemas(ErrorCode)),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+)]
+pub struct AdminApiDoc;
 
-#[async_trait]
-impl Handler for BearerValidator {
-    async fn handle(
-        &self,
-        req: &mut Request,
-        depot: &mut Depot,
-        res: &mut Response,
-        ctrl: &mut FlowCtrl,
-    ) {
-        match req.headers().get("Authorization") {
-            Some(value) if value == &self.token => {
-                ctrl.call_next(req, depot, res).await;
-            }
-            _ => {
-                res.status_code(StatusCode::UNAUTHORIZED);
-                ctrl.skip_rest();
-            }
-        }
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_default()
+            .add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
     }
 }
 
@@ -37,6 +78,9 @@ pub struct AdminServer {
     domain_storage: Arc<DomainStorage>,
     delay_timer: DelayTimer,
     host_alias: Arc<HashMap<String, String>>,
+    domain_retention: Arc<HashMap<String, RetentionPolicy>>,
+    http_metrics: Arc<HttpMetrics>,
+    audit_log: Arc<AuditLog>,
 }
 
 impl AdminServer {
@@ -45,32 +89,158 @@ impl AdminServer {
         domain_storage: Arc<DomainStorage>,
         delay_timer: DelayTimer,
         host_alias: Arc<HashMap<String, String>>,
+        domain_retention: Arc<HashMap<String, RetentionPolicy>>,
+        http_metrics: Arc<HttpMetrics>,
+        audit_log: Arc<AuditLog>,
     ) -> Self {
         AdminServer {
             conf: Arc::new(conf.clone()),
             domain_storage,
             delay_timer,
             host_alias,
+            domain_retention,
+            http_metrics,
+            audit_log,
         }
     }
 
-    fn routes(&self) -> Router {
-        Router::with_hoop(BearerValidator {
-            token: format!("Bearer {}", self.conf.token),
-        })
-        .hoop(
-            affix_state::inject(self.domain_storage.clone())
-                .inject(self.conf.clone())
-                .inject(self.host_alias.clone()),
-        )
-        .push(Router::with_path("status").get(service::get_domain_info))
-        .push(Router::with_path("upload/position").get(service::get_upload_position))
-        .push(Router::with_path("update_version").post(service::update_domain_version))
-        .push(Router::with_path("files/upload_status").post(service::change_upload_status))
-        .push(Router::with_path("file/upload").post(service::update_file))
-        .push(Router::with_path("files/metadata").get(service::get_files_metadata))
-        .push(Router::with_path("files/delete").post(service::remove_domain_version))
-        .push(Router::with_path("files/revoke_version").post(service::revoke_version))
+    fn routes(&self, validator: BearerValidator) -> Router {
+        let api = Router::with_hoop(validator)
+            .hoop(
+                affix_state::inject(self.domain_storage.clone())
+                    .inject(self.conf.clone())
+                    .inject(self.host_alias.clone())
+                    .inject(self.http_metrics.clone())
+                    .inject(self.audit_log.clone())
+                    .inject(self.delay_timer.clone()),
+            )
+            .push(Router::with_path(format!("v{ADMIN_API_VERSION}")).push(Self::api_routes()))
+            // unversioned paths are kept for old spa-client
+            .push(Router::with_hoop(service::deprecated_path).push(Self::api_routes()));
+        // api spec and swagger ui don't need auth
+        let router =
+            Router::new().push(Router::with_path("openapi.json").get(service::get_openapi));
+        let router = if self.conf.swagger_ui {
+            router.push(Router::with_path("swagger-ui/{**path}").get(service::get_swagger_ui))
+        } else {
+            router
+        };
+        // page of dashboard has no data, it calls admin api with token
+        let router = if self.conf.dashboard {
+            router.push(Router::with_path("dashboard/{**path}").get(service::get_dashboard))
+        } else {
+            router
+        };
+        router.push(api)
+    }
+
+    // every route should be documented in `AdminApiDoc`
+    fn api_routes() -> Router {
+        Router::new()
+            .push(Router::with_path("version").get(service::get_version))
+            .push(Router::with_path("status").get(service::get_domain_info))
+            .push(Router::with_path("upload/position").get(service::get_upload_position))
+            .push(
+                Router::with_path("update_version")
+                    .hoop(Audit(AuditAction::UpdateVersion))
+                    .post(service::update_domain_version),
+            )
+            .push(
+                Router::with_path("files/upload_status")
+                    .hoop(Audit(AuditAction::UploadStatus))
+                    .post(service::change_upload_status),
+            )
+            .push(
+                Router::with_path("file/upload")
+                    .hoop(Audit(AuditAction::UploadFile))
+                    .post(service::update_file),
+            )
+            .push(Router::with_path("files/metadata").get(service::get_files_metadata))
+            .push(Router::with_path("files/list").get(service::list_files))
+            .push(Router::with_path("files/diff").get(service::diff_versions))
+            .push(Router::with_path("file/download").get(service::download_file))
+            .push(Router::with_path("files/archive").get(service::download_archive))
+            .push(
+                Router::with_path("files/delete")
+                    .hoop(Audit(AuditAction::DeleteVersion))
+                    .post(service::remove_domain_version),
+            )
+            .push(
+                Router::with_path("files/delete_version")
+                    .hoop(Audit(AuditAction::DeleteVersion))
+                    .post(service::delete_version),
+            )
+            .push(
+                Router::with_path("files/abort_upload")
+                    .hoop(Audit(AuditAction::AbortUpload))
+                    .post(service::abort_upload),
+            )
+            .push(
+                Router::with_path("files/pin")
+                    .hoop(Audit(AuditAction::PinVersion))
+                    .post(service::pin_version),
+            )
+            .push(
+                Router::with_path("files/unpin")
+                    .hoop(Audit(AuditAction::UnpinVersion))
+                    .post(service::unpin_version),
+            )
+            .push(Router::with_path("trash").get(service::list_trash))
+            .push(
+                Router::with_path("trash/restore")
+                    .hoop(Audit(AuditAction::RestoreVersion))
+                    .post(service::restore_trash),
+            )
+            .push(
+                Router::with_path("trash/purge")
+                    .hoop(Audit(AuditAction::PurgeTrash))
+                    .post(service::purge_trash),
+            )
+            .push(
+                Router::with_path("files/revoke_version")
+                    .hoop(Audit(AuditAction::RevokeVersion))
+                    .post(service::revoke_version),
+            )
+            .push(Router::with_path("history").get(service::get_release_history))
+            .push(
+                Router::with_path("rollback")
+                    .hoop(Audit(AuditAction::Rollback))
+                    .post(service::rollback),
+            )
+            .push(
+                Router::with_path("release/batch")
+                    .hoop(Audit(AuditAction::BatchRelease))
+                    .post(service::batch_release),
+            )
+            .push(Router::with_path("schedule").get(service::list_scheduled_releases))
+            .push(
+                Router::with_path("schedule/cancel")
+                    .hoop(Audit(AuditAction::CancelRelease))
+                    .post(service::cancel_scheduled_release),
+            )
+            .push(
+                Router::with_path("tags/set")
+                    .hoop(Audit(AuditAction::SetTag))
+                    .post(service::set_tag),
+            )
+            .push(
+                Router::with_path("tags/delete")
+                    .hoop(Audit(AuditAction::DeleteTag))
+                    .post(service::delete_tag),
+            )
+            .push(
+                Router::with_path("channels/set")
+                    .hoop(Audit(AuditAction::SetChannel))
+                    .post(service::set_channel),
+            )
+            .push(
+                Router::with_path("channels/delete")
+                    .hoop(Audit(AuditAction::DeleteChannel))
+                    .post(service::delete_channel),
+            )
+            .push(Router::with_path("metrics").get(service::get_metrics))
+            .push(Router::with_path("audit").get(service::get_audit_log))
+            .push(Router::with_path("events").get(service::get_events))
     }
 
     pub async fn run(&self) -> anyhow::Result<()> {
@@ -81,12 +251,45 @@ impl AdminServer {
         if let Some(cron_config) = &self.conf.deprecated_version_delete {
             self.delay_timer.add_task(build_async_job(
                 self.domain_storage.clone(),
-                Some(cron_config.max_reserve),
+                cron_config.retention(),
+                self.domain_retention.clone(),
                 &cron_config.cron,
             )?)?;
         }
+        self.delay_timer.add_task(build_purge_trash_job(
+            self.domain_storage.clone(),
+            self.conf.trash.retention_days,
+            &self.conf.trash.cron,
+        )?)?;
+        if let Some(expire_config) = &self.conf.upload_session_expire {
+            self.delay_timer.add_task(build_expire_job(
+                self.domain_storage.clone(),
+                expire_config.idle_timeout,
+                &expire_config.cron,
+            )?)?;
+        }
+        // scheduled releases are kept in file, those missed when server was down run at once
+        for item in self.domain_storage.list_scheduled_releases(None) {
+            self.delay_timer.add_task(build_scheduled_release_job(
+                self.domain_storage.clone(),
+                &item,
+            )?)?;
+        }
 
-        Server::new(listener).serve(self.routes()).await;
+        let validator = BearerValidator::new(&self.conf)?;
+        match &self.conf.tls {
+            Some(tls) => {
+                let acceptor = AdminTlsAcceptor::new(
+                    listener,
+                    load_server_config(tls)?,
+                    validator.peer_certificates(),
+                );
+                Server::new(acceptor).serve(self.routes(validator)).await;
+            }
+            None => {
+                Server::new(listener).serve(self.routes(validator)).await;
+            }
+        }
 
         Ok(())
     }
@@ -98,8 +301,12 @@ impl AdminServer {
     ) -> bool {
         let (host, _) = get_host_path_from_domain(domain);
         if let Some(original_host) = host_alias.get(host) {
-            bad_resp(
-                format!("should not use alias domain, please use {original_host}"),
+            error_resp(
+                ApiError::new(
+                    ErrorCode::DomainIsAlias,
+                    format!("should not use alias domain, please use {original_host}"),
+                )
+                .details(json!({ "domain": original_host })),
                 res,
             );
             return true;
@@ -109,25 +316,154 @@ impl AdminServer {
 }
 
 pub mod service {
-    use crate::admin_server::bad_resp;
-    use crate::domain_storage::{DomainStorage, uri_regex};
+    use crate::admin_server::{
+        SCHEDULED_RELEASE_TASK_ID_OFFSET, anyhow_resp, error_resp, invalid_parameters,
+        schedule_release,
+    };
+    use crate::audit::{AuditLog, AuditTarget};
+    use crate::auth::{AdminIdentity, check_permission};
+    use crate::config::{
+        AdminAction, RetentionPolicy, get_host_path_from_domain, is_same_or_sub_domain,
+    };
+    use crate::domain_storage::{
+        DEFAULT_HISTORY_LIMIT, DomainStorage, uri_regex, write_version_archive,
+    };
+    use crate::limiter::HttpMetrics;
+    use delay_timer::prelude::DelayTimer;
+    use entity::error::{ApiError, ErrorCode};
+    use entity::event::{DomainEvent, DomainEventData};
     use entity::request::{
-        DeleteDomainVersionOption, DomainWithOptVersionOption, DomainWithVersionOption,
-        GetDomainOption, GetDomainPositionFormat, GetDomainPositionOption,
-        UpdateUploadingStatusOption, UploadFileOption,
+        AuditQuery, BatchReleaseOption, DeleteDomainVersionOption, DeleteTagOption,
+        DiffVersionOption, DomainWithOptVersionOption, DomainWithVersionOption, DownloadFileOption,
+        EventsQuery, GetDomainOption, GetDomainPositionFormat, GetDomainPositionOption,
+        ListFilesOption, PurgeTrashOption, ReleaseHistoryQuery, RollbackOption,
+        ScheduledReleaseOption, SetTagOption, TrashItemOption, UpdateUploadingStatusOption,
+        UploadFileOption,
+    };
+    use entity::storage::{
+        ADMIN_API_VERSION, AuditRecord, DomainInfo, DomainVersion, FileEntry, ReleaseAction,
+        ReleaseRecord, ScheduledRelease, ServerVersion, ShortMetaData, TrashItem,
+        UploadDomainPosition, UploadingStatus, VersionDiff,
     };
-    use entity::storage::DomainInfo;
+    use futures_util::{StreamExt, future, stream};
+    use salvo::fs::NamedFile;
+    use salvo::http::HeaderValue;
     use salvo::prelude::*;
+    use salvo::sse::{SseEvent, SseKeepAlive};
     use std::collections::HashMap;
     use std::sync::Arc;
     use tracing::error;
+    use utoipa::{OpenApi, ToSchema};
+
+    // multipart form of `file/upload`, only for api spec
+    #[derive(ToSchema)]
+    #[allow(dead_code)]
+    pub(super) struct UploadFileForm {
+        #[schema(value_type = String, format = Binary)]
+        file: Vec<u8>,
+    }
+
+    #[handler]
+    pub(super) async fn deprecated_path(req: &mut Request, res: &mut Response) {
+        res.headers_mut()
+            .insert("deprecation", HeaderValue::from_static("true"));
+        if let Ok(link) = HeaderValue::from_str(&format!(
+            "</v{ADMIN_API_VERSION}{}>; rel=\"successor-version\"",
+            req.uri().path()
+        )) {
+            res.headers_mut().insert("link", link);
+        }
+    }
 
+    #[utoipa::path(
+        get,
+        path = "/version",
+        responses((status = 200, description = "server version and supported api versions", body = ServerVersion)),
+        tag = "simple",
+    )]
+    #[handler]
+    pub(super) async fn get_version(res: &mut Response) {
+        res.render(Json(ServerVersion {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            api_versions: vec![ADMIN_API_VERSION],
+        }));
+    }
+
+    #[handler]
+    pub(super) async fn get_openapi(res: &mut Response) {
+        res.render(Json(super::AdminApiDoc::openapi()));
+    }
+
+    #[handler]
+    pub(super) async fn get_dashboard(req: &mut Request, res: &mut Response) {
+        let path = req.param::<String>("path").unwrap_or_default();
+        if !path.is_empty() {
+            error_resp(
+                ApiError::new(
+                    ErrorCode::FileNotFound,
+                    format!("dashboard file {path} does not exist"),
+                ),
+                res,
+            );
+        } else if !req.uri().path().ends_with('/') {
+            // relative api url of dashboard needs the trailing slash
+            res.render(Redirect::found(format!("{}/", req.uri().path())));
+        } else {
+            res.render(Text::Html(include_str!("../assets/dashboard.html")));
+        }
+    }
+
+    #[handler]
+    pub(super) async fn get_swagger_ui(req: &mut Request, res: &mut Response) {
+        let path = req.param::<String>("path").unwrap_or_default();
+        // relative urls of swagger ui need the trailing slash
+        if path.is_empty() && !req.uri().path().ends_with('/') {
+            res.render(Redirect::found(format!("{}/", req.uri().path())));
+            return;
+        }
+        let config = Arc::new(utoipa_swagger_ui::Config::from("../openapi.json"));
+        match utoipa_swagger_ui::serve(&path, config) {
+            Ok(Some(file)) => {
+                let _ = res.add_header("content-type", file.content_type, true);
+                let _ = res.write_body(file.bytes.into_owned());
+            }
+            Ok(None) => {
+                error_resp(
+                    ApiError::new(
+                        ErrorCode::FileNotFound,
+                        format!("swagger ui file {path} does not exist"),
+                    ),
+                    res,
+                );
+            }
+            Err(e) => {
+                error!("serve swagger ui error: {e}");
+                error_resp(
+                    ApiError::new(ErrorCode::Internal, format!("serve swagger ui error: {e}")),
+                    res,
+                );
+            }
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/status",
+        params(GetDomainOption),
+        responses((status = 200, description = "domains status", body = Vec<DomainInfo>)),
+        tag = "simple",
+    )]
     #[handler]
     pub(super) async fn get_domain_info(req: &mut Request, res: &mut Response, depot: &mut Depot) {
         let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        let identity = depot.obtain::<AdminIdentity>().unwrap();
         let option = req.parse_queries::<GetDomainOption>();
         match storage.get_domain_info() {
             Ok(domain_info) => {
+                let domain_info: Vec<DomainInfo> = domain_info
+                    .into_iter()
+                    .filter(|x| identity.is_allowed(&x.domain, AdminAction::Read))
+                    .collect();
                 if let Ok(option) = option
                     && let Some(domain) = option.domain
                 {
@@ -142,24 +478,117 @@ pub mod service {
                 res.render(Json(domain_info));
             }
             Err(e) => {
-                bad_resp(e.to_string(), res);
+                anyhow_resp(e, res);
             }
         }
     }
 
+    #[utoipa::path(
+        post,
+        path = "/update_version",
+        request_body = DomainWithOptVersionOption,
+        responses(
+            (status = 200, description = "release message", body = String, content_type = "text/plain"),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "simple",
+    )]
     #[handler]
     pub(super) async fn update_domain_version(
         req: &mut Request,
         res: &mut Response,
         depot: &mut Depot,
     ) {
-        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
         if let Ok(option) = req.parse_json::<DomainWithOptVersionOption>().await {
+            let tag_detail = option.tag.as_ref().map(|tag| format!("tag: {tag}"));
+            let mut target = AuditTarget::new(Some(&option.domain), option.version);
+            if let Some(detail) = &tag_detail {
+                target = target.detail(detail.clone());
+            }
+            depot.inject(target);
+            if !check_permission(depot, res, Some(&option.domain), AdminAction::Release) {
+                return;
+            }
+            let version = match storage.resolve_version(
+                &option.domain,
+                option.version,
+                option.tag.as_deref(),
+            ) {
+                Ok(version) => version,
+                Err(e) => {
+                    anyhow_resp(e, res);
+                    return;
+                }
+            };
+            let actor = depot.obtain::<AdminIdentity>().unwrap().name.clone();
+            if let Some(release_at) = option.release_at {
+                let Some(version) = version else {
+                    error_resp(
+                        ApiError::new(
+                            ErrorCode::InvalidParameters,
+                            "version or tag is required to schedule release",
+                        ),
+                        res,
+                    );
+                    return;
+                };
+                if release_at <= chrono::Utc::now() {
+                    error_resp(
+                        ApiError::new(
+                            ErrorCode::InvalidParameters,
+                            "release_at should be later than now",
+                        ),
+                        res,
+                    );
+                    return;
+                }
+                let delay_timer = depot.obtain::<DelayTimer>().unwrap().clone();
+                let detail = format!("release_at: {}", release_at.to_rfc3339());
+                let detail = match tag_detail {
+                    Some(tag_detail) => format!("{tag_detail}, {detail}"),
+                    None => detail,
+                };
+                depot.inject(AuditTarget::new(Some(&option.domain), Some(version)).detail(detail));
+                match schedule_release(
+                    storage,
+                    &delay_timer,
+                    &option.domain,
+                    version,
+                    release_at,
+                    &actor,
+                ) {
+                    Ok(item) => {
+                        let text = format!(
+                            "domain:{} static web version {} is scheduled to release at {}, id: {}",
+                            item.domain,
+                            item.version,
+                            item.release_at.to_rfc3339(),
+                            item.id
+                        );
+                        tracing::info!("{}", &text);
+                        res.render(text);
+                    }
+                    Err(e) => anyhow_resp(e, res),
+                }
+                return;
+            }
             match storage
-                .upload_domain_with_version(option.domain.clone(), option.version)
+                .release_version(
+                    option.domain.clone(),
+                    version,
+                    &actor,
+                    ReleaseAction::Release,
+                )
                 .await
             {
                 Ok(version) => {
+                    let mut target = AuditTarget::new(Some(&option.domain), Some(version));
+                    if let Some(detail) = tag_detail {
+                        target = target.detail(detail);
+                    }
+                    depot.inject(target);
                     let text = format!(
                         "domain:{} static web version has changed to {}",
                         option.domain, version
@@ -169,14 +598,25 @@ pub mod service {
                 }
                 Err(e) => {
                     error!("upload domain({}) version failure {:?}", option.domain, e);
-                    res.status_code(StatusCode::NOT_FOUND);
+                    anyhow_resp(e, res);
                 }
             }
         } else {
-            res.status_code(StatusCode::BAD_REQUEST);
+            invalid_parameters(res);
         }
     }
 
+    #[utoipa::path(
+        get,
+        path = "/upload/position",
+        params(GetDomainPositionOption),
+        responses(
+            (status = 200, description = "upload position, the path is returned as text if format is Path", body = UploadDomainPosition),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "simple",
+    )]
     #[handler]
     pub(super) async fn get_upload_position(
         req: &mut Request,
@@ -186,6 +626,9 @@ pub mod service {
         let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
         let host_alias = depot.obtain::<Arc<HashMap<String, String>>>().unwrap();
         if let Ok(option) = req.parse_queries::<GetDomainPositionOption>() {
+            if !check_permission(depot, res, Some(&option.domain), AdminAction::Upload) {
+                return;
+            }
             if super::AdminServer::check_alias(&option.domain, host_alias.clone(), res) {
                 return;
             }
@@ -199,60 +642,188 @@ pub mod service {
                         }
                     }
                     Err(err) => {
-                        bad_resp(err.to_string(), res);
+                        anyhow_resp(err, res);
                     }
                 }
             } else {
-                res.status_code(StatusCode::BAD_REQUEST);
+                error_resp(
+                    ApiError::new(
+                        ErrorCode::InvalidParameters,
+                        format!("invalid domain: {}", option.domain),
+                    ),
+                    res,
+                );
             }
         } else {
-            res.status_code(StatusCode::BAD_REQUEST);
+            invalid_parameters(res);
         }
     }
 
+    #[utoipa::path(
+        post,
+        path = "/files/upload_status",
+        request_body = UpdateUploadingStatusOption,
+        responses(
+            (status = 200, description = "status changed, or release message if release is set", body = String, content_type = "text/plain"),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "upload",
+    )]
     #[handler]
     pub(super) async fn change_upload_status(
         req: &mut Request,
         res: &mut Response,
         depot: &mut Depot,
     ) {
-        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
-        let host_alias = depot.obtain::<Arc<HashMap<String, String>>>().unwrap();
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        let host_alias = depot
+            .obtain::<Arc<HashMap<String, String>>>()
+            .unwrap()
+            .clone();
         if let Ok(param) = req.parse_json::<UpdateUploadingStatusOption>().await {
+            let detail = if param.release {
+                format!("status: {:?}, release", param.status)
+            } else {
+                format!("status: {:?}", param.status)
+            };
+            depot.inject(AuditTarget::new(Some(&param.domain), Some(param.version)).detail(detail));
+            if param.release && param.status != UploadingStatus::Finish {
+                error_resp(
+                    ApiError::new(
+                        ErrorCode::InvalidParameters,
+                        "release is only allowed with status Finish",
+                    ),
+                    res,
+                );
+                return;
+            }
+            if !check_permission(depot, res, Some(&param.domain), AdminAction::Upload)
+                || param.release
+                    && !check_permission(depot, res, Some(&param.domain), AdminAction::Release)
+            {
+                return;
+            }
             if super::AdminServer::check_alias(&param.domain, host_alias.clone(), res) {
                 return;
             }
-            match storage
-                .update_uploading_status(param.domain, param.version, param.status)
-                .await
+            if param.release && param.files.is_none() {
+                error_resp(
+                    ApiError::new(
+                        ErrorCode::InvalidParameters,
+                        "files should be set to verify them before release",
+                    ),
+                    res,
+                );
+                return;
+            }
+            // files can't be uploaded until it's finished. version keeps uploading
+            // if files don't match or metadata can't be saved, so it could be finished again
+            let lock = if param.status == UploadingStatus::Finish {
+                let result = storage
+                    .lock_finishing(&param.domain, param.version)
+                    .and_then(|lock| {
+                        if let Some(files) = &param.files {
+                            storage.verify_files(&param.domain, param.version, files)?;
+                        }
+                        if let Some(metadata) = &param.metadata {
+                            storage.save_version_metadata(
+                                &param.domain,
+                                param.version,
+                                metadata,
+                            )?;
+                        }
+                        Ok(lock)
+                    });
+                match result {
+                    Ok(lock) => Some(lock),
+                    Err(e) => {
+                        anyhow_resp(e, res);
+                        return;
+                    }
+                }
+            } else {
+                None
+            };
+            if param.release
+                && let Some(lock) = lock
             {
-                Ok(_) => {}
-                Err(e) => {
-                    bad_resp(e.to_string(), res);
+                let actor = depot.obtain::<AdminIdentity>().unwrap().name.clone();
+                match storage.finish_and_release(lock, &actor).await {
+                    Ok(version) => {
+                        let text = format!(
+                            "domain:{} static web version has changed to {}",
+                            param.domain, version
+                        );
+                        tracing::info!("{}", &text);
+                        res.render(text);
+                    }
+                    Err(e) => {
+                        error!("release domain({}) version failure {:?}", param.domain, e);
+                        anyhow_resp(e, res);
+                    }
                 }
+                return;
+            }
+            // directory of uploading version is created by changing status
+            let uploading = param.status == UploadingStatus::Uploading;
+            let result = storage
+                .update_uploading_status(param.domain.clone(), param.version, param.status)
+                .await;
+            drop(lock);
+            let result = match (result, &param.metadata) {
+                (Ok(_), Some(metadata)) if uploading => {
+                    storage.save_version_metadata(&param.domain, param.version, metadata)
+                }
+                (result, _) => result,
+            };
+            if let Err(e) = result {
+                anyhow_resp(e, res);
             }
         } else {
-            res.status_code(StatusCode::BAD_REQUEST);
+            invalid_parameters(res);
         }
     }
 
+    #[utoipa::path(
+        post,
+        path = "/file/upload",
+        params(UploadFileOption),
+        request_body(content = UploadFileForm, content_type = "multipart/form-data"),
+        responses(
+            (status = 200, description = "file saved"),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "upload",
+    )]
     #[handler]
     pub(super) async fn update_file(req: &mut Request, res: &mut Response, depot: &mut Depot) {
-        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
-        let host_alias = depot.obtain::<Arc<HashMap<String, String>>>().unwrap();
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        let host_alias = depot
+            .obtain::<Arc<HashMap<String, String>>>()
+            .unwrap()
+            .clone();
         let query = match req.parse_queries::<UploadFileOption>() {
             Ok(query) => query,
             Err(_e) => {
-                bad_resp("invalid parameters".to_owned(), res);
+                invalid_parameters(res);
                 return;
             }
         };
+        depot.inject(
+            AuditTarget::new(Some(&query.domain), Some(query.version))
+                .detail(format!("path: {}", query.path)),
+        );
 
+        if !check_permission(depot, res, Some(&query.domain), AdminAction::Upload) {
+            return;
+        }
         if super::AdminServer::check_alias(&query.domain, host_alias.clone(), res) {
             return;
         }
         if let Err(e) = storage.check_if_can_upload(&query.domain) {
-            bad_resp(e.to_string(), res);
+            anyhow_resp(e, res);
             return;
         }
         match req.file("file").await {
@@ -269,16 +840,30 @@ pub mod service {
                     }
                     Err(e) => {
                         error!("save upload file failure {}", e);
-                        bad_resp(format!("save upload file failure: {e}"), res);
+                        anyhow_resp(e, res);
                     }
                 }
             }
             None => {
-                bad_resp("file not found".to_string(), res);
+                error_resp(
+                    ApiError::new(ErrorCode::InvalidParameters, "file not found"),
+                    res,
+                );
             }
         }
     }
 
+    #[utoipa::path(
+        get,
+        path = "/files/metadata",
+        params(DomainWithVersionOption),
+        responses(
+            (status = 200, description = "metadata of uploaded files", body = Vec<ShortMetaData>),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "upload",
+    )]
     #[handler]
     pub(super) async fn get_files_metadata(
         req: &mut Request,
@@ -287,22 +872,30 @@ pub mod service {
     ) {
         let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
         if let Ok(query) = req.parse_queries::<DomainWithVersionOption>() {
+            if !check_permission(depot, res, Some(&query.domain), AdminAction::Read) {
+                return;
+            }
             match storage.get_files_metadata(query.domain, query.version) {
                 Ok(data) => res.render(Json(data)),
                 Err(err) => {
-                    bad_resp(err.to_string(), res);
+                    anyhow_resp(err, res);
                 }
             }
         } else {
-            res.status_code(StatusCode::BAD_REQUEST);
+            invalid_parameters(res);
         }
     }
 
+    // retention: from request, or config of domain, or default_retention
     pub(super) fn _remove_domain_version(
         domain: Option<String>,
-        max_reserve: Option<u32>,
+        retention: Option<RetentionPolicy>,
+        default_retention: RetentionPolicy,
+        domain_retention: &HashMap<String, RetentionPolicy>,
+        dry_run: bool,
         storage: &Arc<DomainStorage>,
-    ) {
+    ) -> Vec<DomainVersion> {
+        let mut deleted = Vec::new();
         let domains_info = if let Some(domain) = domain {
             storage
                 .get_domain_info_by_domain(&domain)
@@ -312,33 +905,210 @@ pub mod service {
             storage.get_domain_info().unwrap_or_else(|_| vec![])
         };
         for info in domains_info {
-            let delete_versions = if let Some(max_reserve) = max_reserve {
-                if let Some(mut max_version) =
-                    info.current_version.or(info.versions.iter().max().copied())
+            let retention = retention
+                .or_else(|| domain_retention.get(&info.domain).copied())
+                .or_else(|| {
+                    let (host, _) = get_host_path_from_domain(&info.domain);
+                    domain_retention.get(host).copied()
+                })
+                .unwrap_or(default_retention);
+            for version in storage.get_versions_out_of_retention(&info, &retention) {
+                if dry_run {
+                    deleted.push(DomainVersion {
+                        domain: info.domain.clone(),
+                        version,
+                    });
+                } else if let Ok(true) = storage.remove_domain_version(&info.domain, Some(version))
                 {
-                    //TODO: fix it, get reserve versions by array index compare, rather than -.
-                    max_version -= max_reserve;
-                    info.versions
-                        .into_iter()
-                        .filter(|v| *v <= max_version)
-                        .collect::<Vec<u32>>()
-                } else {
-                    vec![]
+                    deleted.push(DomainVersion {
+                        domain: info.domain.clone(),
+                        version,
+                    });
+                    storage.events().emit(DomainEventData::Delete {
+                        domain: info.domain.clone(),
+                        version,
+                    });
                 }
-            } else {
-                // TODO: keep uploading versions ?
-                let current_version = info.current_version.unwrap_or(u32::MAX);
-                info.versions
-                    .into_iter()
-                    .filter(|version| *version != current_version)
-                    .collect::<Vec<u32>>()
-            };
-            for version in delete_versions {
-                let _ = storage.remove_domain_version(&info.domain, Some(version));
             }
         }
+        deleted
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/files/list",
+        params(ListFilesOption),
+        responses(
+            (status = 200, description = "files and directories, sorted by path", body = Vec<FileEntry>),
+            (status = 400, description = "invalid parameters", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version or file does not exist", body = ApiError),
+        ),
+        tag = "files",
+    )]
+    #[handler]
+    pub(super) async fn list_files(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(query) = req.parse_queries::<ListFilesOption>() else {
+            invalid_parameters(res);
+            return;
+        };
+        if !check_permission(depot, res, Some(&query.domain), AdminAction::Read) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        match storage.list_files(
+            &query.domain,
+            query.version,
+            query.path.as_deref().unwrap_or_default(),
+            query.recursive.unwrap_or(false),
+        ) {
+            Ok(entries) => res.render(Json(entries)),
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/files/diff",
+        params(DiffVersionOption),
+        responses(
+            (status = 200, description = "changes from version `from` to version `to`", body = VersionDiff),
+            (status = 400, description = "invalid parameters", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version does not exist", body = ApiError),
+        ),
+        tag = "files",
+    )]
+    #[handler]
+    pub(super) async fn diff_versions(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(query) = req.parse_queries::<DiffVersionOption>() else {
+            invalid_parameters(res);
+            return;
+        };
+        if !check_permission(depot, res, Some(&query.domain), AdminAction::Read) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        match storage.diff_versions(&query.domain, query.from, query.to) {
+            Ok(diff) => res.render(Json(diff)),
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/file/download",
+        params(DownloadFileOption),
+        responses(
+            (status = 200, description = "file content", body = Vec<u8>, content_type = "application/octet-stream"),
+            (status = 400, description = "invalid parameters", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version or file does not exist", body = ApiError),
+        ),
+        tag = "files",
+    )]
+    #[handler]
+    pub(super) async fn download_file(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(query) = req.parse_queries::<DownloadFileOption>() else {
+            invalid_parameters(res);
+            return;
+        };
+        if !check_permission(depot, res, Some(&query.domain), AdminAction::Read) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        match storage.get_version_file(&query.domain, query.version, &query.path) {
+            Ok(file) => {
+                let mut builder = NamedFile::builder(&file);
+                if let Some(name) = file.file_name().and_then(|x| x.to_str()) {
+                    builder = builder.attached_name(name);
+                }
+                builder.send(req.headers(), res).await;
+            }
+            Err(e) => anyhow_resp(e, res),
+        }
     }
 
+    // bridge of sync tar writer and async response body
+    struct ChannelWriter(tokio::sync::mpsc::Sender<std::io::Result<Vec<u8>>>);
+
+    impl std::io::Write for ChannelWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .blocking_send(Ok(buf.to_vec()))
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/files/archive",
+        params(DomainWithVersionOption),
+        responses(
+            (status = 200, description = "tar.gz of version", body = Vec<u8>, content_type = "application/gzip"),
+            (status = 400, description = "invalid parameters", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version or file does not exist", body = ApiError),
+        ),
+        tag = "files",
+    )]
+    #[handler]
+    pub(super) async fn download_archive(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(query) = req.parse_queries::<DomainWithVersionOption>() else {
+            invalid_parameters(res);
+            return;
+        };
+        if !check_permission(depot, res, Some(&query.domain), AdminAction::Read) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        let version_path = match storage.get_existing_version_path(&query.domain, query.version) {
+            Ok(path) => path,
+            Err(e) => {
+                anyhow_resp(e, res);
+                return;
+            }
+        };
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        tokio::task::spawn_blocking(move || {
+            let writer = std::io::BufWriter::new(ChannelWriter(sender.clone()));
+            if let Err(e) = write_version_archive(&version_path, writer) {
+                error!("archive {version_path:?} error: {e}");
+                // break the response, so client knows the archive is not complete
+                let _ = sender.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+        });
+        let name = format!(
+            "{}-{}.tar.gz",
+            query.domain.replace('/', "_"),
+            query.version
+        );
+        let _ = res.add_header("content-type", "application/gzip", true);
+        let _ = res.add_header(
+            "content-disposition",
+            format!("attachment; filename=\"{name}\""),
+            true,
+        );
+        res.stream(stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|x| (x, receiver))
+        }));
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/files/delete",
+        request_body = DeleteDomainVersionOption,
+        responses(
+            (status = 200, description = "deleted versions, or versions to delete if dry_run", body = Vec<DomainVersion>),
+            (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "upload",
+    )]
     #[handler]
     pub(super) async fn remove_domain_version(
         req: &mut Request,
@@ -346,66 +1116,890 @@ pub mod service {
         depot: &mut Depot,
     ) {
         if let Ok(query) = req.parse_json::<DeleteDomainVersionOption>().await {
-            let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
-            _remove_domain_version(query.domain, query.max_reserve, storage);
+            depot.inject(AuditTarget::new(query.domain.as_deref(), None));
+            if !check_permission(depot, res, query.domain.as_deref(), AdminAction::Delete) {
+                return;
+            }
+            let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+            // retention config is used by cron, request without rules keeps serving and uploading versions
+            let retention = RetentionPolicy {
+                max_reserve: query.max_reserve,
+                max_age_days: query.max_age_days,
+            };
+            let deleted = _remove_domain_version(
+                query.domain.clone(),
+                Some(retention),
+                RetentionPolicy::default(),
+                &HashMap::new(),
+                query.dry_run,
+                &storage,
+            );
+            let versions: Vec<String> = deleted
+                .iter()
+                .map(|x| format!("{}:{}", x.domain, x.version))
+                .collect();
+            let detail = if query.dry_run {
+                format!("dry run: [{}]", versions.join(", "))
+            } else {
+                format!("deleted: [{}]", versions.join(", "))
+            };
+            depot.inject(AuditTarget::new(query.domain.as_deref(), None).detail(detail));
+            res.render(Json(deleted));
         } else {
-            res.status_code(StatusCode::BAD_REQUEST);
+            invalid_parameters(res);
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/files/delete_version",
+        request_body = DomainWithVersionOption,
+        responses(
+            (status = 200, description = "version deleted"),
+            (status = 400, description = "invalid parameters or status", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version does not exist", body = ApiError),
+        ),
+        tag = "upload",
+    )]
+    #[handler]
+    pub(super) async fn delete_version(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(DomainWithVersionOption { domain, version }) =
+            req.parse_json::<DomainWithVersionOption>().await
+        else {
+            invalid_parameters(res);
+            return;
+        };
+        depot.inject(AuditTarget::new(Some(&domain), Some(version)));
+        if !check_permission(depot, res, Some(&domain), AdminAction::Delete) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        if let Err(e) = storage.delete_version(&domain, version) {
+            anyhow_resp(e, res);
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/files/abort_upload",
+        request_body = DomainWithVersionOption,
+        responses(
+            (status = 200, description = "uploading version aborted, its files are removed"),
+            (status = 400, description = "invalid parameters or status", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "upload",
+    )]
+    #[handler]
+    pub(super) async fn abort_upload(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(DomainWithVersionOption { domain, version }) =
+            req.parse_json::<DomainWithVersionOption>().await
+        else {
+            invalid_parameters(res);
+            return;
+        };
+        depot.inject(AuditTarget::new(Some(&domain), Some(version)));
+        if !check_permission(depot, res, Some(&domain), AdminAction::Upload) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        if let Err(e) = storage.abort_uploading(&domain, version) {
+            anyhow_resp(e, res);
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/files/pin",
+        request_body = DomainWithVersionOption,
+        responses(
+            (status = 200, description = "version pinned, it's protected from deletion"),
+            (status = 400, description = "invalid parameters or status", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version does not exist", body = ApiError),
+        ),
+        tag = "upload",
+    )]
+    #[handler]
+    pub(super) async fn pin_version(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        change_pinned(req, res, depot, true).await
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/files/unpin",
+        request_body = DomainWithVersionOption,
+        responses(
+            (status = 200, description = "version unpinned"),
+            (status = 400, description = "invalid parameters", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version does not exist", body = ApiError),
+        ),
+        tag = "upload",
+    )]
+    #[handler]
+    pub(super) async fn unpin_version(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        change_pinned(req, res, depot, false).await
+    }
+
+    async fn change_pinned(req: &mut Request, res: &mut Response, depot: &mut Depot, pinned: bool) {
+        let Ok(DomainWithVersionOption { domain, version }) =
+            req.parse_json::<DomainWithVersionOption>().await
+        else {
+            invalid_parameters(res);
+            return;
+        };
+        depot.inject(AuditTarget::new(Some(&domain), Some(version)));
+        if !check_permission(depot, res, Some(&domain), AdminAction::Delete) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        if let Err(e) = storage.pin_version(&domain, version, pinned) {
+            anyhow_resp(e, res);
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/schedule",
+        params(GetDomainOption),
+        responses(
+            (status = 200, description = "releases waiting for their time, order by release time", body = Vec<ScheduledRelease>),
+            (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "release",
+    )]
+    #[handler]
+    pub(super) async fn list_scheduled_releases(
+        req: &mut Request,
+        res: &mut Response,
+        depot: &mut Depot,
+    ) {
+        let Ok(query) = req.parse_queries::<GetDomainOption>() else {
+            invalid_parameters(res);
+            return;
+        };
+        if let Some(domain) = &query.domain
+            && !check_permission(depot, res, Some(domain), AdminAction::Read)
+        {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        let identity = depot.obtain::<AdminIdentity>().unwrap();
+        let items: Vec<ScheduledRelease> = storage
+            .list_scheduled_releases(query.domain.as_deref())
+            .into_iter()
+            .filter(|x| identity.is_allowed(&x.domain, AdminAction::Read))
+            .collect();
+        res.render(Json(items));
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/schedule/cancel",
+        request_body = ScheduledReleaseOption,
+        responses(
+            (status = 200, description = "scheduled release is canceled", body = ScheduledRelease),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "scheduled release does not exist", body = ApiError),
+        ),
+        tag = "release",
+    )]
+    #[handler]
+    pub(super) async fn cancel_scheduled_release(
+        req: &mut Request,
+        res: &mut Response,
+        depot: &mut Depot,
+    ) {
+        let Ok(option) = req.parse_json::<ScheduledReleaseOption>().await else {
+            invalid_parameters(res);
+            return;
+        };
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        let Some(item) = storage
+            .list_scheduled_releases(None)
+            .into_iter()
+            .find(|x| x.id == option.id)
+        else {
+            error_resp(
+                ApiError::new(
+                    ErrorCode::ScheduleNotFound,
+                    format!("scheduled release: {} does not exist", option.id),
+                ),
+                res,
+            );
+            return;
+        };
+        depot.inject(
+            AuditTarget::new(Some(&item.domain), Some(item.version))
+                .detail(format!("id: {}", item.id)),
+        );
+        if !check_permission(depot, res, Some(&item.domain), AdminAction::Release) {
+            return;
+        }
+        match storage.remove_scheduled_release(item.id) {
+            Ok(item) => {
+                let delay_timer = depot.obtain::<DelayTimer>().unwrap();
+                if let Err(e) = delay_timer.remove_task(SCHEDULED_RELEASE_TASK_ID_OFFSET + item.id)
+                {
+                    tracing::warn!(
+                        "remove task of scheduled release {} failure: {e:?}",
+                        item.id
+                    );
+                }
+                res.render(Json(item));
+            }
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/trash",
+        params(GetDomainOption),
+        responses(
+            (status = 200, description = "deleted versions in trash, sorted by deleted time", body = Vec<TrashItem>),
+            (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "trash",
+    )]
+    #[handler]
+    pub(super) async fn list_trash(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(query) = req.parse_queries::<GetDomainOption>() else {
+            invalid_parameters(res);
+            return;
+        };
+        if let Some(domain) = &query.domain
+            && !check_permission(depot, res, Some(domain), AdminAction::Read)
+        {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        let identity = depot.obtain::<AdminIdentity>().unwrap();
+        match storage.list_trash(query.domain.as_deref()) {
+            Ok(items) => {
+                let items: Vec<TrashItem> = items
+                    .into_iter()
+                    .filter(|x| identity.is_allowed(&x.domain, AdminAction::Read))
+                    .collect();
+                res.render(Json(items));
+            }
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/trash/restore",
+        request_body = TrashItemOption,
+        responses(
+            (status = 200, description = "version restored, it's not released", body = TrashItem),
+            (status = 400, description = "invalid parameters or version exists", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "trash item does not exist", body = ApiError),
+        ),
+        tag = "trash",
+    )]
+    #[handler]
+    pub(super) async fn restore_trash(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(option) = req.parse_json::<TrashItemOption>().await else {
+            invalid_parameters(res);
+            return;
+        };
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        let item = match storage.get_trash_item(&option.id) {
+            Ok(item) => item,
+            Err(e) => {
+                anyhow_resp(e, res);
+                return;
+            }
+        };
+        depot.inject(AuditTarget::new(Some(&item.domain), Some(item.version)).detail(item.id));
+        if !check_permission(depot, res, Some(&item.domain), AdminAction::Delete) {
+            return;
+        }
+        match storage.restore_trash(&option.id) {
+            Ok(item) => res.render(Json(item)),
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/trash/purge",
+        request_body = PurgeTrashOption,
+        responses(
+            (status = 200, description = "purged items", body = Vec<TrashItem>),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "trash item does not exist", body = ApiError),
+        ),
+        tag = "trash",
+    )]
+    #[handler]
+    pub(super) async fn purge_trash(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(option) = req.parse_json::<PurgeTrashOption>().await else {
+            invalid_parameters(res);
+            return;
+        };
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        let domain = match &option.id {
+            Some(id) => match storage.get_trash_item(id) {
+                Ok(item) => Some(item.domain),
+                Err(e) => {
+                    anyhow_resp(e, res);
+                    return;
+                }
+            },
+            None => option.domain.clone(),
+        };
+        depot.inject(AuditTarget::new(domain.as_deref(), None));
+        if !check_permission(depot, res, domain.as_deref(), AdminAction::Delete) {
+            return;
+        }
+        match storage.purge_trash(domain.as_deref(), option.id.as_deref(), None) {
+            Ok(items) => {
+                let ids: Vec<&str> = items.iter().map(|x| x.id.as_str()).collect();
+                depot.inject(
+                    AuditTarget::new(domain.as_deref(), None)
+                        .detail(format!("purged: [{}]", ids.join(", "))),
+                );
+                res.render(Json(items));
+            }
+            Err(e) => anyhow_resp(e, res),
         }
     }
 
     //TODO: when delete and revoke occur currently. would have problems.
+    #[utoipa::path(
+        post,
+        path = "/files/revoke_version",
+        request_body = DomainWithOptVersionOption,
+        responses(
+            (status = 200, description = "version revoked"),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "upload",
+    )]
     #[handler]
     pub(super) async fn revoke_version(req: &mut Request, res: &mut Response, depot: &mut Depot) {
-        let domain_storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
-        if let Ok(query) = req.parse_json::<DomainWithVersionOption>().await {
-            let DomainWithVersionOption { domain, version } = query;
+        let domain_storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        if let Ok(query) = req.parse_json::<DomainWithOptVersionOption>().await {
+            let DomainWithOptVersionOption {
+                domain,
+                version,
+                tag,
+                release_at,
+                ..
+            } = query;
+            depot.inject(AuditTarget::new(Some(&domain), version));
+            if !check_permission(depot, res, Some(&domain), AdminAction::Release) {
+                return;
+            }
+            if release_at.is_some() {
+                error_resp(
+                    ApiError::new(
+                        ErrorCode::InvalidParameters,
+                        "release_at is not supported in revoke",
+                    ),
+                    res,
+                );
+                return;
+            }
+            let version = match domain_storage.resolve_version(&domain, version, tag.as_deref()) {
+                Ok(Some(version)) => version,
+                Ok(None) => {
+                    invalid_parameters(res);
+                    return;
+                }
+                Err(e) => {
+                    anyhow_resp(e, res);
+                    return;
+                }
+            };
+            let mut target = AuditTarget::new(Some(&domain), Some(version));
+            if let Some(tag) = &tag {
+                target = target.detail(format!("tag: {tag}"));
+            }
+            depot.inject(target);
             match domain_storage.get_domain_info_by_domain(&domain) {
-                Some(info) => {
+                Some(info)
                     if info
                         .current_version
                         .is_some_and(|current_version| current_version > version)
-                        && info.versions.contains(&version)
+                        && info.versions.contains(&version) =>
+                {
+                    let actor = depot.obtain::<AdminIdentity>().unwrap().name.clone();
+                    match domain_storage
+                        .release_version(domain, Some(version), &actor, ReleaseAction::Revoke)
+                        .await
                     {
-                        match domain_storage
-                            .upload_domain_with_version(domain, Some(version))
-                            .await
-                        {
-                            Ok(_) => {}
-                            Err(e) => {
-                                bad_resp(e.to_string(), res);
-                            }
+                        Ok(_) => {}
+                        Err(e) => {
+                            anyhow_resp(e, res);
                         }
-                    } else {
-                        res.status_code(StatusCode::NOT_FOUND);
                     }
                 }
-                None => {
-                    res.status_code(StatusCode::NOT_FOUND);
+                _ => {
+                    error_resp(
+                        ApiError::new(
+                            ErrorCode::VersionNotFound,
+                            format!(
+                                "domain:{domain}, version:{version} can not be revoked, it should be older than serving version"
+                            ),
+                        ),
+                        res,
+                    );
                 }
             };
         } else {
-            res.status_code(StatusCode::BAD_REQUEST);
+            invalid_parameters(res);
         }
     }
+
+    #[utoipa::path(
+        get,
+        path = "/history",
+        params(ReleaseHistoryQuery),
+        responses(
+            (status = 200, description = "the latest releases of domain, order by time", body = Vec<ReleaseRecord>),
+            (status = 400, description = "invalid parameters", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "release",
+    )]
+    #[handler]
+    pub(super) async fn get_release_history(
+        req: &mut Request,
+        res: &mut Response,
+        depot: &mut Depot,
+    ) {
+        let Ok(query) = req.parse_queries::<ReleaseHistoryQuery>() else {
+            invalid_parameters(res);
+            return;
+        };
+        if !check_permission(depot, res, Some(&query.domain), AdminAction::Read) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+        match storage.get_release_history(&query.domain, Some(limit)) {
+            Ok(records) => res.render(Json(records)),
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/rollback",
+        request_body = RollbackOption,
+        responses(
+            (status = 200, description = "release message", body = String, content_type = "text/plain"),
+            (status = 400, description = "invalid parameters, or no previous release", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "previous version does not exist", body = ApiError),
+        ),
+        tag = "release",
+    )]
+    #[handler]
+    pub(super) async fn rollback(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(RollbackOption { domain }) = req.parse_json::<RollbackOption>().await else {
+            invalid_parameters(res);
+            return;
+        };
+        depot.inject(AuditTarget::new(Some(&domain), None));
+        if !check_permission(depot, res, Some(&domain), AdminAction::Release) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        let actor = depot.obtain::<AdminIdentity>().unwrap().name.clone();
+        let result = match storage.get_rollback_version(&domain) {
+            Ok(version) => {
+                depot.inject(AuditTarget::new(Some(&domain), Some(version)));
+                storage
+                    .release_version(
+                        domain.clone(),
+                        Some(version),
+                        &actor,
+                        ReleaseAction::Rollback,
+                    )
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(version) => {
+                let text =
+                    format!("domain:{domain} static web version has rolled back to {version}");
+                tracing::info!("{}", &text);
+                res.render(text);
+            }
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/release/batch",
+        request_body = BatchReleaseOption,
+        responses(
+            (status = 200, description = "released versions, all of them are switched together", body = Vec<DomainVersion>),
+            (status = 400, description = "invalid parameters or status, nothing is released", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version or tag does not exist, nothing is released", body = ApiError),
+        ),
+        tag = "release",
+    )]
+    #[handler]
+    pub(super) async fn batch_release(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(option) = req.parse_json::<BatchReleaseOption>().await else {
+            invalid_parameters(res);
+            return;
+        };
+        let domains: Vec<&str> = option.releases.iter().map(|x| x.domain.as_str()).collect();
+        depot.inject(
+            AuditTarget::new(None, None).detail(format!("domains: [{}]", domains.join(", "))),
+        );
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        let host_alias = depot
+            .obtain::<Arc<HashMap<String, String>>>()
+            .unwrap()
+            .clone();
+        let mut releases = Vec::new();
+        for item in &option.releases {
+            if !check_permission(depot, res, Some(&item.domain), AdminAction::Release)
+                || super::AdminServer::check_alias(&item.domain, host_alias.clone(), res)
+            {
+                return;
+            }
+            if item.release_at.is_some() {
+                error_resp(
+                    ApiError::new(
+                        ErrorCode::InvalidParameters,
+                        "release_at is not supported in batch release",
+                    ),
+                    res,
+                );
+                return;
+            }
+            match storage.resolve_version(&item.domain, item.version, item.tag.as_deref()) {
+                Ok(version) => releases.push((item.domain.clone(), version)),
+                Err(e) => {
+                    anyhow_resp(e, res);
+                    return;
+                }
+            }
+        }
+        let actor = depot.obtain::<AdminIdentity>().unwrap().name.clone();
+        // files of all versions are read to memory
+        let result =
+            tokio::task::spawn_blocking(move || storage.release_versions(&releases, &actor))
+                .await
+                .unwrap_or_else(|e| Err(anyhow::anyhow!("batch release task failure: {e}")));
+        match result {
+            Ok(released) => {
+                let released_text: Vec<String> = released
+                    .iter()
+                    .map(|x| format!("{}:{}", x.domain, x.version))
+                    .collect();
+                let detail = format!("releases: [{}]", released_text.join(", "));
+                tracing::info!("batch release finish, {detail}");
+                depot.inject(AuditTarget::new(None, None).detail(detail));
+                res.render(Json(released));
+            }
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/tags/set",
+        request_body = SetTagOption,
+        responses(
+            (status = 200, description = "version of the tag", body = DomainVersion),
+            (status = 400, description = "invalid parameters, or tag is set to another version", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version or `from` does not exist", body = ApiError),
+        ),
+        tag = "tag",
+    )]
+    #[handler]
+    pub(super) async fn set_tag(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        change_tag(req, res, depot, false).await
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/tags/delete",
+        request_body = DeleteTagOption,
+        responses(
+            (status = 200, description = "tag deleted"),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "tag does not exist", body = ApiError),
+        ),
+        tag = "tag",
+    )]
+    #[handler]
+    pub(super) async fn delete_tag(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        remove_tag(req, res, depot, false).await
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/channels/set",
+        request_body = SetTagOption,
+        responses(
+            (status = 200, description = "version of the channel", body = DomainVersion),
+            (status = 400, description = "invalid parameters, or it's a tag", body = ApiError),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "version or `from` does not exist", body = ApiError),
+        ),
+        tag = "tag",
+    )]
+    #[handler]
+    pub(super) async fn set_channel(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        change_tag(req, res, depot, true).await
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/channels/delete",
+        request_body = DeleteTagOption,
+        responses(
+            (status = 200, description = "channel deleted"),
+            (status = 403, description = "no permission", body = ApiError),
+            (status = 404, description = "channel does not exist", body = ApiError),
+        ),
+        tag = "tag",
+    )]
+    #[handler]
+    pub(super) async fn delete_channel(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        remove_tag(req, res, depot, true).await
+    }
+
+    async fn change_tag(req: &mut Request, res: &mut Response, depot: &mut Depot, channel: bool) {
+        let Ok(option) = req.parse_json::<SetTagOption>().await else {
+            invalid_parameters(res);
+            return;
+        };
+        let kind = if channel { "channel" } else { "tag" };
+        depot.inject(
+            AuditTarget::new(Some(&option.domain), option.version)
+                .detail(format!("{kind}: {}", option.name)),
+        );
+        if !check_permission(depot, res, Some(&option.domain), AdminAction::Release) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        match storage.set_tag(
+            &option.domain,
+            &option.name,
+            option.version,
+            option.from.as_deref(),
+            channel,
+        ) {
+            Ok(version) => {
+                depot.inject(
+                    AuditTarget::new(Some(&option.domain), Some(version))
+                        .detail(format!("{kind}: {}", option.name)),
+                );
+                res.render(Json(DomainVersion {
+                    domain: option.domain,
+                    version,
+                }));
+            }
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    async fn remove_tag(req: &mut Request, res: &mut Response, depot: &mut Depot, channel: bool) {
+        let Ok(DeleteTagOption { domain, name }) = req.parse_json::<DeleteTagOption>().await else {
+            invalid_parameters(res);
+            return;
+        };
+        let kind = if channel { "channel" } else { "tag" };
+        depot.inject(AuditTarget::new(Some(&domain), None).detail(format!("{kind}: {name}")));
+        if !check_permission(depot, res, Some(&domain), AdminAction::Release) {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap().clone();
+        match storage.delete_tag(&domain, &name, channel) {
+            Ok(version) => {
+                depot.inject(
+                    AuditTarget::new(Some(&domain), Some(version))
+                        .detail(format!("{kind}: {name}")),
+                );
+            }
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/metrics",
+        responses((status = 200, description = "prometheus metrics", body = String, content_type = "text/plain")),
+        tag = "monitor",
+    )]
+    #[handler]
+    pub(super) async fn get_metrics(res: &mut Response, depot: &mut Depot) {
+        if !check_permission(depot, res, None, AdminAction::Read) {
+            return;
+        }
+        let metrics = depot.obtain::<Arc<HttpMetrics>>().unwrap();
+        res.render(metrics.render());
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/audit",
+        params(AuditQuery),
+        responses(
+            (status = 200, description = "audit records", body = Vec<AuditRecord>),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "monitor",
+    )]
+    #[handler]
+    pub(super) async fn get_audit_log(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(query) = req.parse_queries::<AuditQuery>() else {
+            invalid_parameters(res);
+            return;
+        };
+        // without domain, records are filtered by permission instead of rejecting
+        if query.domain.is_some()
+            && !check_permission(depot, res, query.domain.as_deref(), AdminAction::Read)
+        {
+            return;
+        }
+        let audit_log = depot.obtain::<Arc<AuditLog>>().unwrap();
+        let identity = depot.obtain::<AdminIdentity>().unwrap();
+        // only return records of domains allowed to read
+        let result = audit_log.query(&query, |record| match &record.domain {
+            Some(domain) => identity.is_allowed(domain, AdminAction::Read),
+            None => identity.is_allowed_all(AdminAction::Read),
+        });
+        match result {
+            Ok(records) => res.render(Json(records)),
+            Err(e) => anyhow_resp(e, res),
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/events",
+        params(EventsQuery),
+        responses(
+            (status = 200, description = "server-sent events, data is json of event", body = DomainEvent, content_type = "text/event-stream"),
+        (status = 400, description = "invalid parameters or status", body = ApiError),
+        (status = 403, description = "no permission", body = ApiError),
+        ),
+        tag = "monitor",
+    )]
+    #[handler]
+    pub(super) async fn get_events(req: &mut Request, res: &mut Response, depot: &mut Depot) {
+        let Ok(query) = req.parse_queries::<EventsQuery>() else {
+            invalid_parameters(res);
+            return;
+        };
+        // without domain, events are filtered by permission instead of rejecting
+        if query.domain.is_some()
+            && !check_permission(depot, res, query.domain.as_deref(), AdminAction::Read)
+        {
+            return;
+        }
+        let storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
+        let identity = depot.obtain::<AdminIdentity>().unwrap().clone();
+        let last_event_id = query
+            .last_event_id
+            .or_else(|| req.header::<u64>("Last-Event-ID"));
+        let (replay, receiver) = match last_event_id {
+            Some(last_event_id) => storage.events().subscribe_since(last_event_id),
+            None => (vec![], storage.events().subscribe()),
+        };
+        let live = stream::unfold(receiver, |mut receiver| async move {
+            // end the stream if lagged, client could replay missed events after reconnecting
+            receiver.recv().await.ok().map(|event| (event, receiver))
+        });
+        let events = if query.follow.unwrap_or(true) {
+            stream::iter(replay).chain(live).boxed()
+        } else {
+            stream::iter(replay).boxed()
+        };
+        let domain = query.domain;
+        let events = events
+            .filter(move |event| {
+                let domains = event.data.domains();
+                let allowed = match (domains.is_empty(), &domain) {
+                    // event of multiple domains is hidden if any of them is not allowed
+                    (false, Some(domain)) => {
+                        domains.iter().any(|x| is_same_or_sub_domain(domain, x))
+                            && domains
+                                .iter()
+                                .all(|x| identity.is_allowed(x, AdminAction::Read))
+                    }
+                    (false, None) => domains
+                        .iter()
+                        .all(|x| identity.is_allowed(x, AdminAction::Read)),
+                    (true, Some(_)) => false,
+                    (true, None) => identity.is_allowed_all(AdminAction::Read),
+                };
+                future::ready(allowed)
+            })
+            .map(|event| {
+                SseEvent::default()
+                    .id(event.id.to_string())
+                    .name(event.data.kind().as_str())
+                    .json(&event)
+            });
+        SseKeepAlive::new(events).stream(res);
+    }
+}
+
+pub(crate) fn error_resp(error: ApiError, res: &mut Response) {
+    res.status_code(StatusCode::from_u16(error.code.status()).unwrap_or(StatusCode::BAD_REQUEST));
+    res.render(Json(error));
 }
 
-fn bad_resp(text: String, res: &mut Response) {
-    res.status_code(StatusCode::BAD_REQUEST);
-    res.render(text);
+// error from storage carries ApiError if it's caused by request, others are internal error.
+fn anyhow_resp(error: anyhow::Error, res: &mut Response) {
+    match error.downcast::<ApiError>() {
+        Ok(error) => error_resp(error, res),
+        Err(error) => error_resp(ApiError::new(ErrorCode::Internal, error.to_string()), res),
+    }
+}
+
+fn invalid_parameters(res: &mut Response) {
+    error_resp(
+        ApiError::new(ErrorCode::InvalidParameters, "invalid parameters"),
+        res,
+    );
 }
 
 use crate::admin_server::service::_remove_domain_version;
 
 fn build_async_job(
     domain_storage: Arc<DomainStorage>,
-    max_reserve: Option<u32>,
+    default_retention: RetentionPolicy,
+    domain_retention: Arc<HashMap<String, RetentionPolicy>>,
     cron: &str,
 ) -> anyhow::Result<Task> {
     let domain_storage = domain_storage.clone();
     let body = move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async {
-            _remove_domain_version(None, max_reserve, &domain_storage);
+            let deleted = _remove_domain_version(
+                None,
+                None,
+                default_retention,
+                &domain_retention,
+                false,
+                &domain_storage,
+            );
+            domain_storage.events().emit(DomainEventData::CronRun {
+                deleted: deleted.len(),
+            });
         });
     };
     let builder = TaskBuilder::default()
@@ -415,13 +2009,161 @@ fn build_async_job(
         .spawn_routine(body)?;
     Ok(builder)
 }
+fn build_purge_trash_job(
+    domain_storage: Arc<DomainStorage>,
+    retention_days: u32,
+    cron: &str,
+) -> anyhow::Result<Task> {
+    let body = move || {
+        let before = chrono::Utc::now() - chrono::TimeDelta::days(retention_days as i64);
+        match domain_storage.purge_trash(None, None, Some(before)) {
+            Ok(purged) if !purged.is_empty() => {
+                tracing::info!("purge {} expired items of trash", purged.len());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("purge trash error: {e}"),
+        }
+    };
+    let builder = TaskBuilder::default()
+        .set_frequency_repeated_by_cron_str(cron)
+        .set_task_id(3)
+        .set_maximum_parallel_runnable_num(1)
+        .spawn_routine(body)?;
+    Ok(builder)
+}
+
+// the release is kept in file first, then waits in timer
+fn schedule_release(
+    domain_storage: Arc<DomainStorage>,
+    delay_timer: &DelayTimer,
+    domain: &str,
+    version: u32,
+    release_at: chrono::DateTime<chrono::Utc>,
+    actor: &str,
+) -> anyhow::Result<ScheduledRelease> {
+    let item = domain_storage.add_scheduled_release(domain, version, release_at, actor)?;
+    let result = build_scheduled_release_job(domain_storage.clone(), &item)
+        .and_then(|task| Ok(delay_timer.add_task(task)?));
+    if let Err(e) = result {
+        let _ = domain_storage.remove_scheduled_release(item.id);
+        return Err(e);
+    }
+    Ok(item)
+}
+
+// task id of scheduled release, keep it away from cron jobs
+const SCHEDULED_RELEASE_TASK_ID_OFFSET: u64 = 100;
+
+fn build_scheduled_release_job(
+    domain_storage: Arc<DomainStorage>,
+    item: &ScheduledRelease,
+) -> anyhow::Result<Task> {
+    let id = item.id;
+    let delay = (item.release_at - chrono::Utc::now())
+        .num_milliseconds()
+        .max(0) as u64;
+    let body = move || {
+        let domain_storage = domain_storage.clone();
+        async move {
+            // it's canceled if not found
+            let Ok(item) = domain_storage.remove_scheduled_release(id) else {
+                return;
+            };
+            match domain_storage
+                .release_version(
+                    item.domain.clone(),
+                    Some(item.version),
+                    &item.actor,
+                    ReleaseAction::Release,
+                )
+                .await
+            {
+                Ok(version) => tracing::info!(
+                    "scheduled release {id}: domain:{} static web version has changed to {version}",
+                    item.domain
+                ),
+                Err(e) => tracing::error!(
+                    "scheduled release {id}: release domain:{}, version:{} failure {e:?}",
+                    item.domain,
+                    item.version
+                ),
+            }
+        }
+    };
+    let builder = TaskBuilder::default()
+        .set_frequency_once_by_seconds(delay.div_ceil(1000).max(1))
+        .set_task_id(SCHEDULED_RELEASE_TASK_ID_OFFSET + id)
+        .set_maximum_parallel_runnable_num(1)
+        .spawn_async_routine(body)?;
+    Ok(builder)
+}
+
+fn build_expire_job(
+    domain_storage: Arc<DomainStorage>,
+    idle_timeout: u64,
+    cron: &str,
+) -> anyhow::Result<Task> {
+    let idle_timeout = chrono::TimeDelta::seconds(idle_timeout as i64);
+    let body = move || {
+        let expired = domain_storage.expire_upload_sessions(idle_timeout);
+        if expired > 0 {
+            tracing::info!("expire {expired} upload sessions");
+        }
+    };
+    let builder = TaskBuilder::default()
+        .set_frequency_repeated_by_cron_str(cron)
+        .set_task_id(2)
+        .set_maximum_parallel_runnable_num(1)
+        .spawn_routine(body)?;
+    Ok(builder)
+}
 // TODO: the code structure is not friendly with Unit Test, need refactor it.
 #[cfg(test)]
 mod test {
+    use super::{AdminApiDoc, AdminServer};
     use chrono::prelude::*;
     use delay_timer::entity::DelayTimerBuilder;
     use delay_timer::prelude::TaskBuilder;
+    use salvo::Router;
+    use std::collections::BTreeSet;
     use std::time::Duration;
+    use utoipa::OpenApi;
+
+    // (method, path) of routes, like: ("get", "/status")
+    fn collect_routes(router: &Router, prefix: &str, routes: &mut BTreeSet<(String, String)>) {
+        let mut path = prefix.to_string();
+        let mut method = None;
+        for filter in router.filters() {
+            let filter = format!("{filter:?}");
+            if let Some(p) = filter.strip_prefix("path:") {
+                path = format!("{path}/{p}");
+            } else if let Some(m) = filter.strip_prefix("method:") {
+                method = Some(m.to_lowercase());
+            }
+        }
+        if let Some(method) = method {
+            routes.insert((method, path.clone()));
+        }
+        for router in router.routers() {
+            collect_routes(router, &path, routes);
+        }
+    }
+
+    #[test]
+    fn openapi_matches_routes() {
+        let mut routes = BTreeSet::new();
+        collect_routes(&AdminServer::api_routes(), "", &mut routes);
+
+        let spec = serde_json::to_value(AdminApiDoc::openapi()).unwrap();
+        let mut documented = BTreeSet::new();
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for method in item.as_object().unwrap().keys() {
+                documented.insert((method.clone(), path.clone()));
+            }
+        }
+        assert!(!routes.is_empty());
+        assert_eq!(routes, documented);
+    }
 
     #[tokio::test]
     async fn delay_is_ok() {
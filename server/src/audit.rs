@@ -0,0 +1,250 @@
+use crate::auth::AdminIdentity;
+use crate::config::is_same_or_sub_domain;
+use anyhow::Context;
+use chrono::Utc;
+use entity::error::ApiError;
+use entity::request::AuditQuery;
+use entity::storage::{AuditAction, AuditRecord, AuditResult};
+use salvo::http::ResBody;
+use salvo::prelude::*;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{error, warn};
+
+pub(crate) const AUDIT_LOG_FILE_NAME: &str = ".SPA-Audit.jsonl";
+const DEFAULT_QUERY_LIMIT: usize = 100;
+
+// append only json lines file
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn new<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("can not open audit log: {}", path.display()))?;
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn append(&self, record: &AuditRecord) {
+        let result = serde_json::to_string(record)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut line| {
+                line.push('\n');
+                let mut file = self.file.lock().unwrap();
+                file.write_all(line.as_bytes())?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            error!("append audit log failure: {e:?}, record: {record:?}");
+        }
+    }
+
+    // return the latest records matching query, order by time
+    pub fn query(
+        &self,
+        query: &AuditQuery,
+        filter: impl Fn(&AuditRecord) -> bool,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+        let file = File::open(&self.path)?;
+        let mut records = VecDeque::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let record = match serde_json::from_str::<AuditRecord>(&line) {
+                Ok(record) => record,
+                Err(e) => {
+                    warn!("skip invalid audit log line: {e}");
+                    continue;
+                }
+            };
+            if is_match(query, &record) && filter(&record) {
+                if records.len() == limit {
+                    records.pop_front();
+                }
+                records.push_back(record);
+            }
+        }
+        Ok(records.into())
+    }
+}
+
+fn is_match(query: &AuditQuery, record: &AuditRecord) -> bool {
+    // domain also matches its sub path
+    query.domain.as_ref().is_none_or(|domain| {
+        record
+            .domain
+            .as_ref()
+            .is_some_and(|x| is_same_or_sub_domain(domain, x))
+    }) && query.name.as_ref().is_none_or(|name| &record.name == name)
+        && query.action.is_none_or(|action| record.action == action)
+        && query.result.is_none_or(|result| record.result == result)
+        && query.since.is_none_or(|since| record.time >= since)
+        && query.until.is_none_or(|until| record.time < until)
+}
+
+// what admin api operates on, handler injects it to depot.
+#[derive(Debug, Default)]
+pub struct AuditTarget {
+    pub domain: Option<String>,
+    pub version: Option<u32>,
+    pub detail: Option<String>,
+}
+
+impl AuditTarget {
+    pub fn new(domain: Option<&str>, version: Option<u32>) -> Self {
+        AuditTarget {
+            domain: domain.map(|x| x.to_string()),
+            version,
+            detail: None,
+        }
+    }
+
+    pub fn detail(mut self, detail: String) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+}
+
+// record the result of state-changing api after handler.
+pub struct Audit(pub AuditAction);
+
+#[async_trait]
+impl Handler for Audit {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        ctrl.call_next(req, depot, res).await;
+        let Ok(audit_log) = depot.obtain::<Arc<AuditLog>>().cloned() else {
+            return;
+        };
+        let status = res.status_code.unwrap_or(StatusCode::OK);
+        let (result, message) = if status.is_success() {
+            (AuditResult::Success, None)
+        } else {
+            let message = match &res.body {
+                ResBody::Once(bytes) => serde_json::from_slice::<ApiError>(bytes)
+                    .map(|x| x.message)
+                    .unwrap_or_else(|_| status.to_string()),
+                _ => status.to_string(),
+            };
+            (AuditResult::Failure, Some(message))
+        };
+        let target = depot.scrape::<AuditTarget>().unwrap_or_default();
+        let record = AuditRecord {
+            time: Utc::now(),
+            name: depot
+                .obtain::<AdminIdentity>()
+                .map(|x| x.name.clone())
+                .unwrap_or_default(),
+            ip: req
+                .remote_addr()
+                .clone()
+                .into_std()
+                .map(|x| x.ip().to_string()),
+            action: self.0,
+            domain: target.domain,
+            version: target.version,
+            detail: target.detail,
+            result,
+            message,
+        };
+        audit_log.append(&record);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::audit::AuditLog;
+    use chrono::{Duration, Utc};
+    use entity::request::AuditQuery;
+    use entity::storage::{AuditAction, AuditRecord, AuditResult};
+    use std::env;
+    use std::fs;
+
+    fn record(domain: &str, version: u32, action: AuditAction) -> AuditRecord {
+        AuditRecord {
+            time: Utc::now(),
+            name: "ci".to_string(),
+            ip: Some("127.0.0.1".to_string()),
+            action,
+            domain: Some(domain.to_string()),
+            version: Some(version),
+            detail: None,
+            result: AuditResult::Success,
+            message: None,
+        }
+    }
+
+    #[test]
+    fn audit_log_query() {
+        let path = env::temp_dir().join(format!("spa-server-audit-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let audit_log = AuditLog::new(&path).unwrap();
+        audit_log.append(&record("www.a.com", 1, AuditAction::UpdateVersion));
+        audit_log.append(&record("www.a.com/sub", 2, AuditAction::UpdateVersion));
+        audit_log.append(&record("www.b.com", 1, AuditAction::RevokeVersion));
+        audit_log.append(&record("www.a.com", 3, AuditAction::UpdateVersion));
+
+        let query = |query: AuditQuery| {
+            audit_log
+                .query(&query, |_| true)
+                .unwrap()
+                .into_iter()
+                .map(|x| (x.domain.unwrap(), x.version.unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let all = query(AuditQuery::default());
+        assert_eq!(all.len(), 4);
+        assert_eq!(
+            query(AuditQuery {
+                domain: Some("www.a.com".to_string()),
+                limit: Some(2),
+                ..Default::default()
+            }),
+            vec![
+                ("www.a.com/sub".to_string(), 2),
+                ("www.a.com".to_string(), 3)
+            ]
+        );
+        assert_eq!(
+            query(AuditQuery {
+                action: Some(AuditAction::RevokeVersion),
+                ..Default::default()
+            }),
+            vec![("www.b.com".to_string(), 1)]
+        );
+        assert!(
+            query(AuditQuery {
+                since: Some(Utc::now() + Duration::seconds(10)),
+                ..Default::default()
+            })
+            .is_empty()
+        );
+        let filtered = audit_log
+            .query(&AuditQuery::default(), |x| {
+                x.domain.as_deref() != Some("www.b.com")
+            })
+            .unwrap();
+        assert_eq!(filtered.len(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,536 @@
+use crate::admin_server::error_resp;
+use crate::config::{
+    AdminAction, AdminConfig, AdminTokenConfig, AuthLockoutConfig, ClientCertConfig,
+    get_host_path_from_domain,
+};
+use crate::domain_storage::is_valid_domain;
+use crate::jwt::JwtValidator;
+use crate::tls::PeerCertificates;
+use anyhow::{Context, bail};
+use dashmap::DashMap;
+use entity::error::{ApiError, ErrorCode};
+use regex::Regex;
+use salvo::prelude::*;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use tracing::{debug, warn};
+
+// who is calling admin api, it's injected to depot after auth.
+#[derive(Debug, Clone)]
+pub struct AdminIdentity {
+    pub name: String,
+    permission: Arc<Permission>,
+}
+
+impl AdminIdentity {
+    pub(crate) fn new(name: String, permission: Arc<Permission>) -> Self {
+        AdminIdentity { name, permission }
+    }
+
+    pub fn is_allowed(&self, domain: &str, action: AdminAction) -> bool {
+        self.permission.actions.contains(&action) && self.permission.match_domain(domain)
+    }
+
+    // action without domain, like delete all domains or get metrics
+    pub fn is_allowed_all(&self, action: AdminAction) -> bool {
+        self.permission.actions.contains(&action) && self.permission.all_domains
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Permission {
+    all_domains: bool,
+    domains: Vec<DomainPattern>,
+    actions: HashSet<AdminAction>,
+}
+
+// host and sub path are matched separately, `*` doesn't match across `/`.
+#[derive(Debug)]
+struct DomainPattern {
+    host: Regex,
+    path: Option<Regex>,
+}
+
+impl DomainPattern {
+    fn new(pattern: &str) -> Result<Self, regex::Error> {
+        let (host, path) = get_host_path_from_domain(pattern);
+        Ok(DomainPattern {
+            host: wildcard_regex(host, "[^/]*")?,
+            path: if path.is_empty() {
+                None
+            } else {
+                Some(wildcard_regex(path, "[^/]*")?)
+            },
+        })
+    }
+
+    // pattern of host also matches its sub path: www.example.com matches www.example.com/a
+    fn is_match(&self, domain: &str) -> bool {
+        let (host, path) = get_host_path_from_domain(domain);
+        self.host.is_match(host) && self.path.as_ref().is_none_or(|x| x.is_match(path))
+    }
+}
+
+impl Permission {
+    fn full() -> Self {
+        Permission {
+            all_domains: true,
+            domains: Vec::new(),
+            actions: AdminAction::ALL.into_iter().collect(),
+        }
+    }
+
+    pub(crate) fn new(domains: &[String], actions: &[AdminAction]) -> anyhow::Result<Self> {
+        let all_domains = domains.iter().any(|x| x == "*");
+        let domains = domains
+            .iter()
+            .map(|pattern| {
+                DomainPattern::new(pattern)
+                    .with_context(|| format!("invalid domain pattern: {pattern}"))
+            })
+            .collect::<anyhow::Result<Vec<DomainPattern>>>()?;
+        Ok(Permission {
+            all_domains,
+            domains,
+            actions: actions.iter().copied().collect(),
+        })
+    }
+
+    fn match_domain(&self, domain: &str) -> bool {
+        self.all_domains || self.domains.iter().any(|pattern| pattern.is_match(domain))
+    }
+}
+
+// `*` matches any characters, others match literally.
+pub(crate) fn pattern_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    wildcard_regex(pattern, ".*")
+}
+
+fn wildcard_regex(pattern: &str, wildcard: &str) -> Result<Regex, regex::Error> {
+    let regex = pattern
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(wildcard);
+    Regex::new(&format!("^{regex}$"))
+}
+
+struct TokenEntry {
+    name: String,
+    sha256: [u8; 32],
+    permission: Arc<Permission>,
+}
+
+impl TokenEntry {
+    fn new(conf: &AdminTokenConfig) -> anyhow::Result<Self> {
+        let sha256 = hex::decode(&conf.token_sha256)
+            .with_context(|| format!("admin token {} token_sha256 is not hex", conf.name))?;
+        let Ok(sha256) = sha256.try_into() else {
+            bail!("admin token {} token_sha256 is not sha256", conf.name)
+        };
+        Ok(TokenEntry {
+            name: conf.name.clone(),
+            sha256,
+            permission: Arc::new(Permission::new(&conf.domains, &conf.actions)?),
+        })
+    }
+}
+
+struct ClientCertEntry {
+    common_name: Regex,
+    permission: Arc<Permission>,
+}
+
+impl ClientCertEntry {
+    fn new(conf: &ClientCertConfig) -> anyhow::Result<Self> {
+        Ok(ClientCertEntry {
+            common_name: pattern_regex(&conf.common_name).with_context(|| {
+                format!(
+                    "invalid client cert common name pattern: {}",
+                    conf.common_name
+                )
+            })?,
+            permission: Arc::new(Permission::new(&conf.domains, &conf.actions)?),
+        })
+    }
+}
+
+pub fn sha256_hex(text: &str) -> String {
+    hex::encode(Sha256::digest(text.as_bytes()))
+}
+
+struct AuthFailure {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+// lock client ip after too many continuous auth failures.
+struct AuthLockout {
+    config: AuthLockoutConfig,
+    failures: DashMap<IpAddr, AuthFailure>,
+}
+
+impl AuthLockout {
+    fn new(config: AuthLockoutConfig) -> Self {
+        AuthLockout {
+            config,
+            failures: DashMap::new(),
+        }
+    }
+
+    fn lockout_duration(&self) -> Duration {
+        Duration::from_secs(self.config.lockout_seconds)
+    }
+
+    // return remaining lock time
+    fn locked(&self, ip: IpAddr, now: Instant) -> Option<Duration> {
+        self.failures
+            .get(&ip)
+            .and_then(|x| x.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+    }
+
+    fn record_failure(&self, ip: IpAddr, now: Instant) -> u32 {
+        let lockout_duration = self.lockout_duration();
+        if self.failures.len() > 1024 {
+            self.failures
+                .retain(|_, x| now.saturating_duration_since(x.last_failure) < lockout_duration);
+        }
+        let mut failure = self.failures.entry(ip).or_insert(AuthFailure {
+            count: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        // failures long ago are forgotten
+        if now.saturating_duration_since(failure.last_failure) >= lockout_duration {
+            failure.count = 0;
+            failure.locked_until = None;
+        }
+        failure.count += 1;
+        failure.last_failure = now;
+        if failure.count >= self.config.max_failures {
+            failure.locked_until = Some(now + lockout_duration);
+            failure.count = 0;
+            warn!(
+                "admin auth: lock {ip} for {}s after {} continuous failures",
+                self.config.lockout_seconds, self.config.max_failures
+            );
+            self.config.max_failures
+        } else {
+            failure.count
+        }
+    }
+
+    fn record_success(&self, ip: IpAddr) {
+        self.failures.remove(&ip);
+    }
+}
+
+pub struct BearerValidator {
+    // plaintext admin_config.token, it has full permission.
+    token: Option<String>,
+    tokens: Vec<TokenEntry>,
+    full_permission: Arc<Permission>,
+    jwt: Option<JwtValidator>,
+    client_certs: Vec<ClientCertEntry>,
+    peer_certificates: PeerCertificates,
+    lockout: AuthLockout,
+}
+
+impl BearerValidator {
+    pub fn new(conf: &AdminConfig) -> anyhow::Result<Self> {
+        let tokens = conf
+            .tokens
+            .iter()
+            .map(TokenEntry::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let client_certs = conf
+            .tls
+            .iter()
+            .flat_map(|tls| tls.client_certs.iter())
+            .map(ClientCertEntry::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(BearerValidator {
+            token: conf.token.clone(),
+            tokens,
+            full_permission: Arc::new(Permission::full()),
+            jwt: conf.jwt.as_ref().map(JwtValidator::new).transpose()?,
+            client_certs,
+            peer_certificates: PeerCertificates::default(),
+            lockout: AuthLockout::new(conf.auth_lockout.clone()),
+        })
+    }
+
+    fn validate(&self, value: &[u8]) -> Option<AdminIdentity> {
+        let token = value.strip_prefix(b"Bearer ")?;
+        if let Some(admin_token) = &self.token
+            && bool::from(token.ct_eq(admin_token.as_bytes()))
+        {
+            return Some(AdminIdentity {
+                name: "admin".to_string(),
+                permission: self.full_permission.clone(),
+            });
+        }
+        let sha256: [u8; 32] = Sha256::digest(token).into();
+        self.tokens
+            .iter()
+            .find(|x| bool::from(x.sha256.ct_eq(&sha256)))
+            .map(|x| AdminIdentity {
+                name: x.name.clone(),
+                permission: x.permission.clone(),
+            })
+    }
+
+    // used by tls acceptor to record client certificates
+    pub fn peer_certificates(&self) -> PeerCertificates {
+        self.peer_certificates.clone()
+    }
+
+    fn validate_client_cert(&self, remote_addr: &SocketAddr) -> Option<AdminIdentity> {
+        if self.client_certs.is_empty() {
+            return None;
+        }
+        let common_name = self.peer_certificates.common_name(remote_addr)?;
+        self.client_certs
+            .iter()
+            .find(|x| x.common_name.is_match(&common_name))
+            .map(|x| AdminIdentity {
+                name: format!("cert:{common_name}"),
+                permission: x.permission.clone(),
+            })
+    }
+
+    async fn authenticate(&self, value: &[u8]) -> Option<AdminIdentity> {
+        if let Some(identity) = self.validate(value) {
+            return Some(identity);
+        }
+        let token = std::str::from_utf8(value.strip_prefix(b"Bearer ")?).ok()?;
+        match &self.jwt {
+            Some(jwt) => jwt.validate(token).await,
+            None => None,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for BearerValidator {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let remote_addr = req.remote_addr().clone().into_std();
+        let ip = remote_addr.map(|x| x.ip());
+        if let Some(ip) = ip
+            && let Some(wait) = self.lockout.locked(ip, Instant::now())
+        {
+            let _ = res.add_header("Retry-After", wait.as_secs().max(1), true);
+            error_resp(
+                ApiError::new(
+                    ErrorCode::TooManyRequests,
+                    "too many auth failures, please retry later",
+                )
+                .details(json!({ "retry_after": wait.as_secs().max(1) })),
+                res,
+            );
+            ctrl.skip_rest();
+            return;
+        }
+        let value = req.headers().get("Authorization");
+        let identity = match (
+            remote_addr.and_then(|x| self.validate_client_cert(&x)),
+            value,
+        ) {
+            (Some(identity), _) => Some(identity),
+            (None, Some(value)) => self.authenticate(value.as_bytes()).await,
+            (None, None) => None,
+        };
+        match identity {
+            Some(identity) => {
+                if let Some(ip) = ip {
+                    self.lockout.record_success(ip);
+                }
+                debug!("admin auth: {} request {}", identity.name, req.uri().path());
+                depot.inject(identity);
+                ctrl.call_next(req, depot, res).await;
+            }
+            None => {
+                // never log the token
+                match ip {
+                    Some(ip) => {
+                        let count = self.lockout.record_failure(ip, Instant::now());
+                        warn!(
+                            "admin auth failure from {ip}, path: {}, has_token: {}, continuous failures: {count}",
+                            req.uri().path(),
+                            value.is_some()
+                        );
+                    }
+                    None => {
+                        warn!("admin auth failure, path: {}", req.uri().path());
+                    }
+                }
+                error_resp(
+                    ApiError::new(ErrorCode::Unauthorized, "invalid or missing credentials"),
+                    res,
+                );
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
+
+// return false and response 403 if not allowed.
+pub(crate) fn check_permission(
+    depot: &Depot,
+    res: &mut Response,
+    domain: Option<&str>,
+    action: AdminAction,
+) -> bool {
+    if let Some(domain) = domain
+        && !is_valid_domain(domain)
+    {
+        error_resp(
+            ApiError::new(
+                ErrorCode::InvalidParameters,
+                format!("domain is invalid: {domain}"),
+            ),
+            res,
+        );
+        return false;
+    }
+    let allowed = depot.obtain::<AdminIdentity>().is_ok_and(|identity| {
+        domain.map_or_else(
+            || identity.is_allowed_all(action),
+            |domain| identity.is_allowed(domain, action),
+        )
+    });
+    if !allowed {
+        error_resp(
+            ApiError::new(
+                ErrorCode::Forbidden,
+                format!(
+                    "token is not allowed to {action:?} {}",
+                    domain.unwrap_or("all domains")
+                ),
+            )
+            .details(json!({ "action": action, "domain": domain })),
+            res,
+        );
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::auth::{AuthLockout, BearerValidator, sha256_hex};
+    use crate::config::{AdminAction, AdminConfig, AdminTokenConfig, AuthLockoutConfig};
+    use crate::domain_storage::is_valid_domain;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    fn admin_config() -> AdminConfig {
+        AdminConfig {
+            port: 9000,
+            addr: "127.0.0.1".to_string(),
+            token: Some("token".to_string()),
+            tokens: vec![AdminTokenConfig {
+                name: "team_a".to_string(),
+                token_sha256: sha256_hex("team_a_token"),
+                domains: vec!["*.a.com".to_string(), "www.example.com/a".to_string()],
+                actions: vec![AdminAction::Read, AdminAction::Upload],
+            }],
+            max_upload_size: 0,
+            deprecated_version_delete: None,
+            upload_session_expire: None,
+            trash: Default::default(),
+            auth_lockout: Default::default(),
+            jwt: None,
+            tls: None,
+            audit_log: None,
+            webhooks: vec![],
+            swagger_ui: false,
+            dashboard: false,
+        }
+    }
+
+    #[test]
+    fn auth_lockout() {
+        let lockout = AuthLockout::new(AuthLockoutConfig {
+            max_failures: 3,
+            lockout_seconds: 60,
+        });
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        assert_eq!(lockout.record_failure(ip, now), 1);
+        assert_eq!(lockout.record_failure(ip, now), 2);
+        assert!(lockout.locked(ip, now).is_none());
+        lockout.record_failure(ip, now);
+        assert_eq!(lockout.locked(ip, now), Some(Duration::from_secs(60)));
+        assert!(
+            lockout
+                .locked(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), now)
+                .is_none()
+        );
+        let now = now + Duration::from_secs(61);
+        assert!(lockout.locked(ip, now).is_none());
+        // failure long ago is forgotten
+        assert_eq!(lockout.record_failure(ip, now), 1);
+        assert_eq!(lockout.record_failure(ip, now + Duration::from_secs(61)), 1);
+        lockout.record_success(ip);
+        assert_eq!(lockout.record_failure(ip, now), 1);
+    }
+
+    #[test]
+    fn bearer_validator_compare() {
+        let validator = BearerValidator::new(&admin_config()).unwrap();
+        let admin = validator.validate(b"Bearer token").unwrap();
+        assert_eq!(admin.name, "admin");
+        assert!(admin.is_allowed_all(AdminAction::Delete));
+        assert!(validator.validate(b"Bearer token2").is_none());
+        assert!(validator.validate(b"Bearer tokem").is_none());
+        assert!(validator.validate(b"token").is_none());
+        assert!(validator.validate(b"").is_none());
+        assert!(validator.validate(b"Bearer ").is_none());
+    }
+
+    #[test]
+    fn scoped_token_permission() {
+        let validator = BearerValidator::new(&admin_config()).unwrap();
+        let identity = validator.validate(b"Bearer team_a_token").unwrap();
+        assert_eq!(identity.name, "team_a");
+        assert!(identity.is_allowed("www.a.com", AdminAction::Upload));
+        assert!(identity.is_allowed("www.a.com/sub", AdminAction::Read));
+        assert!(!identity.is_allowed("a.com", AdminAction::Read));
+        assert!(!identity.is_allowed("www.a.com.cn", AdminAction::Read));
+        assert!(!identity.is_allowed("www.a.com", AdminAction::Release));
+        assert!(identity.is_allowed("www.example.com/a", AdminAction::Upload));
+        assert!(!identity.is_allowed("www.example.com/b", AdminAction::Upload));
+        assert!(!identity.is_allowed("www.example.com", AdminAction::Upload));
+        assert!(!identity.is_allowed_all(AdminAction::Read));
+        // `*` of host doesn't match sub path
+        assert!(!identity.is_allowed("www.b.com/x.a.com", AdminAction::Read));
+        assert!(!identity.is_allowed("www.b.com/x.a.com/c", AdminAction::Read));
+    }
+
+    #[test]
+    fn valid_domain() {
+        assert!(is_valid_domain("www.a.com"));
+        assert!(is_valid_domain("www.a.com/sub/page"));
+        assert!(!is_valid_domain("x.a.com/../www.b.com"));
+        assert!(!is_valid_domain("x.a.com/./b"));
+        assert!(!is_valid_domain("x.a.com//b"));
+        assert!(!is_valid_domain("x.a.com/"));
+        assert!(!is_valid_domain("../x.a.com"));
+        assert!(!is_valid_domain("x.a.com.."));
+        assert!(!is_valid_domain(""));
+    }
+}
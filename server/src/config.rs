@@ -1,6 +1,11 @@
+use crate::auth::BearerValidator;
+use crate::tls::load_server_config;
+use crate::webhook::Webhook;
 use anyhow::{Context, bail};
+use entity::event::DomainEventKind;
 use salvo::http::HeaderValue;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::{env, fs};
 
 const CONFIG_PATH: &str = "config.toml";
@@ -28,6 +33,43 @@ impl Config {
         {
             bail!("domains.domain do not support sub path like 'www.example.com/abc' now")
         }
+        if config
+            .http
+            .rate_limit
+            .iter()
+            .chain(config.domains.iter().filter_map(|x| x.rate_limit.as_ref()))
+            .any(|x| x.rate == 0 || x.burst == 0)
+        {
+            bail!("rate_limit.rate and rate_limit.burst should be greater than 0")
+        }
+        if let Some(admin_config) = &config.admin_config {
+            let has_client_certs = admin_config
+                .tls
+                .as_ref()
+                .is_some_and(|tls| !tls.client_certs.is_empty());
+            if admin_config.token.is_none()
+                && admin_config.tokens.is_empty()
+                && admin_config.jwt.is_none()
+                && !has_client_certs
+            {
+                bail!(
+                    "admin_config.token, admin_config.tokens, admin_config.jwt or admin_config.tls.client_certs should be set"
+                )
+            }
+            if admin_config.auth_lockout.max_failures == 0 {
+                bail!("admin_config.auth_lockout.max_failures should be greater than 0")
+            }
+            if let Some(tls) = &admin_config.tls {
+                if !tls.client_certs.is_empty() && tls.client_ca.is_none() {
+                    bail!("admin_config.tls.client_ca should be set if client_certs is set")
+                }
+                load_server_config(tls)?;
+            }
+            BearerValidator::new(admin_config)?;
+            for webhook in &admin_config.webhooks {
+                Webhook::new(webhook)?;
+            }
+        }
         Ok(config)
     }
 
@@ -42,26 +84,234 @@ impl Config {
 pub struct AdminConfig {
     pub port: u16,
     pub addr: String,
-    pub token: String,
+    // full permission token
+    pub token: Option<String>,
+    #[serde(default)]
+    pub tokens: Vec<AdminTokenConfig>,
     #[serde(default = "default_max_upload_size")]
     pub max_upload_size: u64,
     pub deprecated_version_delete: Option<DeprecatedVersionRemove>,
+    // remove uploading version which is idle too long
+    pub upload_session_expire: Option<UploadSessionExpire>,
+    // deleted versions are moved to trash, and purged after retention days
+    #[serde(default)]
+    pub trash: TrashConfig,
+    #[serde(default)]
+    pub auth_lockout: AuthLockoutConfig,
+    pub jwt: Option<JwtConfig>,
+    pub tls: Option<AdminTlsConfig>,
+    // path of audit log file, default is ${file_dir}/.SPA-Audit.jsonl
+    pub audit_log: Option<String>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    // serve swagger ui at `/swagger-ui/`
+    #[serde(default)]
+    pub swagger_ui: bool,
+    // serve web dashboard at `/dashboard/`
+    #[serde(default)]
+    pub dashboard: bool,
 }
 
 fn default_max_upload_size() -> u64 {
     30 * 1024 * 1024
 }
 
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct AdminTokenConfig {
+    pub name: String,
+    // sha256 hex string of token
+    pub token_sha256: String,
+    // domain patterns, `*` matches any characters, like: "*.example.com"
+    #[serde(default = "default_token_domains")]
+    pub domains: Vec<String>,
+    #[serde(default = "default_token_actions")]
+    pub actions: Vec<AdminAction>,
+}
+
+fn default_token_domains() -> Vec<String> {
+    vec!["*".to_string()]
+}
+fn default_token_actions() -> Vec<AdminAction> {
+    AdminAction::ALL.to_vec()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminAction {
+    Read,
+    Upload,
+    Release,
+    Delete,
+}
+
+impl AdminAction {
+    pub const ALL: [AdminAction; 4] = [
+        AdminAction::Read,
+        AdminAction::Upload,
+        AdminAction::Release,
+        AdminAction::Delete,
+    ];
+}
+
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct AdminTlsConfig {
+    // pem file path of certificate chain and private key
+    pub cert: String,
+    pub key: String,
+    // pem file path of CA, client certificate signed by it is required if set
+    pub client_ca: Option<String>,
+    // map common name of client certificate subject to permission, token is not needed then
+    #[serde(default)]
+    pub client_certs: Vec<ClientCertConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ClientCertConfig {
+    // `*` matches any characters
+    pub common_name: String,
+    #[serde(default = "default_token_domains")]
+    pub domains: Vec<String>,
+    #[serde(default = "default_token_actions")]
+    pub actions: Vec<AdminAction>,
+}
+
+// validate JWT(like OIDC id token of CI) signed by keys of JWKS
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct JwtConfig {
+    // local file path or http(s) url
+    pub jwks: String,
+    pub issuer: String,
+    pub audience: String,
+    // seconds, min interval to fetch jwks url again when meet unknown key id
+    #[serde(default = "default_jwks_refresh_interval")]
+    pub jwks_refresh_interval: u64,
+    // seconds, clock skew allowed when check exp and nbf
+    #[serde(default = "default_jwt_leeway")]
+    pub leeway: u64,
+    // the first matched rule gives permission
+    #[serde(default)]
+    pub rules: Vec<JwtRuleConfig>,
+}
+
+fn default_jwks_refresh_interval() -> u64 {
+    300
+}
+fn default_jwt_leeway() -> u64 {
+    60
+}
+
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct JwtRuleConfig {
+    pub name: String,
+    // all claims should match, `*` matches any characters
+    #[serde(default)]
+    pub claims: BTreeMap<String, String>,
+    #[serde(default = "default_token_domains")]
+    pub domains: Vec<String>,
+    #[serde(default = "default_token_actions")]
+    pub actions: Vec<AdminAction>,
+}
+
+// post json of domain event to url
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct WebhookConfig {
+    pub url: String,
+    // sign body by HMAC-SHA256, put it in header: X-SPA-Signature-256: sha256=$hex
+    pub secret: Option<String>,
+    #[serde(default = "default_webhook_events")]
+    pub events: Vec<DomainEventKind>,
+    // domain patterns, same as admin_config.tokens
+    #[serde(default = "default_token_domains")]
+    pub domains: Vec<String>,
+    // retry when request fails or response status is not 2xx
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+    // seconds, it's doubled after each retry
+    #[serde(default = "default_webhook_retry_backoff")]
+    pub retry_backoff: u64,
+    // seconds
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout: u64,
+}
+
+// file_upload is too noisy for webhook
+fn default_webhook_events() -> Vec<DomainEventKind> {
+    DomainEventKind::ALL
+        .into_iter()
+        .filter(|x| *x != DomainEventKind::FileUpload)
+        .collect()
+}
+fn default_webhook_max_retries() -> u32 {
+    3
+}
+fn default_webhook_retry_backoff() -> u64 {
+    1
+}
+fn default_webhook_timeout() -> u64 {
+    10
+}
+
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct AuthLockoutConfig {
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+    #[serde(default = "default_lockout_seconds")]
+    pub lockout_seconds: u64,
+}
+
+impl Default for AuthLockoutConfig {
+    fn default() -> Self {
+        AuthLockoutConfig {
+            max_failures: default_max_failures(),
+            lockout_seconds: default_lockout_seconds(),
+        }
+    }
+}
+
+fn default_max_failures() -> u32 {
+    5
+}
+fn default_lockout_seconds() -> u64 {
+    300
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct DomainConfig {
     pub domain: String,
     pub alias: Option<Vec<String>>,
+    pub rate_limit: Option<RateLimitConfig>,
+    // retention of deleting deprecated versions, override admin_config.deprecated_version_delete
+    pub retention: Option<RetentionPolicy>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct HttpConfig {
     pub addr: String,
     pub port: u16,
+    pub max_connections: Option<u64>,
+    // seconds
+    #[serde(default = "default_header_read_timeout")]
+    pub header_read_timeout: u64,
+    // seconds
+    #[serde(default = "default_body_read_timeout")]
+    pub body_read_timeout: u64,
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+fn default_header_read_timeout() -> u64 {
+    30
+}
+fn default_body_read_timeout() -> u64 {
+    60
+}
+
+// token bucket per client ip
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct RateLimitConfig {
+    // tokens added per second
+    pub rate: u32,
+    // bucket capacity, max requests allowed in a burst
+    pub burst: u32,
 }
 
 #[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -70,6 +320,26 @@ pub struct DeprecatedVersionRemove {
     pub cron: String,
     #[serde(default = "default_max_reserve")]
     pub max_reserve: u32,
+    pub max_age_days: Option<u32>,
+}
+
+impl DeprecatedVersionRemove {
+    pub fn retention(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_reserve: Some(self.max_reserve),
+            max_age_days: self.max_age_days,
+        }
+    }
+}
+
+// a version is kept if any rule keeps it, serving and uploading versions are always kept.
+// only serving and uploading versions are kept if no rule is set.
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct RetentionPolicy {
+    // keep the most recent finished versions
+    pub max_reserve: Option<u32>,
+    // keep versions finished in recent days
+    pub max_age_days: Option<u32>,
 }
 pub fn default_cron() -> String {
     String::from("0 0 3 * * *")
@@ -78,6 +348,46 @@ pub fn default_max_reserve() -> u32 {
     2
 }
 
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct TrashConfig {
+    #[serde(default = "default_trash_retention_days")]
+    pub retention_days: u32,
+    // cron of purging expired trash
+    #[serde(default = "default_trash_cron")]
+    pub cron: String,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            retention_days: default_trash_retention_days(),
+            cron: default_trash_cron(),
+        }
+    }
+}
+
+fn default_trash_retention_days() -> u32 {
+    7
+}
+fn default_trash_cron() -> String {
+    String::from("0 30 * * * *")
+}
+
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct UploadSessionExpire {
+    // seconds since last activity
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+    #[serde(default = "default_expire_cron")]
+    pub cron: String,
+}
+fn default_idle_timeout() -> u64 {
+    3600
+}
+fn default_expire_cron() -> String {
+    String::from("0 */5 * * * *")
+}
+
 pub fn get_host_path_from_domain(domain: &str) -> (&str, &str) {
     match domain.split_once('/') {
         None => (domain, ""),
@@ -85,6 +395,13 @@ pub fn get_host_path_from_domain(domain: &str) -> (&str, &str) {
     }
 }
 
+// `www.example.com` matches itself and `www.example.com/sub`
+pub(crate) fn is_same_or_sub_domain(domain: &str, target: &str) -> bool {
+    target
+        .strip_prefix(domain)
+        .is_some_and(|x| x.is_empty() || x.starts_with('/'))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct OriginWrapper(HeaderValue);
 
@@ -1,19 +1,29 @@
-use crate::config::get_host_path_from_domain;
+use crate::config::{RetentionPolicy, get_host_path_from_domain, is_same_or_sub_domain};
+use crate::event::EventBus;
 use crate::file_cache::{CacheItem, FileCache};
 use anyhow::{Context, anyhow, bail};
+use chrono::{DateTime, TimeDelta, Utc};
 use dashmap::DashMap;
+use entity::error::{ApiError, ErrorCode};
+use entity::event::DomainEventData;
 use entity::storage::{
-    DomainInfo, GetDomainPositionStatus, ShortMetaData, UploadDomainPosition, UploadingStatus,
+    DomainInfo, DomainVersion, FileDiff, FileEntry, GetDomainPositionStatus, ReleaseAction,
+    ReleaseRecord, ScheduledRelease, ShortMetaData, TrashItem, UploadDomainPosition, UploadSession,
+    UploadingStatus, VersionDiff, VersionMetadata,
 };
+use flate2::Compression;
+use flate2::write::GzEncoder;
 use md5::{Digest, Md5};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, OnceLock};
-use tracing::{debug, info};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tracing::{debug, error, info, warn};
 use walkdir::{DirEntry, WalkDir};
 
 pub(crate) const URI_REGEX_STR: &str =
@@ -25,8 +35,40 @@ pub(crate) fn uri_regex() -> &'static Regex {
     URI_REGEX.get_or_init(|| Regex::new(URI_REGEX_STR).unwrap())
 }
 
+// the whole host should be valid, and sub path can't escape from the domain directory.
+pub(crate) fn is_valid_domain(domain: &str) -> bool {
+    static HOST_REGEX: OnceLock<Regex> = OnceLock::new();
+    let host_regex = HOST_REGEX.get_or_init(|| Regex::new(&format!("^{URI_REGEX_STR}$")).unwrap());
+    match domain.split_once('/') {
+        None => host_regex.is_match(domain),
+        Some((host, path)) => {
+            host_regex.is_match(host)
+                && path
+                    .split('/')
+                    .all(|x| !x.is_empty() && x != "." && x != "..")
+        }
+    }
+}
+
 pub(crate) const UPLOADING_FILE_NAME: &str = ".SPA-Processing";
 pub(crate) const MULTIPLE_WEB_FILE_NAME: &str = ".SPA-Multiple";
+// pinned versions of domain, one version per line
+pub(crate) const PINNED_FILE_NAME: &str = ".SPA-Pinned";
+// ${file_dir}/.SPA-Trash/${id}/{meta.json, files/}
+pub(crate) const TRASH_DIR_NAME: &str = ".SPA-Trash";
+const TRASH_META_FILE_NAME: &str = "meta.json";
+const TRASH_FILES_DIR_NAME: &str = "files";
+const TRASH_VERSION_METADATA_FILE_NAME: &str = "version.json";
+const TRASH_TAGS_FILE_NAME: &str = "tags.json";
+// metadata of versions: ${domain}/.SPA-Meta/${version}.json
+pub(crate) const VERSION_METADATA_DIR_NAME: &str = ".SPA-Meta";
+// tags and channels of domain: {"tags": {"v1.0.0": 1}, "channels": {"stable": 1}}
+pub(crate) const TAGS_FILE_NAME: &str = ".SPA-Tags";
+// release history of domain, json lines of ReleaseRecord
+pub(crate) const RELEASE_HISTORY_FILE_NAME: &str = ".SPA-History.jsonl";
+pub(crate) const DEFAULT_HISTORY_LIMIT: usize = 100;
+// releases waiting for their time: ${file_dir}/.SPA-Scheduled.json, json array of ScheduledRelease
+pub(crate) const SCHEDULED_RELEASE_FILE_NAME: &str = ".SPA-Scheduled.json";
 // pub(crate) const SINGLE_WEB_FILE_NAME: &str = ".SPA-Single";
 
 #[derive(Debug)]
@@ -35,12 +77,50 @@ pub enum DomainMeta {
     MultipleWeb(DashMap<String, (PathBuf, u32)>), // path, u32  {a/b: ($path_prefix/$domain/a/b/${serving_version},  serving_version)}
 }
 
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct DomainTags {
+    #[serde(default)]
+    tags: BTreeMap<String, u32>,
+    #[serde(default)]
+    channels: BTreeMap<String, u32>,
+}
+
+// files of version are in memory, waiting to switch serving version
+struct PreparedRelease {
+    domain: String,
+    version: u32,
+    path: PathBuf,
+    data: HashMap<String, Arc<CacheItem>>,
+}
+
+// uploading version which is being finished, it keeps uploading after dropped if it's not finished
+pub struct FinishingLock<'a> {
+    uploading_status: &'a DashMap<String, UploadSession>,
+    domain: String,
+    version: u32,
+}
+
+impl Drop for FinishingLock<'_> {
+    fn drop(&mut self) {
+        if let Some(mut session) = self.uploading_status.get_mut(&self.domain)
+            && session.version == self.version
+        {
+            session.finishing = false;
+        }
+    }
+}
+
 // TODO: add write locker for domain storage or domain. to keep it free from multiple update at same time.
 pub struct DomainStorage {
     meta: DashMap<String, DomainMeta>,
     prefix: PathBuf,
     cache: FileCache, // {[${domain}/${multiple_path}|$domain]: ${absolute_path}/version}
-    uploading_status: DashMap<String, u32>,
+    uploading_status: DashMap<String, UploadSession>,
+    events: EventBus,
+    // loaded from scheduled releases file at startup, and written through on change
+    scheduled_releases: Mutex<Vec<ScheduledRelease>>,
+    // serving versions are switched in write lock, so lookups get all of a batch or none of it
+    switch_lock: RwLock<()>,
 }
 
 impl DomainStorage {
@@ -49,7 +129,7 @@ impl DomainStorage {
         let path_prefix_buf = path_prefix.to_path_buf();
         if path_prefix.exists() {
             let domain_version: DashMap<String, DomainMeta> = DashMap::new();
-            let uploading_status: DashMap<String, u32> = DashMap::new();
+            let uploading_status: DashMap<String, UploadSession> = DashMap::new();
 
             let domain_dirs = fs::read_dir(path_prefix)?;
             for domain_dir in domain_dirs {
@@ -74,8 +154,10 @@ impl DomainStorage {
                             let (uploading_version, max_version) =
                                 Self::get_meta_info(&sub_dir, &domain_with_sub_path)?;
                             if let Some(uploading_version) = uploading_version {
-                                uploading_status
-                                    .insert(domain_with_sub_path.clone(), uploading_version);
+                                uploading_status.insert(
+                                    domain_with_sub_path.clone(),
+                                    Self::restore_upload_session(&sub_dir, uploading_version),
+                                );
                             }
 
                             if let Some(version) = max_version {
@@ -128,7 +210,10 @@ impl DomainStorage {
                         let (uploading_version, max_version) =
                             Self::get_meta_info(&domain_dir, domain_dir_name)?;
                         if let Some(uploading_version) = uploading_version {
-                            uploading_status.insert(domain_dir_name.to_string(), uploading_version);
+                            uploading_status.insert(
+                                domain_dir_name.to_string(),
+                                Self::restore_upload_session(&domain_dir, uploading_version),
+                            );
                         }
                         if let Some(version) = max_version {
                             for version in Self::get_init_version(version) {
@@ -155,6 +240,9 @@ impl DomainStorage {
                 prefix: path_prefix.to_path_buf(),
                 cache,
                 uploading_status,
+                events: EventBus::new(),
+                scheduled_releases: Mutex::new(Self::read_scheduled_releases(path_prefix)),
+                switch_lock: RwLock::new(()),
             })
         } else {
             Err(anyhow!("{:?} does not exist", path_prefix))
@@ -223,7 +311,32 @@ impl DomainStorage {
         }
         Ok((uploading_version, None))
     }
+    // start is the time of creating uploading file, last activity is the latest modified file
+    fn restore_upload_session(domain_dir: &Path, version: u32) -> UploadSession {
+        let version_dir = domain_dir.join(version.to_string());
+        let modified = |path: &Path| {
+            fs::metadata(path)
+                .and_then(|x| x.modified())
+                .ok()
+                .map(DateTime::<Utc>::from)
+        };
+        let start = modified(&version_dir.join(UPLOADING_FILE_NAME)).unwrap_or_else(Utc::now);
+        let last_activity = WalkDir::new(&version_dir)
+            .into_iter()
+            .filter_map(|x| x.ok())
+            .filter_map(|x| modified(x.path()))
+            .max()
+            .map_or(start, |x| x.max(start));
+        UploadSession {
+            version,
+            start,
+            last_activity,
+            finishing: false,
+        }
+    }
+
     pub fn get_file(&self, host: &str, key: &str) -> Option<Arc<CacheItem>> {
+        let _lock = self.switch_lock.read().unwrap();
         self.cache.get_item(host, key)
     }
 
@@ -232,12 +345,39 @@ impl DomainStorage {
         domain: String,
         version: Option<u32>,
     ) -> anyhow::Result<u32> {
+        let serving_version = self.get_domain_serving_version(&domain);
+        let prepared = self.prepare_release(&domain, version)?;
+        self.register_sub_path(&domain)?;
+        let version = prepared.version;
+        self.commit_release(prepared, serving_version);
+        Ok(version)
+    }
+
+    // switch serving version and send event of it
+    fn commit_release(&self, prepared: PreparedRelease, serving_version: Option<u32>) {
+        let domain = prepared.domain.clone();
+        let version = prepared.version;
+        self.switch_releases(vec![prepared]);
+        let event = if serving_version.is_some_and(|x| x > version) {
+            DomainEventData::Revoke { domain, version }
+        } else {
+            DomainEventData::Release { domain, version }
+        };
+        self.events.emit(event);
+    }
+
+    // files of version are put to memory, serving version is not changed
+    fn prepare_release(
+        &self,
+        domain: &str,
+        version: Option<u32>,
+    ) -> anyhow::Result<PreparedRelease> {
         //TODO: check if multiple
         let version = if let Some(version) = version {
             version
         } else {
             let max_version_opt = self
-                .get_domain_info_by_domain(&domain)
+                .get_domain_info_by_domain(domain)
                 .map(|x| x.versions)
                 .unwrap_or_default()
                 .into_iter()
@@ -245,104 +385,502 @@ impl DomainStorage {
             if let Some(max_version) = max_version_opt {
                 max_version
             } else {
-                return Err(anyhow!(
-                    "domain:{} does not exist version, please check if domain err",
-                    &domain
+                bail!(ApiError::new(
+                    ErrorCode::DomainNotFound,
+                    format!("domain:{domain} does not exist version, please check if domain err")
                 ));
             }
         };
-        let new_path = self.prefix.join(&domain).join(version.to_string());
         if self
             .uploading_status
-            .get(&domain)
-            .filter(|x| *x.value() == version)
+            .get(domain)
+            .filter(|x| x.version == version)
             .is_some()
         {
-            Err(anyhow!(
-                "domain:{},version:{} is uploading now, please finish it firstly",
-                domain,
-                version
+            bail!(ApiError::new(
+                ErrorCode::VersionUploading,
+                format!(
+                    "domain:{domain},version:{version} is uploading now, please finish it firstly"
+                )
             ))
-        } else if new_path.is_dir() {
+        }
+        self.cache_release(domain, version)
+    }
+
+    // put files of version to memory, uploading status is not checked
+    fn cache_release(&self, domain: &str, version: u32) -> anyhow::Result<PreparedRelease> {
+        let new_path = self.prefix.join(domain).join(version.to_string());
+        if new_path.is_dir() {
             info!(
                 "begin to update domain:{}, version:{}, putting files to cache",
-                &domain, version
+                domain, version
             );
-            let (host, path) = get_host_path_from_domain(&domain);
-            match self.meta.get(host) {
-                Some(domain_meta) => {
-                    //TODO: check path and DomainMeta if is pattern
-                    match domain_meta.value() {
-                        DomainMeta::OneWeb(..) => {
-                            //must keep this drop, otherwise, deadlock.
-                            drop(domain_meta);
-                            self.meta.insert(
-                                host.to_string(),
-                                DomainMeta::OneWeb(new_path.clone(), version),
-                            );
-                        }
-                        DomainMeta::MultipleWeb(map) => {
-                            let multiple_file = self.prefix.join(host).join(MULTIPLE_WEB_FILE_NAME);
-                            if multiple_file.exists() {
-                                let mut file = OpenOptions::new()
-                                    .append(true)
-                                    .read(true)
-                                    .open(multiple_file)?;
-                                let mut multiple_path = String::new();
-                                file.read_to_string(&mut multiple_path)?;
-                                if !multiple_path.lines().any(|x| x == path) {
-                                    writeln!(file, "{}", path)?;
-                                }
-                            }
-                            map.insert(path.to_string(), (new_path.clone(), version));
-                        }
-                    };
+            let (host, path) = get_host_path_from_domain(domain);
+            let sub_path = if path.is_empty() { None } else { Some(path) };
+            let data = self.cache.cache_dir(host, sub_path, version, &new_path)?;
+            Ok(PreparedRelease {
+                domain: domain.to_string(),
+                version,
+                path: new_path,
+                data,
+            })
+        } else {
+            bail!(ApiError::new(
+                ErrorCode::VersionNotFound,
+                format!("domain:{domain},version:{version} does not exist")
+            ))
+        }
+    }
+
+    // sub path is kept in multiple file, it's loaded after restart
+    fn register_sub_path(&self, domain: &str) -> anyhow::Result<()> {
+        let (host, path) = get_host_path_from_domain(domain);
+        let multiple = self
+            .meta
+            .get(host)
+            .map(|x| matches!(x.value(), DomainMeta::MultipleWeb(_)));
+        //TODO: check if MULTIPLE_WEB_FILE_NAME exists
+        if multiple == Some(true) || multiple.is_none() && !path.is_empty() {
+            let multiple_file = self.prefix.join(host).join(MULTIPLE_WEB_FILE_NAME);
+            if multiple.is_none() || multiple_file.exists() {
+                let mut file = OpenOptions::new()
+                    .create(multiple.is_none())
+                    .append(true)
+                    .read(true)
+                    .open(&multiple_file)?;
+                let mut multiple_path = String::new();
+                file.read_to_string(&mut multiple_path)?;
+                if !multiple_path.lines().any(|x| x == path) {
+                    writeln!(file, "{}", path)?;
                 }
-                None => {
-                    //TODO: check if MULTIPLE_WEB_FILE_NAME exists
-                    if path.is_empty() {
-                        self.meta.insert(
-                            host.to_string(),
-                            DomainMeta::OneWeb(new_path.clone(), version),
-                        );
-                    } else {
-                        let multiple_file = self.prefix.join(host).join(MULTIPLE_WEB_FILE_NAME);
-                        let mut file = OpenOptions::new()
-                            .create(true)
-                            .append(true)
-                            .read(true)
-                            .open(&multiple_file)?;
-
-                        let mut multiple_path = String::new();
-                        file.read_to_string(&mut multiple_path)?;
-                        if !multiple_path.lines().any(|x| x == path) {
-                            writeln!(file, "{}", path)?;
-                        }
-                        let map = DashMap::new();
+            }
+        }
+        Ok(())
+    }
+
+    // return content of multiple files before registering, None if it did not exist.
+    // they are restored if any of them fails
+    fn register_sub_paths(
+        &self,
+        domains: &[&str],
+    ) -> anyhow::Result<Vec<(PathBuf, Option<String>)>> {
+        let mut backup = Vec::new();
+        for domain in domains {
+            let (host, _) = get_host_path_from_domain(domain);
+            let multiple_file = self.prefix.join(host).join(MULTIPLE_WEB_FILE_NAME);
+            if !backup.iter().any(|(path, _)| *path == multiple_file) {
+                let content = fs::read_to_string(&multiple_file).ok();
+                backup.push((multiple_file, content));
+            }
+            if let Err(e) = self.register_sub_path(domain) {
+                self.restore_multiple_files(backup);
+                return Err(e);
+            }
+        }
+        Ok(backup)
+    }
 
-                        info!("create multiple_file {multiple_file:?}, append {path}");
-                        map.insert(path.to_string(), (new_path.clone(), version));
+    fn restore_multiple_files(&self, backup: Vec<(PathBuf, Option<String>)>) {
+        for (path, content) in backup {
+            let result = match content {
+                Some(content) => fs::write(&path, content),
+                None if path.is_file() => fs::remove_file(&path),
+                None => Ok(()),
+            };
+            if let Err(e) = result {
+                error!("restore multiple file {} failure: {e}", path.display());
+            }
+        }
+    }
 
+    // switch all of them in one write lock
+    fn switch_releases(&self, prepared: Vec<PreparedRelease>) {
+        let _lock = self.switch_lock.write().unwrap();
+        for prepared in prepared {
+            self.switch_release(prepared);
+        }
+    }
+
+    // switch serving version in memory, it can't fail, switch lock should be held
+    fn switch_release(&self, prepared: PreparedRelease) {
+        let PreparedRelease {
+            domain,
+            version,
+            path: new_path,
+            data,
+        } = prepared;
+        let (host, path) = get_host_path_from_domain(&domain);
+        match self.meta.get(host) {
+            Some(domain_meta) => {
+                //TODO: check path and DomainMeta if is pattern
+                match domain_meta.value() {
+                    DomainMeta::OneWeb(..) => {
+                        //must keep this drop, otherwise, deadlock.
+                        drop(domain_meta);
                         self.meta
-                            .insert(host.to_string(), DomainMeta::MultipleWeb(map));
+                            .insert(host.to_string(), DomainMeta::OneWeb(new_path, version));
+                    }
+                    DomainMeta::MultipleWeb(map) => {
+                        map.insert(path.to_string(), (new_path, version));
                     }
+                };
+            }
+            None => {
+                if path.is_empty() {
+                    self.meta
+                        .insert(host.to_string(), DomainMeta::OneWeb(new_path, version));
+                } else {
+                    let map = DashMap::new();
+                    info!("create multiple web of {host}, append {path}");
+                    map.insert(path.to_string(), (new_path, version));
+                    self.meta
+                        .insert(host.to_string(), DomainMeta::MultipleWeb(map));
                 }
-            };
-            let path = if path.is_empty() { None } else { Some(path) };
-            let data = self.cache.cache_dir(host, path, version, &new_path)?;
-            self.cache.update(host.to_string(), path, version, data);
-            debug!(
-                "domain: {host} sub_path: {path:?} all keys:{:?}",
-                self.cache.get_all_keys(host)
-            );
-            info!(
-                "update domain:{}, sub_path: {:?} ,version:{} finish!",
-                host, path, version
-            );
-            Ok(version)
+            }
+        };
+        let path = if path.is_empty() { None } else { Some(path) };
+        self.cache.update(host.to_string(), path, version, data);
+        debug!(
+            "domain: {host} sub_path: {path:?} all keys:{:?}",
+            self.cache.get_all_keys(host)
+        );
+        info!(
+            "update domain:{}, sub_path: {:?} ,version:{} finish!",
+            host, path, version
+        );
+    }
+
+    // release version and record it in history, it's not recorded if serving version is not changed
+    pub async fn release_version(
+        &self,
+        domain: String,
+        version: Option<u32>,
+        actor: &str,
+        action: ReleaseAction,
+    ) -> anyhow::Result<u32> {
+        let previous_version = self.get_domain_serving_version(&domain);
+        let version = self
+            .upload_domain_with_version(domain.clone(), version)
+            .await?;
+        self.append_release_history(&domain, version, previous_version, actor, action);
+        Ok(version)
+    }
+
+    // mark the uploading version as finishing, files uploading in progress are waited for,
+    // and new files are rejected until the lock is dropped
+    pub fn lock_finishing(&self, domain: &str, version: u32) -> anyhow::Result<FinishingLock<'_>> {
+        match self.uploading_status.get_mut(domain) {
+            Some(mut session) if session.version == version => {
+                if session.finishing {
+                    bail!(ApiError::new(
+                        ErrorCode::VersionUploading,
+                        format!("domain:{domain}, version:{version} is finishing")
+                    ));
+                }
+                session.finishing = true;
+            }
+            Some(session) => {
+                bail!(ApiError::new(
+                    ErrorCode::VersionUploading,
+                    format!(
+                        "domain:{domain}, version:{} is in uploading, please finish it firstly",
+                        session.version
+                    )
+                ));
+            }
+            None if !self
+                .get_version_path(domain, version)
+                .join(UPLOADING_FILE_NAME)
+                .exists() =>
+            {
+                bail!(ApiError::new(
+                    ErrorCode::VersionNotUploading,
+                    format!("domain:{domain}, version:{version} is not in uploading")
+                ));
+            }
+            None => {}
+        }
+        Ok(FinishingLock {
+            uploading_status: &self.uploading_status,
+            domain: domain.to_string(),
+            version,
+        })
+    }
+
+    // files are put to memory before the uploading version is finished,
+    // so it's not finished if it can't be released
+    pub async fn finish_and_release(
+        &self,
+        lock: FinishingLock<'_>,
+        actor: &str,
+    ) -> anyhow::Result<u32> {
+        let domain = lock.domain.clone();
+        let version = lock.version;
+        let previous_version = self.get_domain_serving_version(&domain);
+        let prepared = self.cache_release(&domain, version)?;
+        let backup = self.register_sub_paths(&[&domain])?;
+        if let Err(e) = self
+            .update_uploading_status(domain.clone(), version, UploadingStatus::Finish)
+            .await
+        {
+            self.restore_multiple_files(backup);
+            return Err(e);
+        }
+        self.commit_release(prepared, previous_version);
+        self.append_release_history(
+            &domain,
+            version,
+            previous_version,
+            actor,
+            ReleaseAction::Release,
+        );
+        Ok(version)
+    }
+
+    // all versions are put to memory before anything is written, nothing changes if one fails.
+    // serving versions are switched together after that.
+    pub fn release_versions(
+        &self,
+        releases: &[(String, Option<u32>)],
+        actor: &str,
+    ) -> anyhow::Result<Vec<DomainVersion>> {
+        if releases.is_empty() {
+            bail!(ApiError::new(
+                ErrorCode::InvalidParameters,
+                "releases should not be empty"
+            ));
+        }
+        let mut domains = HashSet::new();
+        let mut hosts = HashMap::new();
+        for (domain, _) in releases {
+            if !domains.insert(domain.as_str()) {
+                bail!(ApiError::new(
+                    ErrorCode::InvalidParameters,
+                    format!("domain:{domain} is released more than once")
+                ));
+            }
+            let (host, path) = get_host_path_from_domain(domain);
+            if *hosts.entry(host).or_insert(path.is_empty()) != path.is_empty() {
+                bail!(ApiError::new(
+                    ErrorCode::MixedSingleMultiple,
+                    format!("single SPA and multiple SPA of {host} can't be released together")
+                ));
+            }
+        }
+        let prepared = releases
+            .iter()
+            .map(|(domain, version)| self.prepare_release(domain, *version))
+            .collect::<anyhow::Result<Vec<PreparedRelease>>>()?;
+        let domains: Vec<&str> = prepared.iter().map(|x| x.domain.as_str()).collect();
+        self.register_sub_paths(&domains)?;
+        let released: Vec<(DomainVersion, Option<u32>)> = prepared
+            .iter()
+            .map(|x| {
+                let released = DomainVersion {
+                    domain: x.domain.clone(),
+                    version: x.version,
+                };
+                (released, self.get_domain_serving_version(&x.domain))
+            })
+            .collect();
+        self.switch_releases(prepared);
+        let released: Vec<DomainVersion> = released
+            .into_iter()
+            .map(|(x, previous_version)| {
+                self.append_release_history(
+                    &x.domain,
+                    x.version,
+                    previous_version,
+                    actor,
+                    ReleaseAction::Release,
+                );
+                x
+            })
+            .collect();
+        self.events.emit(DomainEventData::BatchRelease {
+            releases: released.clone(),
+        });
+        Ok(released)
+    }
+
+    // it's not recorded if serving version is not changed
+    fn append_release_history(
+        &self,
+        domain: &str,
+        version: u32,
+        previous_version: Option<u32>,
+        actor: &str,
+        action: ReleaseAction,
+    ) {
+        if previous_version == Some(version) {
+            return;
+        }
+        let record = ReleaseRecord {
+            time: Utc::now(),
+            version,
+            previous_version,
+            actor: actor.to_string(),
+            action,
+        };
+        let result = serde_json::to_string(&record)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut line| {
+                line.push('\n');
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.prefix.join(domain).join(RELEASE_HISTORY_FILE_NAME))?;
+                file.write_all(line.as_bytes())?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            error!("append release history of domain:{domain} failure: {e:?}");
+        }
+    }
+
+    // the latest records, order by time
+    pub fn get_release_history(
+        &self,
+        domain: &str,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<ReleaseRecord>> {
+        let path = self.prefix.join(domain).join(RELEASE_HISTORY_FILE_NAME);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut records: Vec<ReleaseRecord> = fs::read_to_string(path)?
+            .lines()
+            .filter_map(|line| match serde_json::from_str::<ReleaseRecord>(line) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    warn!("skip invalid release history line of domain:{domain}: {e}");
+                    None
+                }
+            })
+            .collect();
+        if let Some(limit) = limit
+            && records.len() > limit
+        {
+            records.drain(..records.len() - limit);
+        }
+        Ok(records)
+    }
+
+    // the version before the latest release which is not rolled back,
+    // so rolling back again returns to the release before it
+    pub fn get_rollback_version(&self, domain: &str) -> anyhow::Result<u32> {
+        let mut rolled_back = 0;
+        for record in self.get_release_history(domain, None)?.iter().rev() {
+            if record.action == ReleaseAction::Rollback {
+                rolled_back += 1;
+            } else if rolled_back > 0 {
+                rolled_back -= 1;
+            } else if let Some(version) = record.previous_version {
+                return Ok(version);
+            } else {
+                break;
+            }
+        }
+        bail!(ApiError::new(
+            ErrorCode::NoPreviousRelease,
+            format!("domain:{domain} has no previous release to rollback")
+        ))
+    }
+
+    fn read_scheduled_releases(path_prefix: &Path) -> Vec<ScheduledRelease> {
+        let path = path_prefix.join(SCHEDULED_RELEASE_FILE_NAME);
+        if !path.exists() {
+            return Vec::new();
+        }
+        match fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_slice::<Vec<ScheduledRelease>>(&data)?))
+        {
+            Ok(items) => items,
+            Err(e) => {
+                error!("read scheduled releases from {path:?} failure: {e:?}");
+                Vec::new()
+            }
+        }
+    }
+
+    fn write_scheduled_releases(&self, items: &[ScheduledRelease]) -> anyhow::Result<()> {
+        let path = self.prefix.join(SCHEDULED_RELEASE_FILE_NAME);
+        if items.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
         } else {
-            Err(anyhow!("{:?} does not exits", new_path))
+            fs::write(path, serde_json::to_vec_pretty(items)?)?;
+        }
+        Ok(())
+    }
+
+    // domain: also includes items of sub path, order by release time
+    pub fn list_scheduled_releases(&self, domain: Option<&str>) -> Vec<ScheduledRelease> {
+        let mut items: Vec<ScheduledRelease> = self
+            .scheduled_releases
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|item| domain.is_none_or(|domain| is_same_or_sub_domain(domain, &item.domain)))
+            .cloned()
+            .collect();
+        items.sort_by(|a, b| a.release_at.cmp(&b.release_at).then(a.id.cmp(&b.id)));
+        items
+    }
+
+    // the version is checked again when it's released
+    pub fn add_scheduled_release(
+        &self,
+        domain: &str,
+        version: u32,
+        release_at: DateTime<Utc>,
+        actor: &str,
+    ) -> anyhow::Result<ScheduledRelease> {
+        let path = self.get_existing_version_path(domain, version)?;
+        if self.check_is_in_upload_process(domain, &version)
+            || path.join(UPLOADING_FILE_NAME).exists()
+        {
+            bail!(ApiError::new(
+                ErrorCode::VersionUploading,
+                format!("domain:{domain}, version:{version} is in uploading, can not be released")
+            ));
         }
+        let mut scheduled_releases = self.scheduled_releases.lock().unwrap();
+        let mut items = scheduled_releases.clone();
+        let item = ScheduledRelease {
+            id: items.iter().map(|x| x.id).max().unwrap_or(0) + 1,
+            domain: domain.to_string(),
+            version,
+            release_at,
+            created_at: Utc::now(),
+            actor: actor.to_string(),
+        };
+        items.push(item.clone());
+        self.write_scheduled_releases(&items)?;
+        *scheduled_releases = items;
+        info!(
+            "domain:{domain}, version:{version} is scheduled to release at {release_at}, id: {}",
+            item.id
+        );
+        Ok(item)
+    }
+
+    // remove it when it's released or canceled
+    pub fn remove_scheduled_release(&self, id: u64) -> anyhow::Result<ScheduledRelease> {
+        let mut scheduled_releases = self.scheduled_releases.lock().unwrap();
+        let mut items = scheduled_releases.clone();
+        let Some(index) = items.iter().position(|x| x.id == id) else {
+            bail!(ApiError::new(
+                ErrorCode::ScheduleNotFound,
+                format!("scheduled release: {id} does not exist")
+            ));
+        };
+        let item = items.remove(index);
+        self.write_scheduled_releases(&items)?;
+        *scheduled_releases = items;
+        Ok(item)
     }
 
     pub fn get_version_path(&self, host: &str, version: u32) -> PathBuf {
@@ -354,7 +892,7 @@ impl DomainStorage {
 
     pub fn get_upload_position(&self, domain: &str) -> anyhow::Result<UploadDomainPosition> {
         self.check_if_can_upload(domain)?;
-        let result = if let Some(version) = self.uploading_status.get(domain).map(|x| *x.value()) {
+        let result = if let Some(version) = self.uploading_status.get(domain).map(|x| x.version) {
             UploadDomainPosition {
                 path: self.get_version_path(domain, version),
                 version,
@@ -385,6 +923,7 @@ impl DomainStorage {
     }
 
     pub fn get_domain_serving_version(&self, domain: &str) -> Option<u32> {
+        let _lock = self.switch_lock.read().unwrap();
         let (host, path) = get_host_path_from_domain(domain);
         let domain_meta = self.meta.get(host)?;
         match domain_meta.value() {
@@ -437,10 +976,35 @@ impl DomainStorage {
             } else {
                 Vec::new()
             };*/
+            let upload_session = self
+                .uploading_status
+                .get(&domain)
+                .map(|x| x.value().clone());
+            let pinned_versions = self.get_pinned_versions(&domain);
+            let metadata: BTreeMap<u32, VersionMetadata> = versions
+                .iter()
+                .filter_map(|version| {
+                    self.get_version_metadata(&domain, *version)
+                        .map(|x| (*version, x))
+                })
+                .collect();
+            let DomainTags { tags, channels } = self.get_domain_tags(&domain);
+            let scheduled_releases = self
+                .list_scheduled_releases(Some(&domain))
+                .into_iter()
+                .filter(|x| x.domain == domain)
+                .collect();
             Some(DomainInfo {
                 domain,
                 current_version,
                 versions,
+                uploading_version: upload_session.as_ref().map(|x| x.version),
+                upload_session,
+                pinned_versions,
+                metadata,
+                tags,
+                channels,
+                scheduled_releases,
                 // web_path,
             })
         }
@@ -487,10 +1051,18 @@ impl DomainStorage {
     fn check_is_in_upload_process(&self, domain: &str, version: &u32) -> bool {
         self.uploading_status
             .get(domain)
-            .filter(|x| x.value() == version)
+            .filter(|x| x.version == *version)
             .is_some()
     }
 
+    fn touch_upload_session(&self, domain: &str, version: u32) {
+        if let Some(mut session) = self.uploading_status.get_mut(domain)
+            && session.version == version
+        {
+            session.last_activity = Utc::now();
+        }
+    }
+
     pub fn get_files_metadata(
         &self,
         domain: String,
@@ -535,7 +1107,126 @@ impl DomainStorage {
             Ok(Vec::new())
         }
     }
+    pub fn get_existing_version_path(&self, domain: &str, version: u32) -> anyhow::Result<PathBuf> {
+        let path = self.get_version_path(domain, version);
+        if !path.is_dir() {
+            bail!(ApiError::new(
+                ErrorCode::VersionNotFound,
+                format!("domain:{domain}, version:{version} does not exist")
+            ));
+        }
+        Ok(path)
+    }
+
+    // path: directory relative to version, list the root of version if it's empty
+    pub fn list_files(
+        &self,
+        domain: &str,
+        version: u32,
+        path: &str,
+        recursive: bool,
+    ) -> anyhow::Result<Vec<FileEntry>> {
+        let version_path = self.get_existing_version_path(domain, version)?;
+        let dir = sanitize_path(&version_path, path.trim_matches('/'))
+            .map_err(|_| ApiError::new(ErrorCode::PathInvalid, format!("invalid path: {path}")))?;
+        if !dir.is_dir() {
+            bail!(ApiError::new(
+                ErrorCode::FileNotFound,
+                format!("directory: {path} does not exist in domain:{domain}, version:{version}")
+            ));
+        }
+        let walker = WalkDir::new(&dir).min_depth(1).sort_by_file_name();
+        let walker = if recursive {
+            walker
+        } else {
+            walker.max_depth(1)
+        };
+        let entries = walker
+            .into_iter()
+            .filter_map(|x| x.ok())
+            .filter(|x| x.file_name() != UPLOADING_FILE_NAME)
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let path = entry.path().strip_prefix(&version_path).ok()?;
+                let path = path.to_str()?.replace('\\', "/");
+                Some(FileEntry {
+                    path,
+                    is_dir: metadata.is_dir(),
+                    length: if metadata.is_dir() { 0 } else { metadata.len() },
+                    modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+                })
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    // path: file path relative to version, like: js/app.js
+    pub fn get_version_file(
+        &self,
+        domain: &str,
+        version: u32,
+        path: &str,
+    ) -> anyhow::Result<PathBuf> {
+        let version_path = self.get_existing_version_path(domain, version)?;
+        let file = sanitize_path(&version_path, path.trim_start_matches('/'))
+            .map_err(|_| ApiError::new(ErrorCode::PathInvalid, format!("invalid path: {path}")))?;
+        if !file.is_file() || file.file_name().is_some_and(|x| x == UPLOADING_FILE_NAME) {
+            bail!(ApiError::new(
+                ErrorCode::FileNotFound,
+                format!("file: {path} does not exist in domain:{domain}, version:{version}")
+            ));
+        }
+        Ok(file)
+    }
+
+    pub fn diff_versions(&self, domain: &str, from: u32, to: u32) -> anyhow::Result<VersionDiff> {
+        self.get_existing_version_path(domain, from)?;
+        self.get_existing_version_path(domain, to)?;
+        let from_files: HashMap<String, ShortMetaData> = self
+            .get_files_metadata(domain.to_string(), from)?
+            .into_iter()
+            .map(|x| (x.path.clone(), x))
+            .collect();
+        let mut to_files = self.get_files_metadata(domain.to_string(), to)?;
+        to_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut diff = VersionDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            modified: Vec::new(),
+            unchanged: 0,
+        };
+        for file in &to_files {
+            match from_files.get(&file.path) {
+                Some(from_file) if from_file.md5 == file.md5 => diff.unchanged += 1,
+                Some(from_file) => diff.modified.push(FileDiff {
+                    path: file.path.clone(),
+                    from_length: Some(from_file.length),
+                    to_length: Some(file.length),
+                }),
+                None => diff.added.push(FileDiff {
+                    path: file.path.clone(),
+                    from_length: None,
+                    to_length: Some(file.length),
+                }),
+            }
+        }
+        let to_paths: HashSet<&str> = to_files.iter().map(|x| x.path.as_str()).collect();
+        diff.removed = from_files
+            .into_values()
+            .filter(|x| !to_paths.contains(x.path.as_str()))
+            .map(|x| FileDiff {
+                path: x.path,
+                from_length: Some(x.length),
+                to_length: None,
+            })
+            .collect();
+        diff.removed.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(diff)
+    }
+
     pub fn check_if_empty_index(&self, host: &str, path: &str) -> bool {
+        let _lock = self.switch_lock.read().unwrap();
         match self.meta.get(host) {
             Some(v) => match v.value() {
                 DomainMeta::OneWeb { .. } => path.is_empty(),
@@ -562,9 +1253,16 @@ impl DomainStorage {
         path: String,
         data: &PathBuf,
     ) -> anyhow::Result<()> {
-        if self.check_is_in_upload_process(&domain, &version) {
-            let file_path = sanitize_path(self.get_version_path(&domain, version), &path)
-                .map_err(|_| anyhow!("path error"))?;
+        // finishing waits for the session which is held until the file is copied
+        if let Some(session) = self
+            .uploading_status
+            .get(&domain)
+            .filter(|x| x.version == version && !x.finishing)
+        {
+            let file_path =
+                sanitize_path(self.get_version_path(&domain, version), &path).map_err(|_| {
+                    ApiError::new(ErrorCode::PathInvalid, format!("invalid path: {path}"))
+                })?;
             let parent_path = file_path
                 .parent()
                 .ok_or_else(|| anyhow!("parent path of:{:?} does not exists", &file_path))?;
@@ -572,12 +1270,20 @@ impl DomainStorage {
                 fs::create_dir_all(parent_path)?;
             };
             fs::copy(data, Path::new(&file_path))?;
-            Ok(())
-        } else {
-            Err(anyhow!(
-                "domain:{}, version:{} can't be uploaded file, it's not in the status allowing to upload file",
+            drop(session);
+            self.touch_upload_session(&domain, version);
+            self.events.emit(DomainEventData::FileUpload {
                 domain,
                 version,
+                path,
+            });
+            Ok(())
+        } else {
+            bail!(ApiError::new(
+                ErrorCode::VersionNotUploading,
+                format!(
+                    "domain:{domain}, version:{version} can't be uploaded file, it's not in the status allowing to upload file"
+                )
             ))
         }
     }
@@ -589,12 +1295,13 @@ impl DomainStorage {
         uploading_status: UploadingStatus,
     ) -> anyhow::Result<()> {
         // TODO: check if multiple and domain match
-        if let Some(uploading_version) = self.uploading_status.get(&domain).map(|v| *v.value()) {
+        if let Some(uploading_version) = self.uploading_status.get(&domain).map(|v| v.version) {
             if uploading_version != version {
-                return Err(anyhow!(
-                    "domain:{}, version:{} is in uploading, please finish it firstly",
-                    domain,
-                    uploading_version,
+                bail!(ApiError::new(
+                    ErrorCode::VersionUploading,
+                    format!(
+                        "domain:{domain}, version:{uploading_version} is in uploading, please finish it firstly"
+                    )
                 ));
             }
             if uploading_status == UploadingStatus::Finish {
@@ -602,11 +1309,15 @@ impl DomainStorage {
                 p.push(UPLOADING_FILE_NAME);
                 fs::remove_file(p)?;
                 self.uploading_status
-                    .remove_if(&domain, |_, v| *v == version);
+                    .remove_if(&domain, |_, v| v.version == version);
                 info!(
                     "domain:{}, version:{} change to upload status:finish",
                     domain, version
                 );
+                self.events
+                    .emit(DomainEventData::UploadFinish { domain, version });
+            } else {
+                self.touch_upload_session(&domain, version);
             }
         } else if uploading_status == UploadingStatus::Uploading {
             if self
@@ -614,10 +1325,11 @@ impl DomainStorage {
                 .filter(|x| *x == version)
                 .is_some()
             {
-                return Err(anyhow!(
-                    "domain:{}, version:{} is in serving, can not change upload status",
-                    domain,
-                    version,
+                bail!(ApiError::new(
+                    ErrorCode::VersionServing,
+                    format!(
+                        "domain:{domain}, version:{version} is in serving, can not change upload status"
+                    )
                 ));
             }
             let mut p = self.get_version_path(&domain, version);
@@ -634,7 +1346,10 @@ impl DomainStorage {
                     }
                 }
             } else if multiple.exists() {
-                bail!("This already has multiple SPA, should not upload single SPA at top path")
+                bail!(ApiError::new(
+                    ErrorCode::MixedSingleMultiple,
+                    "This already has multiple SPA, should not upload single SPA at top path"
+                ))
             }
             fs::create_dir_all(&p)?;
             p.push(UPLOADING_FILE_NAME);
@@ -643,21 +1358,496 @@ impl DomainStorage {
                 "domain:{}, version:{} change to upload status:uploading",
                 domain, version
             );
-            self.uploading_status.insert(domain, version);
+            let now = Utc::now();
+            self.uploading_status.insert(
+                domain.clone(),
+                UploadSession {
+                    version,
+                    start: now,
+                    last_activity: now,
+                    finishing: false,
+                },
+            );
+            self.events
+                .emit(DomainEventData::UploadStart { domain, version });
         } else {
             let mut p = self.get_version_path(&domain, version);
             p.push(UPLOADING_FILE_NAME);
+            if !p.exists() {
+                bail!(ApiError::new(
+                    ErrorCode::VersionNotUploading,
+                    format!("domain:{domain}, version:{version} is not in uploading")
+                ))
+            }
             fs::remove_file(p)?;
             self.uploading_status
-                .remove_if(&domain, |_, v| *v == version);
+                .remove_if(&domain, |_, v| v.version == version);
             info!(
                 "domain:{}, version:{} change to upload status:finish",
                 domain, version
             );
+            self.events
+                .emit(DomainEventData::UploadFinish { domain, version });
+        }
+        Ok(())
+    }
+
+    // check expected files by md5 and length, files not in the list are not checked
+    pub fn verify_files(
+        &self,
+        domain: &str,
+        version: u32,
+        files: &[ShortMetaData],
+    ) -> anyhow::Result<()> {
+        let path = self.get_existing_version_path(domain, version)?;
+        let mut byte_buffer = vec![0u8; 1024 * 1024];
+        let mismatched: Vec<&str> = files
+            .iter()
+            .filter(|file| {
+                let Ok(file_path) = sanitize_path(&path, &file.path) else {
+                    return true;
+                };
+                let length = fs::metadata(&file_path).map(|x| x.len()).ok();
+                length != Some(file.length)
+                    || md5_file(&file_path, &mut byte_buffer).as_ref() != Some(&file.md5)
+            })
+            .map(|file| file.path.as_str())
+            .collect();
+        if !mismatched.is_empty() {
+            bail!(
+                ApiError::new(
+                    ErrorCode::FileMismatch,
+                    format!(
+                        "{} file(s) of domain:{domain}, version:{version} are missing or mismatched",
+                        mismatched.len()
+                    )
+                )
+                .details(serde_json::json!({ "files": mismatched }))
+            );
+        }
+        Ok(())
+    }
+
+    // delete a version which is neither serving nor uploading
+    pub fn delete_version(&self, domain: &str, version: u32) -> anyhow::Result<()> {
+        let path = self.get_existing_version_path(domain, version)?;
+        if self.get_domain_serving_version(domain) == Some(version) {
+            bail!(ApiError::new(
+                ErrorCode::VersionServing,
+                format!("domain:{domain}, version:{version} is in serving, can not be deleted")
+            ));
+        }
+        if self.get_pinned_versions(domain).contains(&version) {
+            bail!(ApiError::new(
+                ErrorCode::VersionPinned,
+                format!("domain:{domain}, version:{version} is pinned, please unpin it firstly")
+            ));
+        }
+        if self.check_is_in_upload_process(domain, &version)
+            || path.join(UPLOADING_FILE_NAME).exists()
+        {
+            bail!(ApiError::new(
+                ErrorCode::VersionUploading,
+                format!(
+                    "domain:{domain}, version:{version} is in uploading, please abort it instead"
+                )
+            ));
+        }
+        self.remove_domain_version(domain, Some(version))?;
+        info!("domain:{domain}, version:{version} is deleted");
+        self.events.emit(DomainEventData::Delete {
+            domain: domain.to_string(),
+            version,
+        });
+        Ok(())
+    }
+
+    // remove uploading status and files of the version, so it could be uploaded again
+    pub fn abort_uploading(&self, domain: &str, version: u32) -> anyhow::Result<()> {
+        let path = self.get_version_path(domain, version);
+        if !self.check_is_in_upload_process(domain, &version)
+            && !path.join(UPLOADING_FILE_NAME).exists()
+        {
+            bail!(ApiError::new(
+                ErrorCode::VersionNotUploading,
+                format!("domain:{domain}, version:{version} is not in uploading")
+            ));
+        }
+        let removed = self
+            .uploading_status
+            .remove_if(domain, |_, v| v.version == version && !v.finishing);
+        // the session is kept only if it's finishing
+        if removed.is_none() && self.check_is_in_upload_process(domain, &version) {
+            bail!(ApiError::new(
+                ErrorCode::VersionUploading,
+                format!("domain:{domain}, version:{version} is finishing, can not be aborted")
+            ));
+        }
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        self.remove_version_metadata(domain, version);
+        self.take_version_tags(domain, version)?;
+        info!("domain:{domain}, version:{version} upload is aborted");
+        self.events.emit(DomainEventData::UploadAbort {
+            domain: domain.to_string(),
+            version,
+        });
+        Ok(())
+    }
+
+    fn get_version_metadata_path(&self, domain: &str, version: u32) -> PathBuf {
+        self.prefix
+            .join(domain)
+            .join(VERSION_METADATA_DIR_NAME)
+            .join(format!("{version}.json"))
+    }
+
+    pub fn get_version_metadata(&self, domain: &str, version: u32) -> Option<VersionMetadata> {
+        let data = fs::read(self.get_version_metadata_path(domain, version)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    // replace the metadata of an existing version
+    pub fn save_version_metadata(
+        &self,
+        domain: &str,
+        version: u32,
+        metadata: &VersionMetadata,
+    ) -> anyhow::Result<()> {
+        self.get_existing_version_path(domain, version)?;
+        fs::create_dir_all(self.prefix.join(domain).join(VERSION_METADATA_DIR_NAME))?;
+        fs::write(
+            self.get_version_metadata_path(domain, version),
+            serde_json::to_vec(metadata)?,
+        )?;
+        debug!("domain:{domain}, version:{version} metadata is saved");
+        Ok(())
+    }
+
+    fn remove_version_metadata(&self, domain: &str, version: u32) {
+        let path = self.get_version_metadata_path(domain, version);
+        if path.exists()
+            && let Err(e) = fs::remove_file(&path)
+        {
+            warn!("remove version metadata {} failure: {e}", path.display());
+        }
+    }
+
+    fn get_domain_tags(&self, domain: &str) -> DomainTags {
+        fs::read(self.prefix.join(domain).join(TAGS_FILE_NAME))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    // name of tag or channel
+    pub fn resolve_tag(&self, domain: &str, name: &str) -> anyhow::Result<u32> {
+        let DomainTags { tags, channels } = self.get_domain_tags(domain);
+        tags.get(name)
+            .or_else(|| channels.get(name))
+            .copied()
+            .ok_or_else(|| {
+                ApiError::new(
+                    ErrorCode::TagNotFound,
+                    format!("domain:{domain}, tag or channel:{name} does not exist"),
+                )
+                .into()
+            })
+    }
+
+    // version of request which sets version or tag, None if both are not set
+    pub fn resolve_version(
+        &self,
+        domain: &str,
+        version: Option<u32>,
+        tag: Option<&str>,
+    ) -> anyhow::Result<Option<u32>> {
+        match (version, tag) {
+            (Some(_), Some(_)) => bail!(ApiError::new(
+                ErrorCode::InvalidParameters,
+                "version and tag should not be set at same time"
+            )),
+            (None, Some(tag)) => self.resolve_tag(domain, tag).map(Some),
+            (version, None) => Ok(version),
+        }
+    }
+
+    // tag is unique in domain and can't be moved to another version, channel can be moved.
+    // the version is set by `version` or by the tag or channel `from`
+    pub fn set_tag(
+        &self,
+        domain: &str,
+        name: &str,
+        version: Option<u32>,
+        from: Option<&str>,
+        channel: bool,
+    ) -> anyhow::Result<u32> {
+        let kind = if channel { "channel" } else { "tag" };
+        if name.is_empty()
+            || name.len() > 64
+            || name.chars().all(|c| c.is_ascii_digit())
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        {
+            bail!(ApiError::new(
+                ErrorCode::InvalidParameters,
+                format!(
+                    "invalid {kind}:{name}, it should be letters, digits, '.', '-' or '_', and not a number"
+                )
+            ));
+        }
+        let version = match (version, from) {
+            (Some(version), None) => version,
+            (None, Some(from)) => self.resolve_tag(domain, from)?,
+            _ => bail!(ApiError::new(
+                ErrorCode::InvalidParameters,
+                "one of version and from should be set"
+            )),
+        };
+        self.get_existing_version_path(domain, version)?;
+        let mut domain_tags = self.get_domain_tags(domain);
+        let (names, others) = if channel {
+            (&mut domain_tags.channels, &domain_tags.tags)
+        } else {
+            (&mut domain_tags.tags, &domain_tags.channels)
+        };
+        if others.contains_key(name) {
+            bail!(ApiError::new(
+                ErrorCode::TagExists,
+                format!(
+                    "domain:{domain}, {name} is already a {}",
+                    if channel { "tag" } else { "channel" }
+                )
+            ));
+        }
+        if let Some(old) = names.get(name)
+            && *old != version
+            && !channel
+        {
+            bail!(ApiError::new(
+                ErrorCode::TagExists,
+                format!("domain:{domain}, tag:{name} is already set to version:{old}")
+            ));
+        }
+        names.insert(name.to_string(), version);
+        fs::write(
+            self.prefix.join(domain).join(TAGS_FILE_NAME),
+            serde_json::to_vec(&domain_tags)?,
+        )?;
+        info!("domain:{domain}, {kind}:{name} is set to version:{version}");
+        Ok(version)
+    }
+
+    // return the version which tag or channel pointed to
+    pub fn delete_tag(&self, domain: &str, name: &str, channel: bool) -> anyhow::Result<u32> {
+        let kind = if channel { "channel" } else { "tag" };
+        let mut domain_tags = self.get_domain_tags(domain);
+        let names = if channel {
+            &mut domain_tags.channels
+        } else {
+            &mut domain_tags.tags
+        };
+        let Some(version) = names.remove(name) else {
+            bail!(ApiError::new(
+                ErrorCode::TagNotFound,
+                format!("domain:{domain}, {kind}:{name} does not exist")
+            ));
+        };
+        fs::write(
+            self.prefix.join(domain).join(TAGS_FILE_NAME),
+            serde_json::to_vec(&domain_tags)?,
+        )?;
+        info!("domain:{domain}, {kind}:{name} of version:{version} is deleted");
+        Ok(version)
+    }
+
+    // remove tags and channels of the version, so they would not point to a new version which reuses the number
+    fn take_version_tags(&self, domain: &str, version: u32) -> anyhow::Result<DomainTags> {
+        let mut domain_tags = self.get_domain_tags(domain);
+        let (tags, others): (BTreeMap<_, _>, BTreeMap<_, _>) = domain_tags
+            .tags
+            .into_iter()
+            .partition(|(_, v)| *v == version);
+        domain_tags.tags = others;
+        let (channels, others): (BTreeMap<_, _>, BTreeMap<_, _>) = domain_tags
+            .channels
+            .into_iter()
+            .partition(|(_, v)| *v == version);
+        domain_tags.channels = others;
+        let removed = DomainTags { tags, channels };
+        if !removed.tags.is_empty() || !removed.channels.is_empty() {
+            fs::write(
+                self.prefix.join(domain).join(TAGS_FILE_NAME),
+                serde_json::to_vec(&domain_tags)?,
+            )?;
+            info!(
+                "domain:{domain}, tags:{:?} and channels:{:?} of version:{version} are removed",
+                removed.tags.keys(),
+                removed.channels.keys()
+            );
+        }
+        Ok(removed)
+    }
+
+    // names which are used by others after taken are skipped
+    fn restore_version_tags(&self, domain: &str, removed: DomainTags) -> anyhow::Result<()> {
+        let mut domain_tags = self.get_domain_tags(domain);
+        for (name, version) in removed.tags {
+            if domain_tags.tags.contains_key(&name) || domain_tags.channels.contains_key(&name) {
+                warn!(
+                    "domain:{domain}, tag:{name} is used, it would not be restored to version:{version}"
+                );
+            } else {
+                domain_tags.tags.insert(name, version);
+            }
+        }
+        for (name, version) in removed.channels {
+            if domain_tags.tags.contains_key(&name) || domain_tags.channels.contains_key(&name) {
+                warn!(
+                    "domain:{domain}, channel:{name} is used, it would not be restored to version:{version}"
+                );
+            } else {
+                domain_tags.channels.insert(name, version);
+            }
         }
+        fs::write(
+            self.prefix.join(domain).join(TAGS_FILE_NAME),
+            serde_json::to_vec(&domain_tags)?,
+        )?;
         Ok(())
     }
 
+    pub fn get_pinned_versions(&self, domain: &str) -> Vec<u32> {
+        let mut versions: Vec<u32> =
+            fs::read_to_string(self.prefix.join(domain).join(PINNED_FILE_NAME))
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| line.trim().parse::<u32>().ok())
+                .collect();
+        versions.sort_unstable();
+        versions.dedup();
+        versions
+    }
+
+    // pinned version is kept by retention and can't be deleted
+    pub fn pin_version(&self, domain: &str, version: u32, pinned: bool) -> anyhow::Result<()> {
+        let path = self.get_existing_version_path(domain, version)?;
+        let mut versions = self.get_pinned_versions(domain);
+        if pinned {
+            if self.check_is_in_upload_process(domain, &version)
+                || path.join(UPLOADING_FILE_NAME).exists()
+            {
+                bail!(ApiError::new(
+                    ErrorCode::VersionUploading,
+                    format!(
+                        "domain:{domain}, version:{version} is in uploading, can not be pinned"
+                    )
+                ));
+            }
+            if versions.contains(&version) {
+                return Ok(());
+            }
+            versions.push(version);
+            versions.sort_unstable();
+        } else {
+            if !versions.contains(&version) {
+                return Ok(());
+            }
+            versions.retain(|x| *x != version);
+        }
+        let content: String = versions.iter().map(|x| format!("{x}\n")).collect();
+        fs::write(self.prefix.join(domain).join(PINNED_FILE_NAME), content)?;
+        info!("domain:{domain}, version:{version} pinned: {pinned}");
+        Ok(())
+    }
+
+    // versions which are not kept by retention policy, serving, uploading and pinned versions are always kept
+    pub fn get_versions_out_of_retention(
+        &self,
+        info: &DomainInfo,
+        retention: &RetentionPolicy,
+    ) -> Vec<u32> {
+        let mut finished: Vec<u32> = info
+            .versions
+            .iter()
+            .copied()
+            .filter(|version| {
+                !self.check_is_in_upload_process(&info.domain, version)
+                    && !self
+                        .get_version_path(&info.domain, *version)
+                        .join(UPLOADING_FILE_NAME)
+                        .exists()
+            })
+            .collect();
+        finished.sort_unstable_by(|a, b| b.cmp(a));
+        let max_reserve = retention.max_reserve.unwrap_or(0) as usize;
+        let deadline = retention
+            .max_age_days
+            .map(|days| Utc::now() - TimeDelta::days(days as i64));
+        let mut out_of_retention: Vec<u32> = finished
+            .into_iter()
+            .skip(max_reserve)
+            .filter(|version| {
+                Some(*version) != info.current_version && !info.pinned_versions.contains(version)
+            })
+            .filter(|version| match deadline {
+                // the version directory is modified at last when finishing upload
+                Some(deadline) => fs::metadata(self.get_version_path(&info.domain, *version))
+                    .and_then(|x| x.modified())
+                    .is_ok_and(|x| DateTime::<Utc>::from(x) < deadline),
+                None => true,
+            })
+            .collect();
+        out_of_retention.sort_unstable();
+        out_of_retention
+    }
+
+    // abort uploading versions which have no activity in idle_timeout, return the number of them
+    pub fn expire_upload_sessions(&self, idle_timeout: TimeDelta) -> usize {
+        let deadline = Utc::now() - idle_timeout;
+        let expired: Vec<(String, u32)> = self
+            .uploading_status
+            .iter()
+            .filter(|x| x.last_activity < deadline && !x.finishing)
+            .map(|x| (x.key().clone(), x.version))
+            .collect();
+        let mut count = 0;
+        for (domain, version) in expired {
+            // it may be active again or finished after collecting
+            let Some((_, session)) = self.uploading_status.remove_if(&domain, |_, v| {
+                v.version == version && v.last_activity < deadline && !v.finishing
+            }) else {
+                continue;
+            };
+            let path = self.get_version_path(&domain, version);
+            if path.exists()
+                && let Err(e) = fs::remove_dir_all(&path)
+            {
+                warn!(
+                    "remove expired upload directory {} failure: {e}",
+                    path.display()
+                );
+            }
+            self.remove_version_metadata(&domain, version);
+            if let Err(e) = self.take_version_tags(&domain, version) {
+                warn!("remove tags of domain:{domain}, version:{version} failure: {e}");
+            }
+            info!(
+                "domain:{domain}, version:{version} upload is expired, last activity: {}",
+                session.last_activity
+            );
+            self.events.emit(DomainEventData::UploadExpire {
+                domain,
+                version,
+                last_activity: session.last_activity,
+            });
+            count += 1;
+        }
+        count
+    }
+
     // No Check, who use this must check if illegal to delete files
     pub fn remove_domain_version(
         &self,
@@ -668,7 +1858,7 @@ impl DomainStorage {
         if let Some(version) = version {
             path = path.join(version.to_string());
             if path.exists() {
-                fs::remove_dir_all(path)?;
+                self.move_to_trash(domain, version, &path)?;
                 return Ok(true);
             }
         } else if path.exists() {
@@ -683,6 +1873,147 @@ impl DomainStorage {
         Ok(false)
     }
 
+    fn move_to_trash(&self, domain: &str, version: u32, path: &Path) -> anyhow::Result<()> {
+        let deleted_at = Utc::now();
+        let id = format!(
+            "{}-{}-{version}",
+            deleted_at.timestamp_millis(),
+            domain.replace('/', "_")
+        );
+        let item_path = self.prefix.join(TRASH_DIR_NAME).join(&id);
+        fs::create_dir_all(&item_path)?;
+        let item = TrashItem {
+            id,
+            domain: domain.to_string(),
+            version,
+            deleted_at,
+        };
+        fs::write(
+            item_path.join(TRASH_META_FILE_NAME),
+            serde_json::to_vec(&item)?,
+        )?;
+        fs::rename(path, item_path.join(TRASH_FILES_DIR_NAME))?;
+        let metadata_path = self.get_version_metadata_path(domain, version);
+        if metadata_path.exists() {
+            fs::rename(
+                metadata_path,
+                item_path.join(TRASH_VERSION_METADATA_FILE_NAME),
+            )?;
+        }
+        let tags = self.take_version_tags(domain, version)?;
+        if !tags.tags.is_empty() || !tags.channels.is_empty() {
+            fs::write(
+                item_path.join(TRASH_TAGS_FILE_NAME),
+                serde_json::to_vec(&tags)?,
+            )?;
+        }
+        info!(
+            "domain:{domain}, version:{version} is moved to trash: {}",
+            item.id
+        );
+        Ok(())
+    }
+
+    // domain: also includes items of sub path, sorted by deleted time
+    pub fn list_trash(&self, domain: Option<&str>) -> anyhow::Result<Vec<TrashItem>> {
+        let trash_path = self.prefix.join(TRASH_DIR_NAME);
+        if !trash_path.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut items: Vec<TrashItem> = fs::read_dir(trash_path)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let meta = fs::read(entry.path().join(TRASH_META_FILE_NAME)).ok()?;
+                let mut item = serde_json::from_slice::<TrashItem>(&meta).ok()?;
+                item.id = entry.file_name().to_str()?.to_string();
+                Some(item)
+            })
+            .filter(|item| domain.is_none_or(|domain| is_same_or_sub_domain(domain, &item.domain)))
+            .collect();
+        items.sort_by(|a, b| a.deleted_at.cmp(&b.deleted_at).then(a.id.cmp(&b.id)));
+        Ok(items)
+    }
+
+    pub fn get_trash_item(&self, id: &str) -> anyhow::Result<TrashItem> {
+        self.list_trash(None)?
+            .into_iter()
+            .find(|item| item.id == id)
+            .ok_or_else(|| {
+                ApiError::new(
+                    ErrorCode::TrashNotFound,
+                    format!("trash: {id} does not exist"),
+                )
+                .into()
+            })
+    }
+
+    // move the version back, it's not released
+    pub fn restore_trash(&self, id: &str) -> anyhow::Result<TrashItem> {
+        let item = self.get_trash_item(id)?;
+        let (domain, version) = (&item.domain, item.version);
+        let path = self.get_version_path(domain, version);
+        if path.exists() {
+            bail!(ApiError::new(
+                ErrorCode::VersionExists,
+                format!("domain:{domain}, version:{version} already exists")
+            ));
+        }
+        self.check_if_can_upload(domain)?;
+        let (host, sub_path) = get_host_path_from_domain(domain);
+        fs::create_dir_all(self.prefix.join(domain))?;
+        let multiple = self.prefix.join(host).join(MULTIPLE_WEB_FILE_NAME);
+        if !sub_path.is_empty() && !multiple.exists() {
+            File::create(multiple)?;
+        }
+        let item_path = self.prefix.join(TRASH_DIR_NAME).join(&item.id);
+        fs::rename(item_path.join(TRASH_FILES_DIR_NAME), &path)?;
+        let metadata_path = item_path.join(TRASH_VERSION_METADATA_FILE_NAME);
+        if metadata_path.exists() {
+            let restored_path = self.get_version_metadata_path(domain, version);
+            fs::create_dir_all(self.prefix.join(domain).join(VERSION_METADATA_DIR_NAME))?;
+            fs::rename(metadata_path, restored_path)?;
+        }
+        if let Some(tags) = fs::read(item_path.join(TRASH_TAGS_FILE_NAME))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<DomainTags>(&data).ok())
+        {
+            self.restore_version_tags(domain, tags)?;
+        }
+        fs::remove_dir_all(item_path)?;
+        info!("domain:{domain}, version:{version} is restored from trash: {id}");
+        Ok(item)
+    }
+
+    // remove trash items deleted before `before`, or all of them if it's None
+    pub fn purge_trash(
+        &self,
+        domain: Option<&str>,
+        id: Option<&str>,
+        before: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Vec<TrashItem>> {
+        let items = match id {
+            Some(id) => vec![self.get_trash_item(id)?],
+            None => self.list_trash(domain)?,
+        };
+        let mut purged = Vec::new();
+        for item in items {
+            if before.is_some_and(|before| item.deleted_at >= before) {
+                continue;
+            }
+            fs::remove_dir_all(self.prefix.join(TRASH_DIR_NAME).join(&item.id))?;
+            info!(
+                "domain:{}, version:{} is purged from trash: {}",
+                item.domain, item.version, item.id
+            );
+            purged.push(item);
+        }
+        Ok(purged)
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
     pub fn check_if_can_upload(&self, domain: &str) -> anyhow::Result<()> {
         match get_host_path_from_domain(domain) {
             (host, "") => {
@@ -692,7 +2023,10 @@ impl DomainStorage {
                     .get(host)
                     .is_some_and(|x| matches!(x.value(), DomainMeta::MultipleWeb(..)))
                 {
-                    bail!("this domain already has multiple SPA!")
+                    bail!(ApiError::new(
+                        ErrorCode::MixedSingleMultiple,
+                        "this domain already has multiple SPA!"
+                    ))
                 }
             }
             (host, _) => {
@@ -701,7 +2035,10 @@ impl DomainStorage {
                     .get(host)
                     .is_some_and(|x| matches!(x.value(), DomainMeta::OneWeb(..)))
                 {
-                    bail!("this domain already has single SPA!")
+                    bail!(ApiError::new(
+                        ErrorCode::MixedSingleMultiple,
+                        "this domain already has single SPA!"
+                    ))
                 }
             }
         };
@@ -726,6 +2063,25 @@ pub fn md5_file(path: impl AsRef<Path>, byte_buffer: &mut [u8]) -> Option<String
     })
 }
 
+// write tar.gz of version, the paths in archive are relative to version
+pub fn write_version_archive(version_path: &Path, writer: impl Write) -> anyhow::Result<()> {
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    for entry in WalkDir::new(version_path).min_depth(1).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_name() == UPLOADING_FILE_NAME {
+            continue;
+        }
+        let name = entry.path().strip_prefix(version_path)?;
+        if entry.file_type().is_dir() {
+            builder.append_dir(name, entry.path())?;
+        } else if entry.file_type().is_file() {
+            builder.append_path_with_name(entry.path(), name)?;
+        }
+    }
+    builder.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
 pub fn sanitize_path(base: impl AsRef<Path>, tail: &str) -> anyhow::Result<PathBuf> {
     let mut buf = PathBuf::from(base.as_ref());
     let p = match percent_encoding::percent_decode_str(tail).decode_utf8() {
@@ -758,9 +2114,13 @@ mod test {
     use crate::config::Config;
     use crate::domain_storage::{DomainStorage, URI_REGEX_STR};
     use crate::file_cache::FileCache;
+    use chrono::TimeDelta;
+    use entity::error::{ApiError, ErrorCode};
+    use entity::storage::UploadingStatus;
     use regex::Regex;
     use salvo::http::uri::Uri;
     use std::env;
+    use std::fs;
     use std::fs::OpenOptions;
     use std::io::Read;
     use std::ops::RangeInclusive;
@@ -848,6 +2208,52 @@ mod test {
         println!("{:?}", result);
     }
 
+    #[tokio::test]
+    async fn test_finishing_lock() {
+        let dir = env::temp_dir().join("spa_server_finishing_lock");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("web")).unwrap();
+        let data = dir.join("data.txt");
+        fs::write(&data, "data").unwrap();
+        let storage = DomainStorage::init(dir.join("web"), FileCache::new()).unwrap();
+        let domain = "finishing.example.com".to_string();
+        let code = |result: anyhow::Result<()>| {
+            result
+                .unwrap_err()
+                .downcast_ref::<ApiError>()
+                .map(|x| x.code)
+        };
+        storage
+            .update_uploading_status(domain.clone(), 1, UploadingStatus::Uploading)
+            .await
+            .unwrap();
+        let save = || storage.save_file(domain.clone(), 1, "a.txt".to_string(), &data);
+        save().unwrap();
+
+        let lock = storage.lock_finishing(&domain, 1).unwrap();
+        assert_eq!(
+            code(storage.lock_finishing(&domain, 1).map(|_| ())),
+            Some(ErrorCode::VersionUploading)
+        );
+        assert_eq!(code(save()), Some(ErrorCode::VersionNotUploading));
+        assert_eq!(
+            code(storage.abort_uploading(&domain, 1)),
+            Some(ErrorCode::VersionUploading)
+        );
+        assert_eq!(storage.expire_upload_sessions(TimeDelta::zero()), 0);
+        // keeps uploading if it's not finished
+        drop(lock);
+        save().unwrap();
+
+        let lock = storage.lock_finishing(&domain, 1).unwrap();
+        storage.finish_and_release(lock, "admin").await.unwrap();
+        assert_eq!(storage.get_domain_serving_version(&domain), Some(1));
+        assert_eq!(code(save()), Some(ErrorCode::VersionNotUploading));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[ignore]
     #[test]
     fn test_file_read() {
@@ -0,0 +1,106 @@
+use entity::event::{DomainEvent, DomainEventData};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+// keep recent events in memory, so client could replay them after reconnecting
+const RECENT_EVENTS_SIZE: usize = 100;
+
+struct RecentEvents {
+    last_id: u64,
+    events: VecDeque<DomainEvent>,
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+    recent: Mutex<RecentEvents>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus {
+            sender: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            recent: Mutex::new(RecentEvents {
+                last_id: 0,
+                events: VecDeque::with_capacity(RECENT_EVENTS_SIZE),
+            }),
+        }
+    }
+
+    pub fn emit(&self, data: DomainEventData) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.last_id += 1;
+        let event = DomainEvent {
+            id: recent.last_id,
+            time: chrono::Utc::now(),
+            data,
+        };
+        debug!("emit event: {event:?}");
+        if recent.events.len() == RECENT_EVENTS_SIZE {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(event.clone());
+        // it's ok if there's no receiver
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+
+    // recent events after last_id, and receiver of events after them, without gap.
+    pub fn subscribe_since(
+        &self,
+        last_id: u64,
+    ) -> (Vec<DomainEvent>, broadcast::Receiver<DomainEvent>) {
+        let recent = self.recent.lock().unwrap();
+        let events = recent
+            .events
+            .iter()
+            .filter(|x| x.id > last_id)
+            .cloned()
+            .collect();
+        (events, self.sender.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::event::{EventBus, RECENT_EVENTS_SIZE};
+    use entity::event::DomainEventData;
+
+    fn release(version: u32) -> DomainEventData {
+        DomainEventData::Release {
+            domain: "www.example.com".to_string(),
+            version,
+        }
+    }
+
+    #[tokio::test]
+    async fn event_bus_replay_and_subscribe() {
+        let bus = EventBus::new();
+        for version in 1..=RECENT_EVENTS_SIZE as u32 + 2 {
+            bus.emit(release(version));
+        }
+        let (events, _) = bus.subscribe_since(0);
+        assert_eq!(events.len(), RECENT_EVENTS_SIZE);
+        assert_eq!(events[0].id, 3);
+        let (events, mut receiver) = bus.subscribe_since(100);
+        assert_eq!(
+            events.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![101, 102]
+        );
+        bus.emit(release(200));
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.id, 103);
+        assert_eq!(event.data, release(200));
+    }
+}
@@ -0,0 +1,401 @@
+use crate::auth::{AdminIdentity, Permission, pattern_regex};
+use crate::config::{JwtConfig, JwtRuleConfig};
+use anyhow::{Context, bail};
+use jsonwebtoken::jwk::{Jwk, JwkSet, PublicKeyUse};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::fs;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+struct JwtRule {
+    name: String,
+    claims: Vec<(String, Regex)>,
+    permission: Arc<Permission>,
+}
+
+impl JwtRule {
+    fn new(conf: &JwtRuleConfig) -> anyhow::Result<Self> {
+        let claims = conf
+            .claims
+            .iter()
+            .map(|(key, pattern)| {
+                pattern_regex(pattern)
+                    .map(|regex| (key.clone(), regex))
+                    .with_context(|| {
+                        format!(
+                            "jwt rule {} has invalid claim pattern: {pattern}",
+                            conf.name
+                        )
+                    })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(JwtRule {
+            name: conf.name.clone(),
+            claims,
+            permission: Arc::new(Permission::new(&conf.domains, &conf.actions)?),
+        })
+    }
+
+    // array claim like groups matches if any item matches
+    fn is_match(&self, claims: &Map<String, Value>) -> bool {
+        self.claims
+            .iter()
+            .all(|(key, pattern)| match claims.get(key) {
+                Some(Value::String(value)) => pattern.is_match(value),
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .any(|value| value.as_str().is_some_and(|value| pattern.is_match(value))),
+                Some(Value::Null) | None => false,
+                Some(value) => pattern.is_match(&value.to_string()),
+            })
+    }
+}
+
+enum JwksSource {
+    File,
+    Url(String),
+}
+
+pub struct JwtValidator {
+    source: JwksSource,
+    jwks: RwLock<Arc<JwkSet>>,
+    // lock it when fetching jwks url, avoid fetching concurrently
+    last_fetch: Mutex<Option<Instant>>,
+    refresh_interval: Duration,
+    validation: Validation,
+    rules: Vec<JwtRule>,
+    client: reqwest::Client,
+}
+
+impl JwtValidator {
+    pub fn new(conf: &JwtConfig) -> anyhow::Result<Self> {
+        if conf.rules.is_empty() {
+            bail!("admin_config.jwt.rules should be set")
+        }
+        let rules = conf
+            .rules
+            .iter()
+            .map(JwtRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (source, jwks) =
+            if conf.jwks.starts_with("http://") || conf.jwks.starts_with("https://") {
+                // fetch it when validating the first token
+                (
+                    JwksSource::Url(conf.jwks.clone()),
+                    JwkSet { keys: Vec::new() },
+                )
+            } else {
+                (JwksSource::File, load_jwks_file(&conf.jwks)?)
+            };
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_issuer(&[&conf.issuer]);
+        validation.set_audience(&[&conf.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        validation.validate_nbf = true;
+        validation.leeway = conf.leeway;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(JwtValidator {
+            source,
+            jwks: RwLock::new(Arc::new(jwks)),
+            last_fetch: Mutex::new(None),
+            refresh_interval: Duration::from_secs(conf.jwks_refresh_interval),
+            validation,
+            rules,
+            client,
+        })
+    }
+
+    pub async fn validate(&self, token: &str) -> Option<AdminIdentity> {
+        let header = decode_header(token).ok()?;
+        let Some(jwk) = self.find_key(header.kid.as_deref()).await else {
+            debug!("jwt auth: no key found for kid: {:?}", header.kid);
+            return None;
+        };
+        // alg of key is preferred, don't trust the token header
+        let algorithm = jwk
+            .common
+            .key_algorithm
+            .and_then(|alg| Algorithm::from_str(&alg.to_string()).ok())
+            .unwrap_or(header.alg);
+        let key = DecodingKey::from_jwk(&jwk).ok()?;
+        let mut validation = self.validation.clone();
+        validation.algorithms = vec![algorithm];
+        let claims = match decode::<Map<String, Value>>(token, &key, &validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                debug!("jwt auth: invalid token, {e}");
+                return None;
+            }
+        };
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        match self.rules.iter().find(|rule| rule.is_match(&claims)) {
+            Some(rule) => Some(AdminIdentity::new(
+                format!("{}:{subject}", rule.name),
+                rule.permission.clone(),
+            )),
+            None => {
+                warn!("jwt auth: no rule matches token of subject: {subject}");
+                None
+            }
+        }
+    }
+
+    async fn find_key(&self, kid: Option<&str>) -> Option<Jwk> {
+        if let Some(jwk) = self.find_cached_key(kid) {
+            return Some(jwk);
+        }
+        // keys may be rotated, fetch it again
+        let JwksSource::Url(url) = &self.source else {
+            return None;
+        };
+        let mut last_fetch = self.last_fetch.lock().await;
+        // other request may have fetched it
+        if let Some(jwk) = self.find_cached_key(kid) {
+            return Some(jwk);
+        }
+        if last_fetch.is_some_and(|x| x.elapsed() < self.refresh_interval) {
+            return None;
+        }
+        *last_fetch = Some(Instant::now());
+        match self.fetch_jwks(url).await {
+            Ok(jwks) => {
+                info!("jwt auth: fetch {} keys from {url}", jwks.keys.len());
+                *self.jwks.write().unwrap() = Arc::new(jwks);
+            }
+            Err(e) => {
+                warn!("jwt auth: fetch jwks from {url} failed: {e:?}");
+                return None;
+            }
+        }
+        self.find_cached_key(kid)
+    }
+
+    fn find_cached_key(&self, kid: Option<&str>) -> Option<Jwk> {
+        let jwks = self.jwks.read().unwrap().clone();
+        let mut keys = jwks
+            .keys
+            .iter()
+            .filter(|jwk| !matches!(jwk.common.public_key_use, Some(PublicKeyUse::Encryption)));
+        match kid {
+            Some(kid) => keys
+                .find(|jwk| jwk.common.key_id.as_deref() == Some(kid))
+                .cloned(),
+            // token without kid is only allowed when there is single key
+            None => {
+                let key = keys.next().cloned();
+                if keys.next().is_some() { None } else { key }
+            }
+        }
+    }
+
+    async fn fetch_jwks(&self, url: &str) -> anyhow::Result<JwkSet> {
+        let body = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        serde_json::from_slice(&body).context("parse jwks error")
+    }
+}
+
+fn load_jwks_file(path: &str) -> anyhow::Result<JwkSet> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("can not read jwks file: {path}"))?;
+    serde_json::from_str(&text).with_context(|| format!("parse jwks file error: {path}"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{AdminAction, JwtConfig, JwtRuleConfig};
+    use crate::jwt::JwtValidator;
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode, get_current_timestamp};
+    use ring::rand::SystemRandom;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+    use salvo::prelude::*;
+    use serde_json::{Value, json};
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    // generate ES256 key pair, return private key and public jwk
+    fn generate_key(kid: &str) -> (EncodingKey, Value) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        // uncompressed point: 0x04 | x | y
+        let point = key_pair.public_key().as_ref();
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "use": "sig",
+            "alg": "ES256",
+            "kid": kid,
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        });
+        (EncodingKey::from_ec_der(pkcs8.as_ref()), jwk)
+    }
+
+    fn sign(key: &EncodingKey, kid: &str, claims: Value) -> String {
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(kid.to_string());
+        encode(&header, &claims, key).unwrap()
+    }
+
+    fn claims(sub: &str, git_ref: &str) -> Value {
+        json!({
+            "iss": "https://issuer.example.com",
+            "aud": "spa-server",
+            "sub": sub,
+            "exp": get_current_timestamp() + 300,
+            "repository": sub,
+            "ref": git_ref,
+        })
+    }
+
+    fn jwt_config(jwks: String) -> JwtConfig {
+        JwtConfig {
+            jwks,
+            issuer: "https://issuer.example.com".to_string(),
+            audience: "spa-server".to_string(),
+            jwks_refresh_interval: 0,
+            leeway: 0,
+            rules: vec![
+                JwtRuleConfig {
+                    name: "ci".to_string(),
+                    claims: [
+                        ("repository".to_string(), "fornetcode/*".to_string()),
+                        ("ref".to_string(), "refs/heads/main".to_string()),
+                    ]
+                    .into(),
+                    domains: vec!["www.example.com".to_string()],
+                    actions: vec![AdminAction::Upload, AdminAction::Release],
+                },
+                JwtRuleConfig {
+                    name: "ops".to_string(),
+                    claims: [("groups".to_string(), "ops".to_string())].into(),
+                    domains: vec!["*".to_string()],
+                    actions: AdminAction::ALL.to_vec(),
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn jwt_validate_with_jwks_file() {
+        let (key, jwk) = generate_key("key1");
+        let (other_key, _) = generate_key("key1");
+        let path = env::temp_dir().join(format!("spa-server-jwks-{}.json", std::process::id()));
+        fs::write(&path, json!({ "keys": [jwk] }).to_string()).unwrap();
+        let validator = JwtValidator::new(&jwt_config(path.display().to_string())).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let token = sign(&key, "key1", claims("fornetcode/www", "refs/heads/main"));
+        let identity = validator.validate(&token).await.unwrap();
+        assert_eq!(identity.name, "ci:fornetcode/www");
+        assert!(identity.is_allowed("www.example.com", AdminAction::Release));
+        assert!(!identity.is_allowed("www.example.com", AdminAction::Delete));
+        assert!(!identity.is_allowed("www.other.com", AdminAction::Upload));
+
+        let mut ops = claims("alice", "");
+        ops["groups"] = json!(["dev", "ops"]);
+        let identity = validator.validate(&sign(&key, "key1", ops)).await.unwrap();
+        assert_eq!(identity.name, "ops:alice");
+        assert!(identity.is_allowed_all(AdminAction::Delete));
+
+        // no rule matches
+        let token = sign(&key, "key1", claims("fornetcode/www", "refs/heads/dev"));
+        assert!(validator.validate(&token).await.is_none());
+        let token = sign(&key, "key1", claims("other/www", "refs/heads/main"));
+        assert!(validator.validate(&token).await.is_none());
+
+        let mut wrong_issuer = claims("fornetcode/www", "refs/heads/main");
+        wrong_issuer["iss"] = json!("https://other.example.com");
+        assert!(
+            validator
+                .validate(&sign(&key, "key1", wrong_issuer))
+                .await
+                .is_none()
+        );
+        let mut wrong_audience = claims("fornetcode/www", "refs/heads/main");
+        wrong_audience["aud"] = json!("other");
+        assert!(
+            validator
+                .validate(&sign(&key, "key1", wrong_audience))
+                .await
+                .is_none()
+        );
+        let mut expired = claims("fornetcode/www", "refs/heads/main");
+        expired["exp"] = json!(get_current_timestamp() - 10);
+        assert!(
+            validator
+                .validate(&sign(&key, "key1", expired))
+                .await
+                .is_none()
+        );
+
+        let token = sign(&key, "key2", claims("fornetcode/www", "refs/heads/main"));
+        assert!(validator.validate(&token).await.is_none());
+        let token = sign(
+            &other_key,
+            "key1",
+            claims("fornetcode/www", "refs/heads/main"),
+        );
+        assert!(validator.validate(&token).await.is_none());
+        assert!(validator.validate("not.a.token").await.is_none());
+    }
+
+    struct JwksHandler(Arc<Mutex<Value>>);
+
+    #[async_trait]
+    impl Handler for JwksHandler {
+        async fn handle(
+            &self,
+            _req: &mut Request,
+            _depot: &mut Depot,
+            res: &mut Response,
+            _ctrl: &mut FlowCtrl,
+        ) {
+            res.render(self.0.lock().unwrap().to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn jwt_fetch_rotated_jwks_url() {
+        let (key1, jwk1) = generate_key("key1");
+        let (key2, jwk2) = generate_key("key2");
+        let jwks = Arc::new(Mutex::new(json!({ "keys": [jwk1] })));
+        let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+        let addr = acceptor.local_addr().unwrap();
+        let router = Router::with_path("jwks").get(JwksHandler(jwks.clone()));
+        tokio::spawn(Server::new(acceptor).serve(router));
+
+        let validator = JwtValidator::new(&jwt_config(format!("http://{addr}/jwks"))).unwrap();
+        let token = sign(&key1, "key1", claims("fornetcode/www", "refs/heads/main"));
+        assert!(validator.validate(&token).await.is_some());
+
+        *jwks.lock().unwrap() = json!({ "keys": [jwk2] });
+        let token = sign(&key2, "key2", claims("fornetcode/www", "refs/heads/main"));
+        assert!(validator.validate(&token).await.is_some());
+        // key1 is removed from jwks
+        let token = sign(&key1, "key1", claims("fornetcode/www", "refs/heads/main"));
+        assert!(validator.validate(&token).await.is_none());
+    }
+}
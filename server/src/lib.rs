@@ -2,23 +2,34 @@
 //#![allow(unused_variables)]
 
 pub mod admin_server;
+pub mod audit;
+pub mod auth;
 pub mod config;
 pub mod domain_storage;
+pub mod event;
 pub mod file_cache;
+pub mod jwt;
+pub mod limiter;
+pub mod tls;
 mod web_server;
+pub mod webhook;
 
 pub mod service;
 
 use crate::admin_server::AdminServer;
-use crate::config::{AdminConfig, Config};
+use crate::audit::{AUDIT_LOG_FILE_NAME, AuditLog};
+use crate::config::{AdminConfig, Config, RetentionPolicy};
 use crate::domain_storage::DomainStorage;
 use crate::file_cache::FileCache;
+use crate::limiter::{HttpMetrics, RateLimiter};
 use crate::service::ServiceConfig;
 use crate::web_server::init_http_server;
+use crate::webhook::run_webhooks;
 use delay_timer::entity::DelayTimer;
 use delay_timer::prelude::DelayTimerBuilder;
 use futures_util::TryFutureExt;
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use tracing::error;
 
@@ -27,8 +38,19 @@ async fn run_admin_server(
     storage: Arc<DomainStorage>,
     delay_timer: DelayTimer,
     host_alias: Arc<HashMap<String, String>>,
+    domain_retention: Arc<HashMap<String, RetentionPolicy>>,
+    http_metrics: Arc<HttpMetrics>,
+    audit_log: Arc<AuditLog>,
 ) -> anyhow::Result<()> {
-    let admin_server = AdminServer::new(config, storage.clone(), delay_timer, host_alias);
+    let admin_server = AdminServer::new(
+        config,
+        storage.clone(),
+        delay_timer,
+        host_alias,
+        domain_retention,
+        http_metrics,
+        audit_log,
+    );
     admin_server.run().await
 }
 
@@ -44,12 +66,19 @@ pub async fn run_server_with_config(config: Config) -> anyhow::Result<()> {
     let domain_storage = Arc::new(DomainStorage::init(&config.file_dir, cache)?);
     let service_config = Arc::new(ServiceConfig::new(&config));
     let host_alias = service_config.host_alias.clone();
+    let domain_retention = service_config.domain_retention.clone();
+    let limiter = Arc::new(RateLimiter::new(&config));
 
     if let Some(admin_config) = &config.admin_config {
         tracing::info!("admin server enabled");
         let delay_timer = DelayTimerBuilder::default()
             .tokio_runtime_by_default()
             .build();
+        let audit_log = match &admin_config.audit_log {
+            Some(path) => AuditLog::new(path)?,
+            None => AuditLog::new(Path::new(&config.file_dir).join(AUDIT_LOG_FILE_NAME))?,
+        };
+        run_webhooks(&admin_config.webhooks, &domain_storage)?;
 
         let _ = tokio::join!(
             run_admin_server(
@@ -57,16 +86,19 @@ pub async fn run_server_with_config(config: Config) -> anyhow::Result<()> {
                 domain_storage.clone(),
                 delay_timer,
                 host_alias,
+                domain_retention,
+                limiter.metrics.clone(),
+                Arc::new(audit_log),
             )
             .map_err(|error| {
                 error!("init admin server error: {error}");
                 panic!("admin server error: {error}")
             }),
-            init_http_server(config.clone(), service_config, domain_storage),
+            init_http_server(config.clone(), service_config, domain_storage, limiter),
         );
     } else {
         tracing::info!("admin server disabled");
-        init_http_server(config, service_config, domain_storage).await?
+        init_http_server(config, service_config, domain_storage, limiter).await?
     }
     Ok(())
 }
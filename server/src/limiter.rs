@@ -0,0 +1,344 @@
+use crate::config::{Config, HttpConfig, RateLimitConfig};
+use dashmap::{DashMap, DashSet};
+use salvo::async_trait;
+use salvo::fuse::{FlexFactory, FlexFusewire, FuseEvent, FuseFactory, FuseInfo, Fusewire};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+#[derive(Debug, Default)]
+pub struct HttpMetrics {
+    pub active_connections: AtomicU64,
+    pub rejected_connections: AtomicU64,
+    pub rate_limited_global: AtomicU64,
+    pub rate_limited_domain: AtomicU64,
+}
+
+impl HttpMetrics {
+    // prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "# TYPE spa_http_active_connections gauge");
+        let _ = writeln!(
+            text,
+            "spa_http_active_connections {}",
+            self.active_connections.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(text, "# TYPE spa_http_rejected_connections_total counter");
+        let _ = writeln!(
+            text,
+            "spa_http_rejected_connections_total {}",
+            self.rejected_connections.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(text, "# TYPE spa_http_rate_limited_total counter");
+        let _ = writeln!(
+            text,
+            "spa_http_rate_limited_total{{scope=\"global\"}} {}",
+            self.rate_limited_global.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            text,
+            "spa_http_rate_limited_total{{scope=\"domain\"}} {}",
+            self.rate_limited_domain.load(Ordering::Relaxed)
+        );
+        text
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        TokenBucket {
+            tokens: config.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, config: &RateLimitConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.rate as f64).min(config.burst as f64);
+        self.updated_at = now;
+    }
+
+    // return the duration to wait for next token if bucket is empty
+    fn try_acquire(&mut self, config: &RateLimitConfig, now: Instant) -> Result<(), Duration> {
+        self.refill(config, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / config.rate as f64,
+            ))
+        }
+    }
+
+    fn is_full(&self, config: &RateLimitConfig, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens + elapsed * config.rate as f64 >= config.burst as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitScope {
+    Global,
+    Domain,
+}
+
+// token bucket per client ip, global one is shared by all domains.
+pub struct RateLimiter {
+    global: Option<RateLimitConfig>,
+    domains: HashMap<String, RateLimitConfig>,
+    global_buckets: DashMap<IpAddr, TokenBucket>,
+    domain_buckets: DashMap<(String, IpAddr), TokenBucket>,
+    pub metrics: Arc<HttpMetrics>,
+}
+
+impl RateLimiter {
+    pub fn new(conf: &Config) -> Self {
+        let domains = conf
+            .domains
+            .iter()
+            .filter_map(|domain| {
+                domain
+                    .rate_limit
+                    .clone()
+                    .map(|rate_limit| (domain.domain.clone(), rate_limit))
+            })
+            .collect();
+        RateLimiter {
+            global: conf.http.rate_limit.clone(),
+            domains,
+            global_buckets: DashMap::new(),
+            domain_buckets: DashMap::new(),
+            metrics: Arc::new(HttpMetrics::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.global.is_some() || !self.domains.is_empty()
+    }
+
+    // host should be the original host, not alias.
+    pub fn check(&self, host: &str, ip: IpAddr) -> Result<(), (RateLimitScope, Duration)> {
+        self.check_at(host, ip, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        host: &str,
+        ip: IpAddr,
+        now: Instant,
+    ) -> Result<(), (RateLimitScope, Duration)> {
+        if let Some(config) = &self.global {
+            self.global_buckets
+                .entry(ip)
+                .or_insert_with(|| TokenBucket::new(config, now))
+                .try_acquire(config, now)
+                .map_err(|wait| {
+                    self.metrics
+                        .rate_limited_global
+                        .fetch_add(1, Ordering::Relaxed);
+                    (RateLimitScope::Global, wait)
+                })?;
+        }
+        if let Some(config) = self.domains.get(host) {
+            self.domain_buckets
+                .entry((host.to_string(), ip))
+                .or_insert_with(|| TokenBucket::new(config, now))
+                .try_acquire(config, now)
+                .map_err(|wait| {
+                    self.metrics
+                        .rate_limited_domain
+                        .fetch_add(1, Ordering::Relaxed);
+                    (RateLimitScope::Domain, wait)
+                })?;
+        }
+        Ok(())
+    }
+
+    // full buckets are the same as new ones, remove them to keep memory bounded.
+    pub fn purge_idle(&self) {
+        let now = Instant::now();
+        if let Some(config) = &self.global {
+            self.global_buckets
+                .retain(|_, bucket| !bucket.is_full(config, now));
+        }
+        self.domain_buckets.retain(|(host, _), bucket| {
+            self.domains
+                .get(host)
+                .is_some_and(|config| !bucket.is_full(config, now))
+        });
+    }
+}
+
+// connections over max_connections, their requests get 503 and then they are closed.
+#[derive(Debug, Default)]
+pub struct RejectedConnections(DashSet<SocketAddr>);
+
+impl RejectedConnections {
+    pub fn contains(&self, remote_addr: &SocketAddr) -> bool {
+        self.0.contains(remote_addr)
+    }
+}
+
+// wrap FlexFactory to count connections of the listener, and reject when it's over max_connections.
+pub struct ConnectionLimiter {
+    factory: FlexFactory,
+    max_connections: Option<u64>,
+    metrics: Arc<HttpMetrics>,
+    rejected: Arc<RejectedConnections>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(
+        conf: &HttpConfig,
+        metrics: Arc<HttpMetrics>,
+        rejected: Arc<RejectedConnections>,
+    ) -> Self {
+        ConnectionLimiter {
+            factory: FlexFactory::new(),
+            max_connections: conf.max_connections,
+            metrics,
+            rejected,
+        }
+    }
+}
+
+impl FuseFactory for ConnectionLimiter {
+    fn create(&self, info: FuseInfo) -> Arc<dyn Fusewire + Sync + Send + 'static> {
+        let active = self
+            .metrics
+            .active_connections
+            .fetch_add(1, Ordering::SeqCst)
+            + 1;
+        let mut rejected = None;
+        if self.max_connections.is_some_and(|max| active > max) {
+            self.metrics
+                .rejected_connections
+                .fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "reject connection from {}, too many connections: {active}",
+                info.remote_addr
+            );
+            if let Some(remote_addr) = info.remote_addr.clone().into_std() {
+                self.rejected.0.insert(remote_addr);
+                rejected = Some(remote_addr);
+            }
+        }
+        Arc::new(LimitedFusewire {
+            inner: self.factory.build(info),
+            metrics: self.metrics.clone(),
+            rejected: rejected.map(|x| (self.rejected.clone(), x)),
+        })
+    }
+}
+
+struct LimitedFusewire {
+    inner: FlexFusewire,
+    metrics: Arc<HttpMetrics>,
+    rejected: Option<(Arc<RejectedConnections>, SocketAddr)>,
+}
+
+#[async_trait]
+impl Fusewire for LimitedFusewire {
+    fn event(&self, event: FuseEvent) {
+        self.inner.event(event);
+    }
+
+    async fn fused(&self) {
+        self.inner.fused().await;
+    }
+}
+
+impl Drop for LimitedFusewire {
+    fn drop(&mut self) {
+        self.metrics
+            .active_connections
+            .fetch_sub(1, Ordering::SeqCst);
+        if let Some((rejected, remote_addr)) = &self.rejected {
+            rejected.0.remove(remote_addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::RateLimitConfig;
+    use crate::limiter::{RateLimitScope, RateLimiter, TokenBucket};
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    fn limiter(global: Option<RateLimitConfig>, domain: Option<RateLimitConfig>) -> RateLimiter {
+        let mut domains = HashMap::new();
+        if let Some(domain) = domain {
+            domains.insert("www.example.com".to_string(), domain);
+        }
+        RateLimiter {
+            global,
+            domains,
+            global_buckets: Default::default(),
+            domain_buckets: Default::default(),
+            metrics: Arc::new(Default::default()),
+        }
+    }
+
+    #[test]
+    fn token_bucket_refill() {
+        let config = RateLimitConfig { rate: 2, burst: 2 };
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(&config, now);
+        assert!(bucket.try_acquire(&config, now).is_ok());
+        assert!(bucket.try_acquire(&config, now).is_ok());
+        assert_eq!(
+            bucket.try_acquire(&config, now),
+            Err(Duration::from_millis(500))
+        );
+        let now = now + Duration::from_millis(500);
+        assert!(bucket.try_acquire(&config, now).is_ok());
+        assert!(!bucket.is_full(&config, now));
+        assert!(bucket.is_full(&config, now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn global_and_domain_limit() {
+        let limiter = limiter(
+            Some(RateLimitConfig { rate: 1, burst: 3 }),
+            Some(RateLimitConfig { rate: 1, burst: 1 }),
+        );
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let now = Instant::now();
+        assert!(limiter.check_at("www.example.com", ip, now).is_ok());
+        assert_eq!(
+            limiter.check_at("www.example.com", ip, now).unwrap_err().0,
+            RateLimitScope::Domain
+        );
+        assert!(limiter.check_at("www.example.com", other_ip, now).is_ok());
+        assert!(limiter.check_at("www.other.com", ip, now).is_ok());
+        assert_eq!(
+            limiter.check_at("www.other.com", ip, now).unwrap_err().0,
+            RateLimitScope::Global
+        );
+        assert_eq!(
+            limiter.metrics.rate_limited_domain.load(Ordering::Relaxed),
+            1
+        );
+        assert_eq!(
+            limiter.metrics.rate_limited_global.load(Ordering::Relaxed),
+            1
+        );
+    }
+}
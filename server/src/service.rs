@@ -1,4 +1,4 @@
-use crate::config::Config;
+use crate::config::{Config, RetentionPolicy};
 use salvo::Response;
 use salvo::http::StatusCode;
 use std::collections::HashMap;
@@ -6,6 +6,7 @@ use std::sync::Arc;
 
 pub struct ServiceConfig {
     pub host_alias: Arc<HashMap<String, String>>,
+    pub domain_retention: Arc<HashMap<String, RetentionPolicy>>,
 }
 
 impl ServiceConfig {
@@ -17,7 +18,11 @@ impl ServiceConfig {
      */
     pub fn new(conf: &Config) -> Self {
         let mut alias_map = HashMap::new();
+        let mut retention_map = HashMap::new();
         for domain in conf.domains.iter() {
+            if let Some(retention) = domain.retention {
+                retention_map.insert(domain.domain.clone(), retention);
+            }
             if let Some(alias_host_list) = domain.alias.as_ref() {
                 for alias_host in alias_host_list {
                     alias_map.insert(alias_host.clone(), domain.domain.clone());
@@ -27,6 +32,7 @@ impl ServiceConfig {
 
         ServiceConfig {
             host_alias: Arc::new(alias_map),
+            domain_retention: Arc::new(retention_map),
         }
     }
 }
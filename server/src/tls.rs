@@ -0,0 +1,213 @@
+use crate::config::AdminTlsConfig;
+use anyhow::Context as _;
+use dashmap::DashMap;
+use rustls::RootCertStore;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ServerConfig, WebPkiClientVerifier};
+use salvo::conn::tcp::{TcpAcceptor, TcpCoupler};
+use salvo::conn::{Accepted, Acceptor, Holding};
+use salvo::fuse::FuseFactory;
+use salvo::http::uri::Scheme;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{Accept, TlsAcceptor};
+use tracing::debug;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+pub fn load_server_config(conf: &AdminTlsConfig) -> anyhow::Result<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = CertificateDer::pem_file_iter(&conf.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("can not read admin tls cert: {}", conf.cert))?;
+    let key = PrivateKeyDer::from_pem_file(&conf.key)
+        .with_context(|| format!("can not read admin tls key: {}", conf.key))?;
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &conf.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(client_ca)
+                .with_context(|| format!("can not read admin tls client_ca: {client_ca}"))?
+            {
+                roots.add(cert?)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+// common name of client certificate, keyed by remote addr of the connection.
+// it's recorded after tls handshake and removed when the connection is closed.
+#[derive(Clone, Default)]
+pub struct PeerCertificates(Arc<DashMap<SocketAddr, String>>);
+
+impl PeerCertificates {
+    pub fn common_name(&self, remote_addr: &SocketAddr) -> Option<String> {
+        self.0.get(remote_addr).map(|x| x.clone())
+    }
+}
+
+fn common_name(cert: &CertificateDer) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert.as_ref()).ok()?;
+    let common_name = cert.subject().iter_common_name().next()?;
+    common_name.as_str().ok().map(|x| x.to_string())
+}
+
+pub struct AdminTlsAcceptor {
+    inner: TcpAcceptor,
+    holdings: Vec<Holding>,
+    tls_acceptor: TlsAcceptor,
+    peers: PeerCertificates,
+}
+
+impl AdminTlsAcceptor {
+    pub fn new(inner: TcpAcceptor, config: ServerConfig, peers: PeerCertificates) -> Self {
+        let holdings = inner
+            .holdings()
+            .iter()
+            .map(|holding| {
+                let mut holding = holding.clone();
+                holding.http_scheme = Scheme::HTTPS;
+                holding
+            })
+            .collect();
+        AdminTlsAcceptor {
+            inner,
+            holdings,
+            tls_acceptor: TlsAcceptor::from(Arc::new(config)),
+            peers,
+        }
+    }
+}
+
+impl Acceptor for AdminTlsAcceptor {
+    type Coupler = TcpCoupler<AdminTlsStream>;
+    type Stream = AdminTlsStream;
+
+    fn holdings(&self) -> &[Holding] {
+        &self.holdings
+    }
+
+    async fn accept(
+        &mut self,
+        fuse_factory: Option<Arc<dyn FuseFactory + Sync + Send + 'static>>,
+    ) -> io::Result<Accepted<Self::Coupler, Self::Stream>> {
+        let accepted = self.inner.accept(fuse_factory).await?;
+        let remote_addr = accepted.remote_addr.clone().into_std();
+        let tls_acceptor = self.tls_acceptor.clone();
+        let peers = self.peers.clone();
+        // handshake is done when reading the stream, don't block the accept loop
+        let mut accepted = accepted.map_into(
+            |_| TcpCoupler::new(),
+            |stream| AdminTlsStream {
+                state: State::Handshaking(Box::new(tls_acceptor.accept(stream))),
+                remote_addr,
+                peers,
+            },
+        );
+        accepted.http_scheme = Scheme::HTTPS;
+        Ok(accepted)
+    }
+}
+
+type TcpStream = <TcpAcceptor as Acceptor>::Stream;
+
+enum State {
+    Handshaking(Box<Accept<TcpStream>>),
+    Ready(Box<TlsStream<TcpStream>>),
+    Error,
+}
+
+pub struct AdminTlsStream {
+    state: State,
+    remote_addr: Option<SocketAddr>,
+    peers: PeerCertificates,
+}
+
+impl AdminTlsStream {
+    fn poll_stream(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&mut TlsStream<TcpStream>>> {
+        if let State::Handshaking(accept) = &mut self.state {
+            match ready!(Pin::new(accept.as_mut()).poll(cx)) {
+                Ok(stream) => {
+                    let common_name = stream
+                        .get_ref()
+                        .1
+                        .peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .and_then(common_name);
+                    if let (Some(remote_addr), Some(common_name)) = (self.remote_addr, common_name)
+                    {
+                        debug!("admin tls: client certificate {common_name} from {remote_addr}");
+                        self.peers.0.insert(remote_addr, common_name);
+                    }
+                    self.state = State::Ready(Box::new(stream));
+                }
+                Err(e) => {
+                    debug!(
+                        "admin tls: handshake failure from {:?}, {e}",
+                        self.remote_addr
+                    );
+                    self.state = State::Error;
+                    return Poll::Ready(Err(e));
+                }
+            }
+        }
+        match &mut self.state {
+            State::Ready(stream) => Poll::Ready(Ok(stream)),
+            _ => Poll::Ready(Err(io::Error::other("tls handshake failed"))),
+        }
+    }
+}
+
+impl AsyncRead for AdminTlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let stream = ready!(self.get_mut().poll_stream(cx))?;
+        Pin::new(stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for AdminTlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let stream = ready!(self.get_mut().poll_stream(cx))?;
+        Pin::new(stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let stream = ready!(self.get_mut().poll_stream(cx))?;
+        Pin::new(stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let stream = ready!(self.get_mut().poll_stream(cx))?;
+        Pin::new(stream).poll_shutdown(cx)
+    }
+}
+
+impl Drop for AdminTlsStream {
+    fn drop(&mut self) {
+        if let (Some(remote_addr), State::Ready(_)) = (self.remote_addr, &self.state) {
+            self.peers.0.remove(&remote_addr);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use crate::config::Config;
 use crate::domain_storage::DomainStorage;
+use crate::limiter::{ConnectionLimiter, RateLimiter, RejectedConnections};
 use crate::service::ServiceConfig;
+use hyper_util::rt::TokioTimer;
 use salvo::fs::NamedFile;
 use salvo::http::uri::{Authority, PathAndQuery, Uri};
 use salvo::http::{ParseError, ResBody};
@@ -8,6 +10,7 @@ use salvo::prelude::*;
 use std::borrow::Cow;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[inline]
 pub(crate) fn decode_url_path_safely(path: &str) -> String {
@@ -60,6 +63,71 @@ fn replace_uri_path(original_uri: &Uri, new_path: &str) -> Result<Uri, ParseErro
     Ok(Uri::from_parts(uri_parts)?)
 }
 
+// connection over max_connections is closed after the response
+#[handler]
+async fn reject_connection(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let rejected = depot.obtain::<Arc<RejectedConnections>>().unwrap();
+    if let Some(remote_addr) = req.remote_addr().clone().into_std()
+        && rejected.contains(&remote_addr)
+    {
+        tracing::debug!("{remote_addr} is rejected by max_connections");
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        let _ = res.add_header("Connection", "close", true);
+        ctrl.skip_rest();
+    }
+}
+
+// the whole request body should be received in body_read_timeout, files are served after that
+#[handler]
+async fn body_read_timeout(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let conf = depot.obtain::<Arc<Config>>().unwrap();
+    let timeout = Duration::from_secs(conf.http.body_read_timeout);
+    if tokio::time::timeout(timeout, req.payload()).await.is_err() {
+        tracing::debug!(
+            "{:?} request body is not received in time",
+            req.remote_addr()
+        );
+        res.status_code(StatusCode::REQUEST_TIMEOUT);
+        let _ = res.add_header("Connection", "close", true);
+        ctrl.skip_rest();
+    }
+}
+
+#[handler]
+async fn rate_limit(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let limiter = depot.obtain::<Arc<RateLimiter>>().unwrap();
+    let service_config = depot.obtain::<Arc<ServiceConfig>>().unwrap();
+    let (Some(ip), Some(author)) = (
+        req.remote_addr().clone().into_std().map(|addr| addr.ip()),
+        get_authority(req),
+    ) else {
+        return;
+    };
+    let origin_host = author.host();
+    let host = service_config
+        .host_alias
+        .get(origin_host)
+        .map(|x| x.as_str())
+        .unwrap_or(origin_host);
+    if let Err((scope, wait)) = limiter.check(host, ip) {
+        tracing::debug!("{ip} request {host} is limited by {scope:?} rate limit");
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        res.status_code(StatusCode::TOO_MANY_REQUESTS);
+        let _ = res.add_header("Retry-After", retry_after, true);
+        ctrl.skip_rest();
+    }
+}
+
 #[handler]
 async fn file_resp(req: &mut Request, depot: &mut Depot, res: &mut Response, _ctrl: &mut FlowCtrl) {
     let domain_storage = depot.obtain::<Arc<DomainStorage>>().unwrap();
@@ -128,21 +196,48 @@ pub async fn init_http_server(
     conf: Arc<Config>,
     service_config: Arc<ServiceConfig>,
     storage: Arc<DomainStorage>,
+    limiter: Arc<RateLimiter>,
 ) -> anyhow::Result<()> {
     let http_config = &conf.http;
     let listener = TcpListener::new((http_config.addr.clone(), http_config.port))
         .bind()
         .await;
 
+    let rejected = Arc::new(RejectedConnections::default());
     // StaticDir::new();
-    let router = Router::with_hoop(
+    let mut router = Router::with_hoop(
         affix_state::inject(service_config.clone())
             .inject(storage.clone())
-            .inject(conf.clone()),
-    )
-    .path("{*path}")
-    .get(file_resp);
-
-    Server::new(listener).serve(router).await;
+            .inject(conf.clone())
+            .inject(limiter.clone())
+            .inject(rejected.clone()),
+    );
+    if http_config.max_connections.is_some() {
+        router = router.hoop(reject_connection);
+    }
+    router = router.hoop(body_read_timeout);
+    if limiter.is_enabled() {
+        router = router.hoop(rate_limit);
+        let limiter = limiter.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                limiter.purge_idle();
+            }
+        });
+    }
+    let router = router.path("{*path}").get(file_resp);
+
+    let mut server = Server::new(listener).fuse_factory(ConnectionLimiter::new(
+        http_config,
+        limiter.metrics.clone(),
+        rejected,
+    ));
+    server
+        .http1_mut()
+        .timer(TokioTimer::new())
+        .header_read_timeout(Duration::from_secs(http_config.header_read_timeout));
+    server.serve(router).await;
     Ok(())
 }
@@ -0,0 +1,254 @@
+use crate::auth::pattern_regex;
+use crate::config::WebhookConfig;
+use crate::domain_storage::DomainStorage;
+use anyhow::{Context, bail};
+use entity::event::{DomainEvent, DomainEventKind};
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, warn};
+
+pub const SIGNATURE_HEADER: &str = "X-SPA-Signature-256";
+pub const EVENT_HEADER: &str = "X-SPA-Event";
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub struct Webhook {
+    url: String,
+    secret: Option<String>,
+    events: Vec<DomainEventKind>,
+    domains: Vec<Regex>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    client: reqwest::Client,
+}
+
+impl Webhook {
+    pub fn new(conf: &WebhookConfig) -> anyhow::Result<Self> {
+        if !conf.url.starts_with("http://") && !conf.url.starts_with("https://") {
+            bail!("webhook url should be http(s): {}", conf.url)
+        }
+        let domains = conf
+            .domains
+            .iter()
+            .map(|domain| {
+                pattern_regex(domain)
+                    .with_context(|| format!("invalid webhook domain pattern: {domain}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(conf.timeout))
+            .build()?;
+        Ok(Webhook {
+            url: conf.url.clone(),
+            secret: conf.secret.clone(),
+            events: conf.events.clone(),
+            domains,
+            max_retries: conf.max_retries,
+            retry_backoff: Duration::from_secs(conf.retry_backoff),
+            client,
+        })
+    }
+
+    // event without domain, like cron_run, is only filtered by event kind,
+    // batch release is matched if any of its domains is matched
+    fn is_match(&self, event: &DomainEvent) -> bool {
+        let domains = event.data.domains();
+        self.events.contains(&event.data.kind())
+            && (domains.is_empty()
+                || domains
+                    .iter()
+                    .any(|domain| self.domains.iter().any(|x| x.is_match(domain))))
+    }
+
+    async fn send(&self, event: &DomainEvent, body: &[u8]) -> anyhow::Result<()> {
+        let mut req = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, event.data.kind().as_str())
+            .body(body.to_vec());
+        if let Some(secret) = &self.secret {
+            req = req.header(SIGNATURE_HEADER, sign(secret, body));
+        }
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            bail!("response status: {}", resp.status())
+        }
+        Ok(())
+    }
+
+    async fn deliver(&self, event: &DomainEvent) {
+        let body = serde_json::to_vec(event).unwrap();
+        let mut backoff = self.retry_backoff;
+        for retry in 0..=self.max_retries {
+            match self.send(event, &body).await {
+                Ok(_) => {
+                    debug!("webhook {} deliver {event:?} success", self.url);
+                    return;
+                }
+                Err(e) if retry < self.max_retries => {
+                    warn!(
+                        "webhook {} deliver failure: {e}, retry after {backoff:?}",
+                        self.url
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    error!(
+                        "webhook {} deliver {event:?} failure: {e}, give up",
+                        self.url
+                    );
+                }
+            }
+        }
+    }
+
+    // events are delivered one by one for each webhook to keep them in order
+    pub fn spawn(self, storage: &DomainStorage) {
+        let mut receiver = storage.events().subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        if self.is_match(&event) {
+                            self.deliver(&event).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("webhook {} is too slow, skip {n} events", self.url);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+pub fn run_webhooks(configs: &[WebhookConfig], storage: &Arc<DomainStorage>) -> anyhow::Result<()> {
+    for conf in configs {
+        Webhook::new(conf)?.spawn(storage);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::WebhookConfig;
+    use crate::domain_storage::DomainStorage;
+    use crate::file_cache::FileCache;
+    use crate::webhook::{EVENT_HEADER, SIGNATURE_HEADER, Webhook, sign};
+    use entity::event::{DomainEvent, DomainEventData, DomainEventKind};
+    use salvo::prelude::*;
+    use std::env;
+    use std::fs;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    // fail the first request to test retry
+    struct Receiver {
+        count: AtomicU32,
+        sender: mpsc::UnboundedSender<(String, String, Vec<u8>)>,
+    }
+
+    #[async_trait]
+    impl Handler for Receiver {
+        async fn handle(
+            &self,
+            req: &mut Request,
+            _depot: &mut Depot,
+            res: &mut Response,
+            _ctrl: &mut FlowCtrl,
+        ) {
+            let header = |name: &str| req.header::<String>(name).unwrap_or_default();
+            let received = (header(EVENT_HEADER), header(SIGNATURE_HEADER));
+            let body = req.payload().await.unwrap().to_vec();
+            if self.count.fetch_add(1, Ordering::SeqCst) == 0 {
+                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+            } else {
+                self.sender.send((received.0, received.1, body)).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_deliver_with_retry() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+        let addr = acceptor.local_addr().unwrap();
+        let router = Router::with_path("hook").post(Receiver {
+            count: AtomicU32::new(0),
+            sender,
+        });
+        tokio::spawn(Server::new(acceptor).serve(router));
+
+        let dir = env::temp_dir().join("spa-server-webhook-test");
+        fs::create_dir_all(&dir).unwrap();
+        let storage = DomainStorage::init(&dir, FileCache::new()).unwrap();
+        let webhook = Webhook::new(&WebhookConfig {
+            url: format!("http://{addr}/hook"),
+            secret: Some("secret".to_string()),
+            events: vec![DomainEventKind::Release],
+            domains: vec!["*.example.com".to_string()],
+            max_retries: 1,
+            retry_backoff: 0,
+            timeout: 5,
+        })
+        .unwrap();
+        webhook.spawn(&storage);
+
+        let events = storage.events();
+        events.emit(DomainEventData::UploadFinish {
+            domain: "www.example.com".to_string(),
+            version: 1,
+        });
+        events.emit(DomainEventData::Release {
+            domain: "www.other.com".to_string(),
+            version: 1,
+        });
+        events.emit(DomainEventData::Release {
+            domain: "www.example.com".to_string(),
+            version: 1,
+        });
+
+        let (event, signature, body) =
+            tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(event, "release");
+        assert_eq!(signature, sign("secret", &body));
+        let event: DomainEvent = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            event.data,
+            DomainEventData::Release {
+                domain: "www.example.com".to_string(),
+                version: 1
+            }
+        );
+        // filtered events are not delivered
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), receiver.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn webhook_sign() {
+        // echo -n 'body' | openssl dgst -sha256 -hmac 'secret'
+        assert_eq!(
+            sign("secret", b"body"),
+            "sha256=dc46983557fea127b43af721467eb9b3fde2338fe3e14f51952aa8478c13d355"
+        );
+    }
+}
@@ -1,9 +1,28 @@
 #![allow(unused_variables)]
 mod common;
+use chrono::{TimeDelta, Utc};
 use common::*;
 
+use entity::error::{ApiError, ErrorCode};
+use entity::event::{DomainEvent, DomainEventData, DomainEventKind};
+use entity::request::{
+    AuditQuery, DeleteDomainVersionOption, DomainWithOptVersionOption, EventsQuery,
+    ListFilesOption, PurgeTrashOption, ReleaseHistoryQuery, SetTagOption,
+    UpdateUploadingStatusOption,
+};
+use entity::storage::{
+    AuditAction, AuditRecord, AuditResult, FileDiff, GetDomainPositionStatus, ReleaseAction,
+    ScheduledRelease, ServerVersion, ShortMetaData, UploadingStatus, VersionMetadata,
+};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use salvo::prelude::*;
 use spa_server::config::get_host_path_from_domain;
+use spa_server::webhook;
+use std::pin::pin;
 use std::time::Duration;
+use std::{env, fs};
+use tokio::sync::mpsc;
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 3)]
 async fn test_wrong_token() {
@@ -51,3 +70,1993 @@ async fn test_remove_last_version_and_domain() {
     assert!(result.unwrap().is_empty());
     server_handle.abort();
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_lockout_after_wrong_token() {
+    let server_handle = run_server_with_config("server_config_lockout.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let client = get_http_client();
+    let status = |token: &'static str| async move {
+        client
+            .get("http://127.0.0.1:9002/status")
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    };
+    assert_eq!(status("token").await, StatusCode::OK);
+    assert_eq!(status("wrong_token").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(status("wrong_token").await, StatusCode::UNAUTHORIZED);
+    // locked, even with correct token
+    assert_eq!(status("token").await, StatusCode::TOO_MANY_REQUESTS);
+    server_handle.abort();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_scoped_token_permission() {
+    let server_handle = run_server_with_config("server_config_tokens.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let client = get_http_client();
+    let upload_position = |domain: &'static str| async move {
+        client
+            .get("http://127.0.0.1:9003/upload/position")
+            .query(&[("domain", domain)])
+            .bearer_auth("team_token")
+            .send()
+            .await
+            .unwrap()
+            .status()
+    };
+    assert_eq!(
+        upload_position("scoped.fornetcode.com").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        upload_position("scoped.fornetcode.com/27").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        upload_position("www.fornetcode.com").await,
+        StatusCode::FORBIDDEN
+    );
+    // sub path can't escape to another domain
+    assert_eq!(
+        upload_position("scoped.fornetcode.com/../www.fornetcode.com").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        upload_position("www.fornetcode.com/scoped.fornetcode.com").await,
+        StatusCode::FORBIDDEN
+    );
+
+    let release = client
+        .post("http://127.0.0.1:9003/update_version")
+        .json(&serde_json::json!({"domain": "scoped.fornetcode.com"}))
+        .bearer_auth("team_token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(release.status(), StatusCode::FORBIDDEN);
+    let error: ApiError = release.json().await.unwrap();
+    assert_eq!(error.code, ErrorCode::Forbidden);
+    assert_eq!(
+        error.details,
+        Some(serde_json::json!({"action": "release", "domain": "scoped.fornetcode.com"}))
+    );
+
+    let delete_all = client
+        .post("http://127.0.0.1:9003/files/delete")
+        .json(&serde_json::json!({}))
+        .bearer_auth("team_token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(delete_all.status(), StatusCode::FORBIDDEN);
+
+    // spa-client decodes error response to ApiError
+    let api = token_client_api("http://127.0.0.1:9003", "team_token");
+    let result = api
+        .upload_file(
+            "scoped.fornetcode.com",
+            "1",
+            "index.html",
+            get_test_dir().join("server_config_tokens.toml"),
+        )
+        .await;
+    let error = result.unwrap_err();
+    let error = error.downcast_ref::<ApiError>().unwrap();
+    assert_eq!(error.code, ErrorCode::VersionNotUploading);
+    let result = api
+        .release_domain_version("scoped.fornetcode.com".to_string(), None)
+        .await;
+    let error = result.unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ApiError>().map(|e| e.code),
+        Some(ErrorCode::Forbidden)
+    );
+    server_handle.abort();
+}
+
+fn token_client_api(address: &str, auth_token: &str) -> spa_client::api::API {
+    let config = spa_client::config::Config {
+        server: spa_client::config::AdminServerConfig {
+            address: address.to_string(),
+            auth_token: auth_token.to_string(),
+            client_cert: None,
+            client_key: None,
+            ca_cert: None,
+        },
+        upload: spa_client::config::UploadConfig { parallel: 1 },
+    };
+    spa_client::api::API::new(&config).unwrap()
+}
+
+fn mtls_client_api(cert: Option<&str>, auth_token: &str) -> anyhow::Result<spa_client::api::API> {
+    let mtls_dir = get_test_dir().join("mtls");
+    let path = |name: String| Some(mtls_dir.join(name).display().to_string());
+    let config = spa_client::config::Config {
+        server: spa_client::config::AdminServerConfig {
+            address: "https://127.0.0.1:9004".to_string(),
+            auth_token: auth_token.to_string(),
+            client_cert: cert.and_then(|cert| path(format!("{cert}.pem"))),
+            client_key: cert.and_then(|cert| path(format!("{cert}.key"))),
+            ca_cert: path("ca.pem".to_string()),
+        },
+        upload: spa_client::config::UploadConfig { parallel: 1 },
+    };
+    spa_client::api::API::new(&config)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_mtls_client_cert() {
+    let domain = "mtls.fornetcode.com".to_string();
+    let server_handle = run_server_with_config("server_config_mtls.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // certificate common name is mapped to permission, token is not needed
+    let api = mtls_client_api(Some("client_deploy"), "").unwrap();
+    assert!(api.get_domain_info(Some(domain.clone())).await.is_ok());
+    let result = api.release_domain_version(domain.clone(), None).await;
+    assert!(result.unwrap_err().to_string().contains("not allowed"));
+
+    // certificate without mapping still needs token
+    let api = mtls_client_api(Some("client_ops"), "").unwrap();
+    assert!(api.get_domain_info(Some(domain.clone())).await.is_err());
+    let api = mtls_client_api(Some("client_ops"), "token").unwrap();
+    assert!(api.get_domain_info(Some(domain.clone())).await.is_ok());
+
+    // certificate is required and should be signed by client_ca
+    let api = mtls_client_api(None, "token").unwrap();
+    assert!(api.get_domain_info(Some(domain.clone())).await.is_err());
+    let api = mtls_client_api(Some("client_other"), "token").unwrap();
+    assert!(api.get_domain_info(Some(domain.clone())).await.is_err());
+
+    server_handle.abort();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_audit_log() {
+    let domain = "audit.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let _ = std::fs::remove_file(get_test_dir().join("web/.SPA-Audit-test.jsonl"));
+    let server_handle = run_server_with_config("server_config_audit.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let client = get_http_client();
+    let team_post = |path: &'static str, body: serde_json::Value| async move {
+        client
+            .post(format!("http://127.0.0.1:9005/{path}"))
+            .json(&body)
+            .bearer_auth("team_token")
+            .send()
+            .await
+            .unwrap()
+            .status()
+    };
+    assert_eq!(
+        team_post(
+            "files/upload_status",
+            serde_json::json!({"domain": domain, "version": 1, "status": 0})
+        )
+        .await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        team_post("update_version", serde_json::json!({"domain": domain})).await,
+        StatusCode::FORBIDDEN
+    );
+
+    let api = token_client_api("http://127.0.0.1:9005", "token");
+    assert!(
+        api.revoke_version("other.fornetcode.com".to_string(), 1)
+            .await
+            .is_err()
+    );
+
+    let records = api.get_audit(&AuditQuery::default()).await.unwrap();
+    assert_eq!(records.len(), 3);
+    let upload_status = &records[0];
+    assert_eq!(upload_status.name, "team");
+    assert_eq!(upload_status.action, AuditAction::UploadStatus);
+    assert_eq!(upload_status.domain.as_deref(), Some(domain));
+    assert_eq!(upload_status.version, Some(1));
+    assert_eq!(upload_status.ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(upload_status.result, AuditResult::Success);
+    let release = &records[1];
+    assert_eq!(release.action, AuditAction::UpdateVersion);
+    assert_eq!(release.result, AuditResult::Failure);
+    assert!(release.message.as_ref().unwrap().contains("not allowed"));
+    assert_eq!(records[2].name, "admin");
+
+    let failures = api
+        .get_audit(&AuditQuery {
+            result: Some(AuditResult::Failure),
+            limit: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].action, AuditAction::RevokeVersion);
+
+    // scoped token only sees records of its domains
+    let team_records = client
+        .get("http://127.0.0.1:9005/audit")
+        .bearer_auth("team_token")
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<AuditRecord>>()
+        .await
+        .unwrap();
+    assert_eq!(team_records.len(), 2);
+    let status = client
+        .get("http://127.0.0.1:9005/audit")
+        .query(&[("domain", "other.fornetcode.com")])
+        .bearer_auth("team_token")
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    server_handle.abort();
+    // other tests count domains in the same file_dir
+    clean_web_domain_dir(domain);
+}
+
+struct WebhookReceiver(mpsc::UnboundedSender<DomainEvent>);
+
+#[salvo::async_trait]
+impl Handler for WebhookReceiver {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        _res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let signature = req
+            .header::<String>(webhook::SIGNATURE_HEADER)
+            .unwrap_or_default();
+        let body = req.payload().await.unwrap().to_vec();
+        assert_eq!(signature, webhook::sign("webhook_secret", &body));
+        self.0.send(serde_json::from_slice(&body).unwrap()).unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_webhook_events() {
+    let domain = "webhook.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let acceptor = TcpListener::new("127.0.0.1:9106").bind().await;
+    let router = Router::with_path("hook").post(WebhookReceiver(sender));
+    let receiver_handle = tokio::spawn(Server::new(acceptor).serve(router));
+    let server_handle = run_server_with_config("server_config_webhook.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9006", "token");
+    for version in [1, 2] {
+        for status in [UploadingStatus::Uploading, UploadingStatus::Finish] {
+            api.change_uploading_status(UpdateUploadingStatusOption {
+                domain: domain.to_string(),
+                version,
+                status,
+                metadata: None,
+                files: None,
+                release: false,
+            })
+            .await
+            .unwrap();
+        }
+        api.release_domain_version(domain.to_string(), Some(version))
+            .await
+            .unwrap();
+    }
+    api.revoke_version(domain.to_string(), 1).await.unwrap();
+    api.remove_files(Some(domain.to_string()), None)
+        .await
+        .unwrap();
+
+    let mut events = Vec::new();
+    while events.len() < 6 {
+        let event = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.data.domain(), Some(domain));
+        events.push((event.data.kind(), event.data.version().unwrap()));
+    }
+    // upload_start is filtered out
+    assert_eq!(
+        events,
+        vec![
+            (DomainEventKind::UploadFinish, 1),
+            (DomainEventKind::Release, 1),
+            (DomainEventKind::UploadFinish, 2),
+            (DomainEventKind::Release, 2),
+            (DomainEventKind::Revoke, 1),
+            (DomainEventKind::Delete, 2),
+        ]
+    );
+    server_handle.abort();
+    receiver_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_events_stream() {
+    let domain = "events.fornetcode.com";
+    let other_domain = "other-events.fornetcode.com";
+    clean_web_domain_dir(domain);
+    clean_web_domain_dir(other_domain);
+    let server_handle = run_server_with_config("server_config_events.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9007", "token");
+    let mut events = pin!(api.get_events(&EventsQuery::default()).await.unwrap());
+
+    let change_status = |domain: &str, status: UploadingStatus| {
+        api.change_uploading_status(UpdateUploadingStatusOption {
+            domain: domain.to_string(),
+            version: 1,
+            status,
+            metadata: None,
+            files: None,
+            release: false,
+        })
+    };
+    change_status(other_domain, UploadingStatus::Uploading)
+        .await
+        .unwrap();
+    change_status(domain, UploadingStatus::Uploading)
+        .await
+        .unwrap();
+    api.upload_file(
+        domain,
+        "1",
+        "index.html",
+        get_template_version(LOCAL_HOST, 1).join("index.html"),
+    )
+    .await
+    .unwrap();
+    change_status(domain, UploadingStatus::Finish)
+        .await
+        .unwrap();
+    api.release_domain_version(domain.to_string(), Some(1))
+        .await
+        .unwrap();
+
+    let mut received = Vec::new();
+    while received.len() < 5 {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        received.push(event);
+    }
+    assert_eq!(
+        received.iter().map(|x| x.id).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4, 5]
+    );
+    assert_eq!(
+        received[2].data,
+        DomainEventData::FileUpload {
+            domain: domain.to_string(),
+            version: 1,
+            path: "index.html".to_string()
+        }
+    );
+    assert_eq!(received[4].data.kind(), DomainEventKind::Release);
+
+    // replay recent events, scoped token only gets events of its domains
+    let team_api = token_client_api("http://127.0.0.1:9007", "team_token");
+    let replay = team_api
+        .get_events(&EventsQuery {
+            last_event_id: Some(2),
+            follow: Some(false),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .map(|x| x.unwrap().id)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(replay, vec![3, 4, 5]);
+    let result = team_api
+        .get_events(&EventsQuery {
+            domain: Some(other_domain.to_string()),
+            ..Default::default()
+        })
+        .await;
+    assert!(result.is_err());
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+    clean_web_domain_dir(other_domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_openapi_and_swagger_ui() {
+    let server_handle = run_server_with_config("server_config_openapi.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let client = get_http_client();
+    // no auth needed
+    let spec: serde_json::Value = client
+        .get("http://127.0.0.1:9008/openapi.json")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    assert!(spec["paths"]["/status"]["get"].is_object());
+    assert!(spec["components"]["schemas"]["ApiError"].is_object());
+
+    let resp = client
+        // redirect to swagger-ui/
+        .get("http://127.0.0.1:9008/swagger-ui")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.text().await.unwrap().contains("swagger-ui"));
+    let initializer = client
+        .get("http://127.0.0.1:9008/swagger-ui/swagger-initializer.js")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(initializer.contains("../openapi.json"));
+    let resp = client
+        .get("http://127.0.0.1:9008/swagger-ui/not-exist.js")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let error: ApiError = resp.json().await.unwrap();
+    assert_eq!(error.code, ErrorCode::FileNotFound);
+
+    // api still needs auth
+    let resp = client
+        .get("http://127.0.0.1:9008/status")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    server_handle.abort();
+}
+
+#[handler]
+async fn future_server_version(res: &mut Response) {
+    res.render(Json(ServerVersion {
+        version: "9.0.0".to_string(),
+        api_versions: vec![9],
+    }));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_versioned_api() {
+    let server_handle = run_server_with_config("server_config_version.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9009", "token");
+    let version = api.check_server_version().await.unwrap();
+    assert!(!version.version.is_empty());
+    assert_eq!(version.api_versions, vec![1]);
+
+    // unversioned path is deprecated alias
+    let client = get_http_client();
+    let resp = client
+        .get("http://127.0.0.1:9009/status")
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["deprecation"], "true");
+    assert_eq!(
+        resp.headers()["link"],
+        "</v1/status>; rel=\"successor-version\""
+    );
+    let resp = client
+        .get("http://127.0.0.1:9009/v1/status")
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get("deprecation").is_none());
+    server_handle.abort();
+
+    // server without `v1/version`
+    let acceptor = TcpListener::new("127.0.0.1:9109").bind().await;
+    let router = Router::with_path("version").get(future_server_version);
+    let old_server_handle = tokio::spawn(Server::new(acceptor).serve(router));
+    let api = token_client_api("http://127.0.0.1:9109", "token");
+    let error = api.check_server_version().await.unwrap_err().to_string();
+    assert!(error.contains("does not support admin api v1"), "{error}");
+    old_server_handle.abort();
+
+    // server of other admin api version
+    let acceptor = TcpListener::new("127.0.0.1:9110").bind().await;
+    let router = Router::with_path("v1/version").get(future_server_version);
+    let new_server_handle = tokio::spawn(Server::new(acceptor).serve(router));
+    let api = token_client_api("http://127.0.0.1:9110", "token");
+    let error = api.check_server_version().await.unwrap_err().to_string();
+    assert!(error.contains("supports admin api [9]"), "{error}");
+    new_server_handle.abort();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_dashboard() {
+    let domain = "dashboard.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_dashboard.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let client = get_http_client();
+    // redirect to dashboard/
+    let resp = client
+        .get("http://127.0.0.1:9010/dashboard")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.url().path(), "/dashboard/");
+    assert!(resp.text().await.unwrap().contains("spa-server dashboard"));
+    let resp = client
+        .get("http://127.0.0.1:9010/dashboard/not-exist.js")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let error: ApiError = resp.json().await.unwrap();
+    assert_eq!(error.code, ErrorCode::FileNotFound);
+
+    // upload state is shown by status
+    let api = token_client_api("http://127.0.0.1:9010", "token");
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        domain: domain.to_string(),
+        version: 1,
+        status: UploadingStatus::Uploading,
+        metadata: None,
+        files: None,
+        release: false,
+    })
+    .await
+    .unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].uploading_version, Some(1));
+    assert_eq!(info[0].current_version, None);
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        domain: domain.to_string(),
+        version: 1,
+        status: UploadingStatus::Finish,
+        metadata: None,
+        files: None,
+        release: false,
+    })
+    .await
+    .unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].uploading_version, None);
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_list_and_download_files() {
+    let domain = "files.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_files.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9011", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    spa_client::upload_files(
+        api.clone(),
+        domain.to_string(),
+        Some(1),
+        template.clone(),
+        1,
+    )
+    .await
+    .unwrap();
+
+    let query = ListFilesOption {
+        domain: domain.to_string(),
+        version: 1,
+        path: None,
+        recursive: Some(true),
+    };
+    let entries = api.list_files(&query).await.unwrap();
+    let paths: Vec<&str> = entries.iter().map(|x| x.path.as_str()).collect();
+    assert_eq!(paths, vec!["1.html", "index.html", "test.bin", "test.js"]);
+    assert!(entries.iter().all(|x| !x.is_dir && x.modified.is_some()));
+    assert_eq!(
+        entries[3].length,
+        fs::metadata(template.join("test.js")).unwrap().len()
+    );
+
+    let output_dir = env::temp_dir().join("spa-server-download-test");
+    let _ = fs::remove_dir_all(&output_dir);
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let output = output_dir.join("test.js");
+    api.download_file(domain, 1, "test.js", &output)
+        .await
+        .unwrap();
+    assert_eq!(
+        fs::read(&output).unwrap(),
+        fs::read(template.join("test.js")).unwrap()
+    );
+
+    let code = |result: anyhow::Result<u64>| {
+        result
+            .unwrap_err()
+            .downcast_ref::<ApiError>()
+            .map(|x| x.code)
+    };
+    assert_eq!(
+        code(api.download_file(domain, 1, "../1/test.js", &output).await),
+        Some(ErrorCode::PathInvalid)
+    );
+    assert_eq!(
+        code(api.download_file(domain, 1, "missing.js", &output).await),
+        Some(ErrorCode::FileNotFound)
+    );
+    assert_eq!(
+        code(api.download_archive(domain, 2, &output).await),
+        Some(ErrorCode::VersionNotFound)
+    );
+
+    let archive = output_dir.join("files.tar.gz");
+    api.download_archive(domain, 1, &archive).await.unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(
+        fs::File::open(&archive).unwrap(),
+    ));
+    let mut names: Vec<String> = archive
+        .entries()
+        .unwrap()
+        .map(|x| x.unwrap().path().unwrap().display().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["1.html", "index.html", "test.bin", "test.js"]);
+
+    fs::remove_dir_all(&output_dir).unwrap();
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_diff_versions() {
+    let domain = "diff.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_diff.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9012", "token");
+    for (version, template) in [(1, "1"), (2, "4")] {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            get_template_version(LOCAL_HOST, 27).join(template),
+            1,
+        )
+        .await
+        .unwrap();
+    }
+
+    let diff = api.diff_versions(domain, 1, 2).await.unwrap();
+    let paths = |files: &Vec<FileDiff>| files.iter().map(|x| x.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths(&diff.added), vec!["3.html", "4.html"]);
+    assert_eq!(paths(&diff.removed), vec!["1.html", "test.bin", "test.js"]);
+    assert_eq!(paths(&diff.modified), vec!["index.html"]);
+    assert_eq!(diff.unchanged, 0);
+    assert!(diff.added[0].from_length.is_none() && diff.added[0].to_length.is_some());
+    assert!(diff.removed[0].from_length.is_some() && diff.removed[0].to_length.is_none());
+
+    let diff = api.diff_versions(domain, 2, 2).await.unwrap();
+    assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.modified.is_empty());
+    assert_eq!(diff.unchanged, 3);
+
+    let result = api.diff_versions(domain, 1, 3).await;
+    assert_eq!(
+        result
+            .unwrap_err()
+            .downcast_ref::<ApiError>()
+            .map(|x| x.code),
+        Some(ErrorCode::VersionNotFound)
+    );
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_delete_version_and_abort_upload() {
+    let domain = "abort.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_abort.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9013", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    for version in [1, 2] {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+        )
+        .await
+        .unwrap();
+    }
+    api.release_domain_version(domain.to_string(), Some(1))
+        .await
+        .unwrap();
+
+    let code = |result: anyhow::Result<()>| {
+        result
+            .unwrap_err()
+            .downcast_ref::<ApiError>()
+            .map(|x| x.code)
+    };
+    assert_eq!(
+        code(api.delete_version(domain.to_string(), 1).await),
+        Some(ErrorCode::VersionServing)
+    );
+
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        domain: domain.to_string(),
+        version: 3,
+        status: UploadingStatus::Uploading,
+        metadata: None,
+        files: None,
+        release: false,
+    })
+    .await
+    .unwrap();
+    api.upload_file(domain, "3", "test.js", template.join("test.js"))
+        .await
+        .unwrap();
+    assert_eq!(
+        api.get_upload_position(domain).await.unwrap().status,
+        GetDomainPositionStatus::InUploading
+    );
+    assert_eq!(
+        code(api.delete_version(domain.to_string(), 3).await),
+        Some(ErrorCode::VersionUploading)
+    );
+
+    api.abort_upload(domain.to_string(), 3).await.unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].uploading_version, None);
+    assert_eq!(info[0].versions.len(), 2);
+    let position = api.get_upload_position(domain).await.unwrap();
+    assert_eq!(position.status, GetDomainPositionStatus::NewVersion);
+    assert_eq!(position.version, 3);
+    assert_eq!(
+        code(api.abort_upload(domain.to_string(), 3).await),
+        Some(ErrorCode::VersionNotUploading)
+    );
+
+    api.delete_version(domain.to_string(), 2).await.unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].versions, vec![1]);
+    assert_eq!(info[0].current_version, Some(1));
+    assert_eq!(
+        code(api.delete_version(domain.to_string(), 2).await),
+        Some(ErrorCode::VersionNotFound)
+    );
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_expire_upload_session() {
+    let domain = "expire.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_expire.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9014", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    spa_client::upload_files(
+        api.clone(),
+        domain.to_string(),
+        Some(1),
+        template.clone(),
+        1,
+    )
+    .await
+    .unwrap();
+
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        domain: domain.to_string(),
+        version: 2,
+        status: UploadingStatus::Uploading,
+        metadata: None,
+        files: None,
+        release: false,
+    })
+    .await
+    .unwrap();
+    api.upload_file(domain, "2", "test.js", template.join("test.js"))
+        .await
+        .unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    let session = info[0].upload_session.as_ref().unwrap();
+    assert_eq!(session.version, 2);
+    assert!(session.last_activity >= session.start);
+    assert_eq!(info[0].uploading_version, Some(2));
+
+    tokio::time::sleep(Duration::from_secs(4)).await;
+
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert!(info[0].upload_session.is_none());
+    assert_eq!(info[0].uploading_version, None);
+    assert_eq!(info[0].versions, vec![1]);
+    let err = api
+        .upload_file(domain, "2", "test.js", template.join("test.js"))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ApiError>().map(|x| x.code),
+        Some(ErrorCode::VersionNotUploading)
+    );
+    let position = api.get_upload_position(domain).await.unwrap();
+    assert_eq!(position.status, GetDomainPositionStatus::NewVersion);
+    assert_eq!(position.version, 2);
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_retention_policy() {
+    let domain = "retention.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_retention.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9015", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    for version in [1, 2, 3] {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+        )
+        .await
+        .unwrap();
+    }
+    api.release_domain_version(domain.to_string(), Some(1))
+        .await
+        .unwrap();
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        domain: domain.to_string(),
+        version: 4,
+        status: UploadingStatus::Uploading,
+        metadata: None,
+        files: None,
+        release: false,
+    })
+    .await
+    .unwrap();
+
+    let remove = |max_reserve: Option<u32>, max_age_days: Option<u32>, dry_run: bool| {
+        let api = api.clone();
+        async move {
+            api.remove_deprecated_versions(DeleteDomainVersionOption {
+                domain: Some(domain.to_string()),
+                max_reserve,
+                max_age_days,
+                dry_run,
+            })
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.version)
+            .collect::<Vec<u32>>()
+        }
+    };
+    // domain config is used by cron, request without rules keeps serving and uploading versions
+    assert_eq!(remove(None, None, true).await, vec![2, 3]);
+    assert_eq!(remove(Some(1), None, true).await, vec![2]);
+    assert_eq!(remove(Some(0), Some(1), true).await, Vec::<u32>::new());
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].versions.len(), 4);
+
+    assert_eq!(remove(Some(1), None, false).await, vec![2]);
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    let mut versions = info[0].versions.clone();
+    versions.sort();
+    assert_eq!(versions, vec![1, 3, 4]);
+    assert_eq!(info[0].current_version, Some(1));
+    // version gap does not change the retention
+    assert_eq!(remove(Some(1), None, false).await, Vec::<u32>::new());
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_pin_version() {
+    let domain = "pin.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_pin.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9016", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    for version in [1, 2, 3] {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+        )
+        .await
+        .unwrap();
+    }
+    api.release_domain_version(domain.to_string(), Some(3))
+        .await
+        .unwrap();
+    api.pin_version(domain.to_string(), 1, true).await.unwrap();
+    // pin twice is fine
+    api.pin_version(domain.to_string(), 1, true).await.unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].pinned_versions, vec![1]);
+
+    let code = |result: anyhow::Result<()>| {
+        result
+            .unwrap_err()
+            .downcast_ref::<ApiError>()
+            .map(|x| x.code)
+    };
+    assert_eq!(
+        code(api.delete_version(domain.to_string(), 1).await),
+        Some(ErrorCode::VersionPinned)
+    );
+    assert_eq!(
+        code(api.pin_version(domain.to_string(), 4, true).await),
+        Some(ErrorCode::VersionNotFound)
+    );
+    let deleted = api
+        .remove_deprecated_versions(DeleteDomainVersionOption {
+            domain: Some(domain.to_string()),
+            max_reserve: Some(0),
+            max_age_days: None,
+            dry_run: false,
+        })
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].version, 2);
+
+    api.pin_version(domain.to_string(), 1, false).await.unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert!(info[0].pinned_versions.is_empty());
+    api.delete_version(domain.to_string(), 1).await.unwrap();
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_trash_restore_and_purge() {
+    let domain = "trash.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_trash.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9017", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    let upload = |version: u32| {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+        )
+    };
+    for version in [1, 2, 3] {
+        upload(version).await.unwrap();
+    }
+    api.release_domain_version(domain.to_string(), Some(3))
+        .await
+        .unwrap();
+    let versions = || async {
+        let mut versions = api.get_domain_info(Some(domain.to_string())).await.unwrap()[0]
+            .versions
+            .clone();
+        versions.sort();
+        versions
+    };
+
+    api.delete_version(domain.to_string(), 1).await.unwrap();
+    assert_eq!(versions().await, vec![2, 3]);
+    let trash = api.list_trash(Some(domain.to_string())).await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!((trash[0].domain.as_str(), trash[0].version), (domain, 1));
+
+    let item = api.restore_trash(trash[0].id.clone()).await.unwrap();
+    assert_eq!(item.version, 1);
+    assert_eq!(versions().await, vec![1, 2, 3]);
+    assert!(
+        api.list_trash(Some(domain.to_string()))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let files = api
+        .list_files(&ListFilesOption {
+            domain: domain.to_string(),
+            version: 1,
+            path: None,
+            recursive: Some(true),
+        })
+        .await
+        .unwrap();
+    assert!(files.iter().any(|x| x.path == "test.js"));
+
+    let code = |result: anyhow::Error| result.downcast_ref::<ApiError>().map(|x| x.code);
+    assert_eq!(
+        code(api.restore_trash("unknown".to_string()).await.unwrap_err()),
+        Some(ErrorCode::TrashNotFound)
+    );
+
+    api.remove_deprecated_versions(DeleteDomainVersionOption {
+        domain: Some(domain.to_string()),
+        max_reserve: Some(0),
+        max_age_days: None,
+        dry_run: false,
+    })
+    .await
+    .unwrap();
+    assert_eq!(versions().await, vec![3]);
+    let trash = api.list_trash(Some(domain.to_string())).await.unwrap();
+    assert_eq!(trash.len(), 2);
+
+    // version of same number is uploaded again
+    upload(2).await.unwrap();
+    let item_2 = trash.iter().find(|x| x.version == 2).unwrap();
+    assert_eq!(
+        code(api.restore_trash(item_2.id.clone()).await.unwrap_err()),
+        Some(ErrorCode::VersionExists)
+    );
+
+    let purged = api
+        .purge_trash(PurgeTrashOption {
+            domain: None,
+            id: Some(item_2.id.clone()),
+        })
+        .await
+        .unwrap();
+    assert_eq!(purged.len(), 1);
+    let purged = api
+        .purge_trash(PurgeTrashOption {
+            domain: Some(domain.to_string()),
+            id: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(purged.len(), 1);
+    assert_eq!(purged[0].version, 1);
+    assert!(
+        api.list_trash(Some(domain.to_string()))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_version_metadata() {
+    let domain = "metadata.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_metadata.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9018", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    let metadata = VersionMetadata {
+        commit: Some("4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string()),
+        branch: Some("main".to_string()),
+        message: Some("first version".to_string()),
+        extra: [("pipeline".to_string(), "42".to_string())].into(),
+        ..Default::default()
+    };
+    for (version, metadata) in [(1, Some(metadata.clone())), (2, None)] {
+        spa_client::upload_files_with_metadata(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+            metadata,
+        )
+        .await
+        .unwrap();
+    }
+    let get_metadata = || async {
+        api.get_domain_info(Some(domain.to_string()))
+            .await
+            .unwrap()
+            .remove(0)
+            .metadata
+    };
+    let versions_metadata = get_metadata().await;
+    assert_eq!(versions_metadata.len(), 1);
+    assert_eq!(versions_metadata.get(&1), Some(&metadata));
+
+    // metadata is moved to trash with version
+    api.release_domain_version(domain.to_string(), Some(2))
+        .await
+        .unwrap();
+    api.delete_version(domain.to_string(), 1).await.unwrap();
+    assert!(get_metadata().await.is_empty());
+    let trash = api.list_trash(Some(domain.to_string())).await.unwrap();
+    api.restore_trash(trash[0].id.clone()).await.unwrap();
+    assert_eq!(get_metadata().await.get(&1), Some(&metadata));
+
+    // metadata is removed with aborted version
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        domain: domain.to_string(),
+        version: 3,
+        status: UploadingStatus::Uploading,
+        metadata: Some(metadata.clone()),
+        files: None,
+        release: false,
+    })
+    .await
+    .unwrap();
+    assert!(get_metadata().await.contains_key(&3));
+    api.abort_upload(domain.to_string(), 3).await.unwrap();
+    assert!(!get_metadata().await.contains_key(&3));
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_tag_and_channel() {
+    let domain = "tag.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_tag.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9019", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    for version in [1, 2, 3] {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+        )
+        .await
+        .unwrap();
+    }
+    let set = |name: &str, version: Option<u32>, from: Option<&str>, channel: bool| {
+        api.set_tag(
+            SetTagOption {
+                domain: domain.to_string(),
+                name: name.to_string(),
+                version,
+                from: from.map(|x| x.to_string()),
+            },
+            channel,
+        )
+    };
+    let code = |result: anyhow::Error| result.downcast_ref::<ApiError>().map(|x| x.code);
+
+    set("v1.0.0", Some(1), None, false).await.unwrap();
+    set("v2.0.0", Some(2), None, false).await.unwrap();
+    // set the same version again is fine
+    set("v2.0.0", Some(2), None, false).await.unwrap();
+    assert_eq!(
+        code(set("v2.0.0", Some(3), None, false).await.unwrap_err()),
+        Some(ErrorCode::TagExists)
+    );
+    assert_eq!(
+        code(set("v4.0.0", Some(4), None, false).await.unwrap_err()),
+        Some(ErrorCode::VersionNotFound)
+    );
+    assert_eq!(
+        code(set("4", Some(3), None, false).await.unwrap_err()),
+        Some(ErrorCode::InvalidParameters)
+    );
+
+    set("beta", Some(3), None, true).await.unwrap();
+    let stable = set("stable", None, Some("v1.0.0"), true).await.unwrap();
+    assert_eq!(stable.version, 1);
+    // channel is moved
+    let stable = set("stable", None, Some("beta"), true).await.unwrap();
+    assert_eq!(stable.version, 3);
+    assert_eq!(
+        code(set("v1.0.0", Some(1), None, true).await.unwrap_err()),
+        Some(ErrorCode::TagExists)
+    );
+    assert_eq!(
+        code(set("rc", None, Some("unknown"), true).await.unwrap_err()),
+        Some(ErrorCode::TagNotFound)
+    );
+
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(
+        info[0].tags,
+        [("v1.0.0".to_string(), 1), ("v2.0.0".to_string(), 2)].into()
+    );
+    assert_eq!(
+        info[0].channels,
+        [("beta".to_string(), 3), ("stable".to_string(), 3)].into()
+    );
+
+    api.release_domain_tag(domain.to_string(), "stable".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        api.get_domain_info(Some(domain.to_string())).await.unwrap()[0].current_version,
+        Some(3)
+    );
+    api.revoke_domain_tag(domain.to_string(), "v2.0.0".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        api.get_domain_info(Some(domain.to_string())).await.unwrap()[0].current_version,
+        Some(2)
+    );
+    assert_eq!(
+        code(
+            api.release_domain_tag(domain.to_string(), "unknown".to_string())
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::TagNotFound)
+    );
+
+    api.delete_tag(domain.to_string(), "v1.0.0".to_string(), false)
+        .await
+        .unwrap();
+    assert_eq!(
+        code(
+            api.delete_tag(domain.to_string(), "beta".to_string(), false)
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::TagNotFound)
+    );
+    api.delete_tag(domain.to_string(), "beta".to_string(), true)
+        .await
+        .unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].tags, [("v2.0.0".to_string(), 2)].into());
+    assert_eq!(info[0].channels, [("stable".to_string(), 3)].into());
+
+    let records = api
+        .get_audit(&AuditQuery {
+            domain: Some(domain.to_string()),
+            action: Some(AuditAction::SetChannel),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(
+        records
+            .iter()
+            .any(|x| x.version == Some(3) && x.detail.as_deref() == Some("channel: stable"))
+    );
+
+    // tags of deleted version are removed, re-uploaded version which reuses the number has no tags
+    set("v3.0.0", Some(3), None, false).await.unwrap();
+    api.delete_version(domain.to_string(), 3).await.unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].tags, [("v2.0.0".to_string(), 2)].into());
+    assert!(info[0].channels.is_empty());
+    spa_client::upload_files(api.clone(), domain.to_string(), None, template.clone(), 1)
+        .await
+        .unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert!(info[0].versions.contains(&3));
+    assert_eq!(info[0].tags, [("v2.0.0".to_string(), 2)].into());
+    assert!(info[0].channels.is_empty());
+    assert_eq!(
+        code(
+            api.release_domain_tag(domain.to_string(), "v3.0.0".to_string())
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::TagNotFound)
+    );
+
+    // tags are restored with the version, unless the name is used
+    set("stable", Some(3), None, true).await.unwrap();
+    api.delete_version(domain.to_string(), 3).await.unwrap();
+    let trash = api.list_trash(Some(domain.to_string())).await.unwrap();
+    assert_eq!(trash.len(), 2);
+    set("stable", Some(1), None, true).await.unwrap();
+    api.restore_trash(trash[0].id.clone()).await.unwrap();
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(
+        info[0].tags,
+        [("v2.0.0".to_string(), 2), ("v3.0.0".to_string(), 3)].into()
+    );
+    assert_eq!(info[0].channels, [("stable".to_string(), 1)].into());
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_release_history_and_rollback() {
+    let domain = "history.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_history.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9020", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    for version in [1, 2, 3] {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+        )
+        .await
+        .unwrap();
+    }
+    let code = |result: anyhow::Error| result.downcast_ref::<ApiError>().map(|x| x.code);
+    assert_eq!(
+        code(api.rollback(domain.to_string()).await.unwrap_err()),
+        Some(ErrorCode::NoPreviousRelease)
+    );
+
+    // 2 -> 1 -> 3
+    api.release_domain_version(domain.to_string(), Some(2))
+        .await
+        .unwrap();
+    api.revoke_version(domain.to_string(), 1).await.unwrap();
+    api.release_domain_version(domain.to_string(), Some(3))
+        .await
+        .unwrap();
+    // not recorded if serving version is not changed
+    api.release_domain_version(domain.to_string(), Some(3))
+        .await
+        .unwrap();
+
+    let serving = || async {
+        api.get_domain_info(Some(domain.to_string())).await.unwrap()[0].current_version
+    };
+    api.rollback(domain.to_string()).await.unwrap();
+    assert_eq!(serving().await, Some(1));
+    api.rollback(domain.to_string()).await.unwrap();
+    assert_eq!(serving().await, Some(2));
+    assert_eq!(
+        code(api.rollback(domain.to_string()).await.unwrap_err()),
+        Some(ErrorCode::NoPreviousRelease)
+    );
+
+    let history = api
+        .get_release_history(&ReleaseHistoryQuery {
+            domain: domain.to_string(),
+            limit: None,
+        })
+        .await
+        .unwrap();
+    let history: Vec<(ReleaseAction, Option<u32>, u32)> = history
+        .iter()
+        .map(|x| (x.action, x.previous_version, x.version))
+        .collect();
+    assert_eq!(
+        history,
+        vec![
+            (ReleaseAction::Release, None, 2),
+            (ReleaseAction::Revoke, Some(2), 1),
+            (ReleaseAction::Release, Some(1), 3),
+            (ReleaseAction::Rollback, Some(3), 1),
+            (ReleaseAction::Rollback, Some(1), 2),
+        ]
+    );
+    let history = api
+        .get_release_history(&ReleaseHistoryQuery {
+            domain: domain.to_string(),
+            limit: Some(1),
+        })
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].version, 2);
+    assert_eq!(history[0].actor, "admin");
+
+    // a new release after rollback could be rolled back
+    api.release_domain_version(domain.to_string(), Some(3))
+        .await
+        .unwrap();
+    api.rollback(domain.to_string()).await.unwrap();
+    assert_eq!(serving().await, Some(2));
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test]
+async fn test_scheduled_release() {
+    let domain = "schedule.fornetcode.com";
+    // scheduled releases are kept in file_dir, don't share it with other servers
+    let web_dir = get_test_dir().join("web_schedule");
+    if web_dir.exists() {
+        fs::remove_dir_all(&web_dir).unwrap();
+    }
+    fs::create_dir_all(&web_dir).unwrap();
+    let server_handle = run_server_with_config("server_config_schedule.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9021", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    for version in [1, 2, 3] {
+        spa_client::upload_files(
+            api.clone(),
+            domain.to_string(),
+            Some(version),
+            template.clone(),
+            1,
+        )
+        .await
+        .unwrap();
+    }
+    api.release_domain_version(domain.to_string(), Some(1))
+        .await
+        .unwrap();
+
+    let code = |result: anyhow::Error| result.downcast_ref::<ApiError>().map(|x| x.code);
+    let now = Utc::now();
+    assert_eq!(
+        code(
+            api.schedule_release(domain.to_string(), Some(2), None, now - TimeDelta::hours(1))
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::InvalidParameters)
+    );
+    assert_eq!(
+        code(
+            api.schedule_release(domain.to_string(), None, None, now + TimeDelta::hours(1))
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::InvalidParameters)
+    );
+    assert_eq!(
+        code(
+            api.schedule_release(domain.to_string(), Some(9), None, now + TimeDelta::hours(1))
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::VersionNotFound)
+    );
+    // revoke can't be scheduled
+    let resp = get_http_client()
+        .post("http://127.0.0.1:9021/v1/files/revoke_version")
+        .bearer_auth("token")
+        .json(&serde_json::json!({
+            "domain": domain,
+            "version": 1,
+            "release_at": now + TimeDelta::hours(1),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = resp.json().await.unwrap();
+    assert_eq!(error.code, ErrorCode::InvalidParameters);
+
+    api.schedule_release(
+        domain.to_string(),
+        Some(2),
+        None,
+        now + TimeDelta::seconds(2),
+    )
+    .await
+    .unwrap();
+    api.schedule_release(domain.to_string(), Some(3), None, now + TimeDelta::hours(1))
+        .await
+        .unwrap();
+    let items = api
+        .list_scheduled_releases(Some(domain.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(
+        items.iter().map(|x| x.version).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert_eq!(items[0].actor, "admin");
+    let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+    assert_eq!(info[0].scheduled_releases, items);
+
+    let canceled = api.cancel_scheduled_release(items[1].id).await.unwrap();
+    assert_eq!(canceled.version, 3);
+    assert_eq!(
+        code(api.cancel_scheduled_release(items[1].id).await.unwrap_err()),
+        Some(ErrorCode::ScheduleNotFound)
+    );
+
+    let serving = |api: spa_client::api::API| async move {
+        api.get_domain_info(Some(domain.to_string())).await.unwrap()[0].current_version
+    };
+    assert_eq!(serving(api.clone()).await, Some(1));
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert_eq!(serving(api.clone()).await, Some(2));
+    assert!(api.list_scheduled_releases(None).await.unwrap().is_empty());
+    let history = api
+        .get_release_history(&ReleaseHistoryQuery {
+            domain: domain.to_string(),
+            limit: Some(1),
+        })
+        .await
+        .unwrap();
+    assert_eq!(history[0].version, 2);
+    assert_eq!(history[0].actor, "admin");
+
+    // release missed when server was down runs after restart
+    let missed = ScheduledRelease {
+        id: 7,
+        domain: domain.to_string(),
+        version: 3,
+        release_at: Utc::now() - TimeDelta::minutes(1),
+        created_at: Utc::now() - TimeDelta::hours(1),
+        actor: "admin".to_string(),
+    };
+    fs::write(
+        web_dir.join(".SPA-Scheduled.json"),
+        serde_json::to_vec(&vec![missed]).unwrap(),
+    )
+    .unwrap();
+    let restart_handle = run_server_with_config("server_config_schedule_restart.toml");
+    tokio::time::sleep(Duration::from_secs(4)).await;
+
+    let api = token_client_api("http://127.0.0.1:9022", "token");
+    assert_eq!(serving(api.clone()).await, Some(3));
+    assert!(api.list_scheduled_releases(None).await.unwrap().is_empty());
+
+    server_handle.abort();
+    restart_handle.abort();
+    fs::remove_dir_all(&web_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_upload_and_release() {
+    let domain = "release.fornetcode.com";
+    clean_web_domain_dir(domain);
+    let server_handle = run_server_with_config("server_config_upload_release.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let api = token_client_api("http://127.0.0.1:9023", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    spa_client::upload_files_and_release(
+        api.clone(),
+        domain.to_string(),
+        Some(1),
+        template.clone(),
+        1,
+        None,
+    )
+    .await
+    .unwrap();
+    let info = || async {
+        api.get_domain_info(Some(domain.to_string()))
+            .await
+            .unwrap()
+            .remove(0)
+    };
+    assert_eq!(info().await.current_version, Some(1));
+    let history = api
+        .get_release_history(&ReleaseHistoryQuery {
+            domain: domain.to_string(),
+            limit: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].actor, "admin");
+
+    let option =
+        |status: UploadingStatus, files: Option<Vec<ShortMetaData>>| UpdateUploadingStatusOption {
+            domain: domain.to_string(),
+            version: 2,
+            status,
+            metadata: None,
+            files,
+            release: true,
+        };
+    let code = |result: anyhow::Error| result.downcast_ref::<ApiError>().map(|x| x.code);
+    assert_eq!(
+        code(
+            api.finish_and_release(option(UploadingStatus::Uploading, None))
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::InvalidParameters)
+    );
+    assert!(info().await.uploading_version.is_none());
+
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        release: false,
+        ..option(UploadingStatus::Uploading, None)
+    })
+    .await
+    .unwrap();
+    api.upload_file(
+        domain.to_string(),
+        "2".to_string(),
+        "index.html".to_string(),
+        template.join("index.html"),
+    )
+    .await
+    .unwrap();
+    // files are required to release
+    assert_eq!(
+        code(
+            api.finish_and_release(option(UploadingStatus::Finish, None))
+                .await
+                .unwrap_err()
+        ),
+        Some(ErrorCode::InvalidParameters)
+    );
+    assert_eq!(info().await.uploading_version, Some(2));
+    let mut files = api.get_file_metadata(domain, 2).await.unwrap();
+    files.push(ShortMetaData {
+        path: "test.js".to_string(),
+        md5: "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+        length: 0,
+    });
+    let error = api
+        .finish_and_release(option(UploadingStatus::Finish, Some(files)))
+        .await
+        .unwrap_err();
+    let error = error.downcast_ref::<ApiError>().unwrap();
+    assert_eq!(error.code, ErrorCode::FileMismatch);
+    assert_eq!(
+        error.details,
+        Some(serde_json::json!({ "files": ["test.js"] }))
+    );
+    // nothing changes, the version could be uploaded again
+    let domain_info = info().await;
+    assert_eq!(domain_info.current_version, Some(1));
+    assert_eq!(domain_info.uploading_version, Some(2));
+
+    let files = api.get_file_metadata(domain, 2).await.unwrap();
+    let resp = api
+        .finish_and_release(option(UploadingStatus::Finish, Some(files)))
+        .await
+        .unwrap();
+    assert!(resp.contains("has changed to 2"));
+    let domain_info = info().await;
+    assert_eq!(domain_info.current_version, Some(2));
+    assert_eq!(domain_info.uploading_version, None);
+
+    server_handle.abort();
+    clean_web_domain_dir(domain);
+}
+
+#[tokio::test]
+async fn test_batch_release() {
+    let (www, admin, app) = (
+        "batch.fornetcode.com",
+        "batch2.fornetcode.com/admin",
+        "batch2.fornetcode.com/app",
+    );
+    clean_web_domain_dir(www);
+    clean_web_domain_dir("batch2.fornetcode.com");
+    let server_handle = run_server_with_config("server_config_batch.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let start = Utc::now();
+    let api = token_client_api("http://127.0.0.1:9024", "token");
+    let template = get_template_version(LOCAL_HOST, 27).join("1");
+    for domain in [www, admin, app] {
+        for version in [1, 2] {
+            spa_client::upload_files(
+                api.clone(),
+                domain.to_string(),
+                Some(version),
+                template.clone(),
+                1,
+            )
+            .await
+            .unwrap();
+        }
+    }
+    let release =
+        |domain: &str, version: Option<u32>, tag: Option<&str>| DomainWithOptVersionOption {
+            domain: domain.to_string(),
+            version,
+            tag: tag.map(|x| x.to_string()),
+            release_at: None,
+        };
+    let serving = || async {
+        let mut serving = Vec::new();
+        for domain in [www, admin, app] {
+            let info = api.get_domain_info(Some(domain.to_string())).await.unwrap();
+            serving.push(info[0].current_version);
+        }
+        serving
+    };
+    let released = api
+        .batch_release(vec![
+            release(www, Some(1), None),
+            release(admin, Some(1), None),
+            release(app, Some(1), None),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        released
+            .iter()
+            .map(|x| (x.domain.as_str(), x.version))
+            .collect::<Vec<_>>(),
+        vec![(www, 1), (admin, 1), (app, 1)]
+    );
+    assert_eq!(serving().await, vec![Some(1); 3]);
+
+    // nothing is released if any fails
+    let code = |result: anyhow::Error| result.downcast_ref::<ApiError>().map(|x| x.code);
+    assert_eq!(
+        code(
+            api.batch_release(vec![
+                release(www, Some(2), None),
+                release(admin, Some(9), None)
+            ])
+            .await
+            .unwrap_err()
+        ),
+        Some(ErrorCode::VersionNotFound)
+    );
+    assert_eq!(
+        code(
+            api.batch_release(vec![
+                release(www, Some(2), None),
+                release(www, Some(1), None)
+            ])
+            .await
+            .unwrap_err()
+        ),
+        Some(ErrorCode::InvalidParameters)
+    );
+    api.change_uploading_status(UpdateUploadingStatusOption {
+        domain: app.to_string(),
+        version: 3,
+        status: UploadingStatus::Uploading,
+        metadata: None,
+        files: None,
+        release: false,
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        code(
+            api.batch_release(vec![
+                release(www, Some(2), None),
+                release(app, Some(3), None)
+            ])
+            .await
+            .unwrap_err()
+        ),
+        Some(ErrorCode::VersionUploading)
+    );
+    assert_eq!(serving().await, vec![Some(1); 3]);
+    // new sub path is not kept if the batch fails
+    let new = "batch2.fornetcode.com/new";
+    spa_client::upload_files(api.clone(), new.to_string(), Some(1), template.clone(), 1)
+        .await
+        .unwrap();
+    let multiple_file = get_test_dir()
+        .join("web/batch2.fornetcode.com")
+        .join(".SPA-Multiple");
+    let multiple = fs::read_to_string(&multiple_file).unwrap();
+    assert_eq!(
+        code(
+            api.batch_release(vec![
+                release(new, Some(1), None),
+                release(www, Some(9), None)
+            ])
+            .await
+            .unwrap_err()
+        ),
+        Some(ErrorCode::VersionNotFound)
+    );
+    assert_eq!(fs::read_to_string(&multiple_file).unwrap(), multiple);
+    assert!(!multiple.lines().any(|x| x == "new"));
+    let info = api.get_domain_info(Some(new.to_string())).await.unwrap();
+    assert!(info.iter().all(|x| x.current_version.is_none()));
+    assert_eq!(serving().await, vec![Some(1); 3]);
+
+    // registered sub path is removed if registering another one fails
+    let broken = "batch3.fornetcode.com/app";
+    clean_web_domain_dir("batch3.fornetcode.com");
+    spa_client::upload_files(
+        api.clone(),
+        broken.to_string(),
+        Some(1),
+        template.clone(),
+        1,
+    )
+    .await
+    .unwrap();
+    let broken_multiple_file = get_test_dir()
+        .join("web/batch3.fornetcode.com")
+        .join(".SPA-Multiple");
+    fs::remove_file(&broken_multiple_file).unwrap();
+    fs::create_dir(&broken_multiple_file).unwrap();
+    assert_eq!(
+        code(
+            api.batch_release(vec![
+                release(www, Some(2), None),
+                release(new, Some(1), None),
+                release(broken, Some(1), None)
+            ])
+            .await
+            .unwrap_err()
+        ),
+        Some(ErrorCode::Internal)
+    );
+    assert_eq!(fs::read_to_string(&multiple_file).unwrap(), multiple);
+    let info = api.get_domain_info(Some(new.to_string())).await.unwrap();
+    assert!(info.iter().all(|x| x.current_version.is_none()));
+    assert_eq!(serving().await, vec![Some(1); 3]);
+    clean_web_domain_dir("batch3.fornetcode.com");
+
+    api.set_tag(
+        SetTagOption {
+            domain: admin.to_string(),
+            name: "stable".to_string(),
+            version: Some(2),
+            from: None,
+        },
+        true,
+    )
+    .await
+    .unwrap();
+    api.batch_release(vec![
+        release(www, Some(2), None),
+        release(admin, None, Some("stable")),
+        release(app, Some(2), None),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(serving().await, vec![Some(2); 3]);
+
+    // a single event for each batch, and history for each domain
+    let events = api
+        .get_events(&EventsQuery {
+            domain: Some("batch2.fornetcode.com".to_string()),
+            last_event_id: Some(0),
+            follow: Some(false),
+        })
+        .await
+        .unwrap()
+        .map(|x| x.unwrap().data)
+        .filter(|x| futures::future::ready(x.kind() == DomainEventKind::BatchRelease))
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].domains(), vec![www, admin, app]);
+    // token which can't read batch.fornetcode.com doesn't get it
+    let events = token_client_api("http://127.0.0.1:9024", "team_token")
+        .get_events(&EventsQuery {
+            domain: Some("batch2.fornetcode.com".to_string()),
+            last_event_id: Some(0),
+            follow: Some(false),
+        })
+        .await
+        .unwrap()
+        .map(|x| x.unwrap().data.kind())
+        .collect::<Vec<_>>()
+        .await;
+    assert!(!events.is_empty());
+    assert!(!events.contains(&DomainEventKind::BatchRelease));
+    let history = api
+        .get_release_history(&ReleaseHistoryQuery {
+            domain: admin.to_string(),
+            limit: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        history
+            .iter()
+            .map(|x| (x.previous_version, x.version))
+            .collect::<Vec<_>>(),
+        vec![(None, 1), (Some(1), 2)]
+    );
+    let records = api
+        .get_audit(&AuditQuery {
+            action: Some(AuditAction::BatchRelease),
+            since: Some(start),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        records.iter().map(|x| x.result).collect::<Vec<_>>(),
+        vec![
+            AuditResult::Success,
+            AuditResult::Failure,
+            AuditResult::Failure,
+            AuditResult::Failure,
+            AuditResult::Failure,
+            AuditResult::Failure,
+            AuditResult::Success,
+        ]
+    );
+
+    server_handle.abort();
+    clean_web_domain_dir(www);
+    clean_web_domain_dir("batch2.fornetcode.com");
+}
@@ -3,13 +3,14 @@ use reqwest::redirect::Policy;
 use reqwest::{Client, ClientBuilder, StatusCode, Url};
 use spa_client::api::API;
 use std::path::PathBuf;
-use std::sync::OnceLock;
+use std::sync::{Mutex, OnceLock};
 use std::{env, fs, io};
 use tokio::task::JoinHandle;
 use tracing::{debug, error};
 use tracing_subscriber::EnvFilter;
 
 pub const LOCAL_HOST: &str = "local.fornetcode.com";
+#[allow(dead_code)]
 pub const LOCAL_HOST2: &str = "local2.fornetcode.com";
 
 pub fn get_test_dir() -> PathBuf {
@@ -52,6 +53,9 @@ fn get_tls_config() -> ClientConfig {
  */
 
 pub fn run_server_with_config(config_file_name: &str) -> JoinHandle<()> {
+    // tests in same binary would change SPA_CONFIG concurrently, hold it until config is loaded.
+    static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+    let config_lock = CONFIG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     unsafe {
         env::set_var(
             "SPA_CONFIG",
@@ -82,10 +86,13 @@ pub fn run_server_with_config(config_file_name: &str) -> JoinHandle<()> {
         .with_test_writer()
         .try_init();
 
+    // load config immediately, tests in same binary would change SPA_CONFIG concurrently.
+    let config = spa_server::config::Config::load().unwrap();
+    drop(config_lock);
     tokio::spawn(async move {
-        let result = spa_server::run_server().await;
-        if result.is_err() {
-            error!("spa server run error: {:?}", result.unwrap_err());
+        let result = spa_server::run_server_with_config(config).await;
+        if let Err(e) = result {
+            error!("spa server run error: {:?}", e);
         } else {
             debug!("spa server finish");
         }
@@ -130,6 +137,7 @@ pub async fn upload_file_and_check(
     assert_files(domain, request_prefix, version, check_path).await;
 }
 
+#[allow(dead_code)]
 pub async fn assert_redirects(request: &str, redirect_urls: Vec<String>) {
     let mut request = request.to_string();
     for redirect_url in redirect_urls {
@@ -201,6 +209,7 @@ pub fn get_http_client() -> &'static Client {
             .unwrap()
     })
 }
+#[allow(dead_code)]
 pub fn get_http_no_redirect_client() -> &'static Client {
     static CLIENT: OnceLock<Client> = OnceLock::new();
     CLIENT.get_or_init(|| {
@@ -216,6 +225,7 @@ pub fn get_http_no_redirect_client() -> &'static Client {
     })
 }
 
+#[allow(dead_code)]
 pub async fn assert_redirect_correct(request_prefix: &str, target_prefix: &str) -> String {
     let client = get_http_no_redirect_client();
     let query = [("lang", "rust"), ("browser", "servo"), ("zh", "转义字符")];
@@ -237,6 +247,7 @@ pub async fn assert_redirect_correct(request_prefix: &str, target_prefix: &str)
     );
     location
 }
+#[allow(dead_code)]
 pub async fn assert_files_no_exists(request_prefix: &str, check_path: Vec<&'static str>) {
     for file in check_path {
         println!("begin to check: {request_prefix}/{file} no exists");
@@ -255,4 +266,15 @@ pub fn clean_web_domain_dir(domain: &str) {
     if path.exists() {
         fs::remove_dir_all(path).unwrap();
     }
+    // deleted versions of domain in trash, like: .SPA-Trash/${id}/meta.json
+    let trash = get_test_dir().join("web").join(".SPA-Trash");
+    for entry in fs::read_dir(trash).into_iter().flatten().flatten() {
+        let meta = fs::read(entry.path().join("meta.json")).unwrap_or_default();
+        let item_domain = serde_json::from_slice::<serde_json::Value>(&meta)
+            .ok()
+            .and_then(|x| x["domain"].as_str().map(|x| x.to_string()));
+        if item_domain.is_some_and(|x| x == domain || x.starts_with(&format!("{domain}/"))) {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
 }
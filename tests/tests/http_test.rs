@@ -1,5 +1,8 @@
 #![allow(unused_variables)]
+use reqwest::StatusCode;
 use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
 use tokio::time::sleep;
 use tracing::debug;
 
@@ -280,3 +283,80 @@ async fn alias_start_server_and_client_upload_file() {
     // )
     // .await
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn http_limits() {
+    let server_handle = run_server_with_config("server_config_limit.toml");
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let client = get_http_no_redirect_client();
+    // per-domain rate limit, global bucket still has tokens
+    let resp = client
+        .get("http://127.0.0.1:8081/")
+        .header("Host", LOCAL_HOST)
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let resp = client
+        .get("http://127.0.0.1:8081/")
+        .header("Host", LOCAL_HOST)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "1");
+    // global rate limit, the other domain is limited too
+    let resp = client.get("http://127.0.0.1:8081/").send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "1");
+
+    let metrics = client
+        .get("http://127.0.0.1:9001/metrics")
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("spa_http_rate_limited_total{scope=\"domain\"} 1"));
+    assert!(metrics.contains("spa_http_rate_limited_total{scope=\"global\"} 1"));
+
+    // body sent slowly is cut off by body_read_timeout
+    let mut stream = TcpStream::connect("127.0.0.1:8081").await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 10\r\n\r\n")
+        .await
+        .unwrap();
+    let response = tokio::spawn(async move {
+        let mut response = String::new();
+        for _ in 0..4 {
+            sleep(Duration::from_secs(1)).await;
+            if stream.write_all(b"a").await.is_err() {
+                break;
+            }
+        }
+        let _ = stream.read_to_string(&mut response).await;
+        response
+    });
+    let response = response.await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+
+    // connections over max_connections get 503
+    let mut connections = Vec::new();
+    for _ in 0..16 {
+        connections.push(TcpStream::connect("127.0.0.1:8081").await.unwrap());
+    }
+    let mut stream = TcpStream::connect("127.0.0.1:8081").await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    drop(connections);
+    server_handle.abort();
+}